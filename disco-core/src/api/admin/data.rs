@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::user::{Role, User};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRole<'a> {
+    pub role: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Suspension<'a> {
+    // days
+    pub days: i64,
+    pub reason: &'a str,
+    pub appeal: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ban<'a> {
+    pub reason: &'a str,
+    pub appeal: Option<&'a str>,
+}
+
+/// User as seen by moderators. Includes private data, such as the email
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserResponse {
+    id: Option<String>,
+    alias: String,
+    email: String,
+    role: Role,
+    status: &'static str,
+    suspended_until: Option<String>,
+    reason: Option<String>,
+    appeal: Option<String>,
+    verified: bool,
+    creation_date: String,
+}
+
+impl From<User> for AdminUserResponse {
+    fn from(u: User) -> Self {
+        let status = u.status();
+        AdminUserResponse {
+            id: u.id().map(|x| x.to_string()),
+            alias: u.alias().to_string(),
+            email: u.email().email().to_string(),
+            role: u.role(),
+            status: status.state(),
+            suspended_until: status.until().map(|x| x.to_string()),
+            reason: status.reason().map(|x| x.to_string()),
+            appeal: status.appeal().map(|x| x.to_string()),
+            verified: u.verified(),
+            creation_date: u.creation_date().to_string(),
+        }
+    }
+}
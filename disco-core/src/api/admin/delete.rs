@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use rocket::State;
+
+use crate::api::admin::locate_target;
+use crate::api::data::ObjectIdWrapper;
+use crate::api::media::delete_media;
+use crate::api::posts::delete_post_media;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::sessions::delete_all_sessions_from;
+use crate::api::users::auth::claims::ModeratorClaims;
+use crate::api::{MEDIA_ID, POSTS_ID};
+use crate::mongo::media::Media;
+use crate::mongo::post::Post;
+use crate::storage::MediaStore;
+use crate::mongo::session::Session;
+use crate::mongo::user::{Alias, User};
+
+/// # MODERATOR! `DELETE /api/admin/users/<alias>/sessions`
+/// Closes every session of a user. The access tokens they issued stop working
+/// immediately
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 403 | Insufficient privileges |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `DELETE /api/admin/users/Altair-Bueno/sessions`
+#[delete("/users/<alias>/sessions")]
+pub async fn delete_user_sessions(
+    alias: Alias,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
+    token: ModeratorClaims,
+) -> ApiResult<()> {
+    let user = locate_target(&alias, &token, user_collection).await?;
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    delete_all_sessions_from(user.id().unwrap(), session_collection, redis).await
+}
+
+/// # MODERATOR! `DELETE /api/admin/posts/<id>`
+/// Deletes any post, together with its photo and audio
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `id` isn't correctly formated |
+/// | 403 | Insufficient privileges |
+/// | 404 | Post not found |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `DELETE /api/admin/posts/6138ae1329e3d1d8a3c6a0f2`
+#[delete("/posts/<id>")]
+pub async fn delete_any_post(
+    id: ObjectIdWrapper,
+    post_collection: &State<Collection<Post>>,
+    media_collection: &State<Collection<Media>>,
+    store: &State<Arc<dyn MediaStore>>,
+    _token: ModeratorClaims,
+) -> ApiResult<()> {
+    let post = post_collection
+        .find_one_and_delete(doc! { POSTS_ID: id.extract() }, None)
+        .await?
+        .ok_or(ApiError::NotFound("Post"))?;
+    delete_post_media(&post, media_collection, store.as_ref()).await;
+    Ok(())
+}
+
+/// # MODERATOR! `DELETE /api/admin/media/<id>`
+/// Deletes any media file. Posts that use it will point to missing media, so
+/// prefer [deleting the post](delete_any_post) instead
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `id` isn't correctly formated |
+/// | 403 | Insufficient privileges |
+/// | 404 | Media not found |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `DELETE /api/admin/media/6138ae1329e3d1d8a3c6a0f2`
+#[delete("/media/<id>")]
+pub async fn delete_any_media(
+    id: ObjectIdWrapper,
+    media_collection: &State<Collection<Media>>,
+    store: &State<Arc<dyn MediaStore>>,
+    _token: ModeratorClaims,
+) -> ApiResult<()> {
+    let oid = id.extract();
+    media_collection
+        .find_one_and_delete(doc! { MEDIA_ID: oid }, None)
+        .await?
+        .ok_or(ApiError::NotFound("Media"))?;
+    // The file may have been removed already by the media GC
+    let _ = delete_media(store.as_ref(), &oid).await;
+    Ok(())
+}
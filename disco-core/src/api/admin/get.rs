@@ -0,0 +1,103 @@
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use rocket::futures::StreamExt;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::api::admin::data::AdminUserResponse;
+use crate::api::admin::BLOCK_SIZE;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::ModeratorClaims;
+use crate::api::{USER_CREATION_DATE, USER_ROLE};
+use crate::mongo::user::{Role, User};
+
+/// # MODERATOR! `GET /api/admin/users?block=<usize>&role=<role>`
+/// Returns every user, newest first. The method receives the following query
+/// parameters:
+///
+/// - `block`: Block number to get. Each block has [BLOCK_SIZE] users
+/// - `role`: Optional. Only return users with this role: `user`, `moderator`
+///   or `admin`
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// [{
+///     "id": String,
+///     "alias": String,
+///     "email": String,
+///     "role": String,
+///     "status": String,              // active, suspended or banned
+///     "suspended_until": String,     // Optional
+///     "reason": String,              // Optional
+///     "appeal": String,              // Optional
+///     "verified": bool,
+///     "creation_date": String
+/// },
+///
+/// ...]
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid role |
+/// | 403 | Insufficient privileges |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `GET /api/admin/users?block=0&role=moderator`
+///
+/// ```json
+/// [{
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
+///     "alias": "Altair-Bueno",
+///     "email": "hello@world.com",
+///     "role": "moderator",
+///     "status": "active",
+///     "suspended_until": null,
+///     "reason": null,
+///     "appeal": null,
+///     "verified": true,
+///     "creation_date": "2021-09-08 12:36:51.077 UTC"
+/// }]
+/// ```
+#[get("/users?<block>&<role>")]
+pub async fn get_users(
+    block: usize,
+    role: Option<&str>,
+    user_collection: &State<Collection<User>>,
+    _token: ModeratorClaims,
+) -> ApiResult<Json<Vec<AdminUserResponse>>> {
+    let filter = match role {
+        Some(x) => {
+            let role = x
+                .parse::<Role>()
+                .map_err(|_| ApiError::BadRequest("Invalid role"))?;
+            doc! { USER_ROLE: role }
+        }
+        None => doc! {},
+    };
+    let options = FindOptions::builder()
+        .sort(doc! { USER_CREATION_DATE: -1 })
+        .skip((block * BLOCK_SIZE) as u64)
+        .limit(BLOCK_SIZE as i64)
+        .build();
+    let mut cursor = user_collection.find(filter, options).await?;
+
+    let mut response = Vec::with_capacity(BLOCK_SIZE);
+    while let Some(user) = cursor.next().await {
+        response.push(AdminUserResponse::from(user?));
+    }
+    Ok(Json(response))
+}
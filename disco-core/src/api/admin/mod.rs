@@ -0,0 +1,55 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::http::Status;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::USER_ALIAS_NORMALIZED;
+use crate::mongo::user::{Alias, User};
+
+/// Data structures used on this module
+mod data;
+/// DELETE /api/admin
+pub mod delete;
+/// GET /api/admin
+pub mod get;
+/// POST /api/admin
+pub mod post;
+
+/// Block size for user listings
+const BLOCK_SIZE: usize = 40;
+/// Maximum length of suspension reasons and appeal notes
+const RESTRICTION_TEXT_LENGTH: usize = 500;
+
+/// Finds the user a privileged action is applied to. Users can only act on
+/// users with a lower role, so moderators can't suspend each other and admins
+/// can't demote each other
+async fn locate_target(
+    alias: &Alias,
+    token: &TokenClaims,
+    user_collection: &Collection<User>,
+) -> ApiResult<User> {
+    let user = user_collection
+        .find_one(doc! { USER_ALIAS_NORMALIZED: alias.normalized() }, None)
+        .await?
+        .ok_or(ApiError::NotFound("User"))?;
+    if user.role() >= token.role() {
+        Err(ApiError::Other("Insufficient privileges", Status::Forbidden))
+    } else {
+        Ok(user)
+    }
+}
+
+/// Validates the reason and appeal note of a suspension or ban. A reason is
+/// required
+fn restriction_text(reason: &str, appeal: Option<&str>) -> ApiResult<(String, Option<String>)> {
+    let reason = reason.trim();
+    if reason.is_empty() || reason.chars().count() > RESTRICTION_TEXT_LENGTH {
+        return Err(ApiError::BadRequest("Invalid reason"));
+    }
+    let appeal = appeal.map(str::trim).filter(|x| !x.is_empty());
+    if appeal.map(|x| x.chars().count() > RESTRICTION_TEXT_LENGTH) == Some(true) {
+        return Err(ApiError::BadRequest("Invalid appeal note"));
+    }
+    Ok((reason.to_string(), appeal.map(str::to_string)))
+}
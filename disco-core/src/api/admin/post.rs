@@ -0,0 +1,264 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::api::admin::data::{Ban, Suspension, UpdateRole};
+use crate::api::admin::{locate_target, restriction_text};
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::sessions::delete_all_sessions_from;
+use crate::api::users::auth::claims::{AdminClaims, ModeratorClaims};
+use crate::api::users::auth::revocation::{lift_restriction, restrict_account};
+use crate::api::{ACCESS_TOKEN_USER_ID, USER_ID, USER_ROLE, USER_STATUS};
+use crate::mongo::access_token::AccessToken;
+use crate::mongo::session::Session;
+use crate::mongo::user::{AccountStatus, Alias, Role, User};
+
+/// Longest suspension (days). Longer suspensions should be bans
+const MAX_SUSPENSION_DAYS: i64 = 365;
+
+/// # ADMIN! `POST /api/admin/users/<alias>/role`
+/// Changes the role of a user. Admins can't change the role of other admins.
+/// A promotion applies the next time the user refreshes their token. A
+/// demoted user is logged out of every session, so the old privileges stop
+/// working at once
+///
+/// ```json
+/// {
+///     "role": String      // user, moderator or admin
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid role |
+/// | 403 | Insufficient privileges |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/admin/users/Altair-Bueno/role`
+///
+/// ```json
+/// {
+///     "role": "moderator"
+/// }
+/// ```
+#[post("/users/<alias>/role", format = "json", data = "<info>")]
+pub async fn update_role(
+    alias: Alias,
+    info: Json<UpdateRole<'_>>,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
+    token: AdminClaims,
+) -> ApiResult<()> {
+    let role = info
+        .role
+        .parse::<Role>()
+        .map_err(|_| ApiError::BadRequest("Invalid role"))?;
+    let user = locate_target(&alias, &token, user_collection).await?;
+    let update = doc! { "$set": { USER_ROLE: role } };
+    user_collection
+        .update_one(doc! { USER_ID: user.id() }, update, None)
+        .await?;
+    if role < user.role() {
+        // Unwrap is safe. Users stored on the database always have an ObjectId
+        delete_all_sessions_from(user.id().unwrap(), session_collection, redis).await?;
+    }
+    Ok(())
+}
+
+/// # MODERATOR! `POST /api/admin/users/<alias>/suspend`
+/// Suspends a user for the given number of days. The user is logged out of
+/// every session and can't log in or use their access tokens until the
+/// suspension ends. Their public posts are hidden meanwhile
+///
+/// The reason and the appeal note are shown to the user when they try to log
+/// in
+///
+/// ```json
+/// {
+///     "days": i64,
+///     "reason": String,
+///     "appeal": String        // Optional
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid number of days, reason or appeal note |
+/// | 403 | Insufficient privileges |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/admin/users/Altair-Bueno/suspend`
+///
+/// ```json
+/// {
+///     "days": 7,
+///     "reason": "Spam",
+///     "appeal": "Reply to the email we sent you"
+/// }
+/// ```
+#[post("/users/<alias>/suspend", format = "json", data = "<info>")]
+pub async fn suspend_user(
+    alias: Alias,
+    info: Json<Suspension<'_>>,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
+    token: ModeratorClaims,
+) -> ApiResult<()> {
+    if !(1..=MAX_SUSPENSION_DAYS).contains(&info.days) {
+        return Err(ApiError::BadRequest("Invalid number of days"));
+    }
+    let (reason, appeal) = restriction_text(info.reason, info.appeal)?;
+    let user = locate_target(&alias, &token, user_collection).await?;
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    let user_id = user.id().unwrap();
+    let seconds = info.days * 3600 * 24;
+    let status = AccountStatus::suspended_for(seconds, reason, appeal);
+    let update = doc! { "$set": { USER_STATUS: status } };
+    user_collection
+        .update_one(doc! { USER_ID: user.id() }, update, None)
+        .await?;
+    restrict_account(redis, &user_id, Some(seconds as usize)).await?;
+    delete_all_sessions_from(user_id, session_collection, redis).await
+}
+
+/// # ADMIN! `POST /api/admin/users/<alias>/ban`
+/// Bans a user until they are [reinstated](reinstate_user). The user is logged
+/// out of every session and their personal access tokens are deleted. Their
+/// public posts are hidden
+///
+/// ```json
+/// {
+///     "reason": String,
+///     "appeal": String        // Optional
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid reason or appeal note |
+/// | 403 | Insufficient privileges |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/admin/users/Altair-Bueno/ban`
+///
+/// ```json
+/// {
+///     "reason": "Impersonation"
+/// }
+/// ```
+#[post("/users/<alias>/ban", format = "json", data = "<info>")]
+pub async fn ban_user(
+    alias: Alias,
+    info: Json<Ban<'_>>,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    access_token_collection: &State<Collection<AccessToken>>,
+    redis: &State<MultiplexedConnection>,
+    token: AdminClaims,
+) -> ApiResult<()> {
+    let (reason, appeal) = restriction_text(info.reason, info.appeal)?;
+    let user = locate_target(&alias, &token, user_collection).await?;
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    let user_id = user.id().unwrap();
+    let update = doc! { "$set": { USER_STATUS: AccountStatus::banned(reason, appeal) } };
+    user_collection
+        .update_one(doc! { USER_ID: user.id() }, update, None)
+        .await?;
+    restrict_account(redis, &user_id, None).await?;
+    delete_all_sessions_from(user_id, session_collection, redis).await?;
+    let filter = doc! { ACCESS_TOKEN_USER_ID: user_id };
+    access_token_collection.delete_many(filter, None).await?;
+    Ok(())
+}
+
+/// # MODERATOR! `POST /api/admin/users/<alias>/reinstate`
+/// Lifts the suspension or ban of a user. Only admins can lift bans
+///
+/// > Note: This is a no body post request
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 403 | Insufficient privileges |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/admin/users/Altair-Bueno/reinstate`
+#[post("/users/<alias>/reinstate")]
+pub async fn reinstate_user(
+    alias: Alias,
+    user_collection: &State<Collection<User>>,
+    redis: &State<MultiplexedConnection>,
+    token: ModeratorClaims,
+) -> ApiResult<()> {
+    let user = locate_target(&alias, &token, user_collection).await?;
+    if user.status().is_banned() && token.role() < Role::Admin {
+        return Err(ApiError::Other("Insufficient privileges", Status::Forbidden));
+    }
+    let update = doc! { "$set": { USER_STATUS: AccountStatus::Active } };
+    user_collection
+        .update_one(doc! { USER_ID: user.id() }, update, None)
+        .await?;
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    lift_restriction(redis, &user.id().unwrap()).await?;
+    Ok(())
+}
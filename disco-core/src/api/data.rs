@@ -36,6 +36,9 @@ pub struct ApiPostResponse {
     photo: String,
     visibility: Visibility,
     creation_date: String,
+    // audio still being transcoded. Only the author sees these posts
+    #[serde(default)]
+    processing: bool,
 }
 
 impl From<Post> for ApiPostResponse {
@@ -49,6 +52,7 @@ impl From<Post> for ApiPostResponse {
             photo: p.photo().to_string(),
             visibility: p.visibility().clone(),
             creation_date: p.creation_date().to_string(),
+            processing: p.processing(),
         }
     }
 }
@@ -1,27 +1,65 @@
+use std::sync::Arc;
+
 use mongodb::bson::doc;
-use rocket::tokio::fs::File;
+use mongodb::bson::oid::ObjectId;
+use rocket::tokio::io::AsyncReadExt;
 use rocket::State;
 
-use crate::api::media::oid_to_path;
+use crate::api::media::stream::{redirect_media, serve_media, MediaRequest, MediaResponse};
+use crate::api::media::{media_key, rendition_key, transcoded_key};
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::pending_deletion;
+use crate::config::Config;
+use crate::mongo::access_token::Scope;
 use crate::api::{MEDIA_ID, MEDIA_STATUS};
-use crate::mongo::media::{Media, Status};
+use crate::mongo::media::{Media, Rendition, Status};
+use crate::mongo::user::User;
 use crate::mongo::visibility::Visibility;
+use crate::storage::MediaStore;
 use crate::api::data::ObjectIdWrapper;
 
-/// # `GET /api/media/<id>`
+/// Time (seconds) redirects to the store are valid
+const PRESIGNED_URL_TTL: u64 = 300;
+
+/// Bytes read to guess the format of media without a stored MIME type
+const INSPECTED_BYTES: u64 = 8192;
+
+/// # `GET /api/media/<id>?<size>`
 /// Returns the requested media by its id
 ///
 /// > Note: Requesting unclaimed media will return `404 Not found`
 ///
+/// # Size
+///
+/// Images are served as one of their
+/// [renditions](crate::mongo::media::Rendition): `thumb`, `feed` or `full`.
+/// `full` is used by default. Images uploaded before renditions existed are
+/// served as they were uploaded
+///
+/// Audio is served as the loudness normalized AAC file created by the
+/// [transcoding job](crate::api::media::transcode), and as it was uploaded until then
+///
 /// # Auth behaviour
 /// - If the user is not authenticated, only public media is available
 /// - If the user is authenticated, private media uploaded by them are available
-/// too
+/// too. Personal access tokens need the `read:private` scope
+///
+/// # Streaming
+///
+/// Media is served with its `Content-Type`, an `ETag` and its `Last-Modified`
+/// date. Requests with `If-None-Match` or `If-Modified-Since` receive
+/// `304 Not Modified` if their copy is still valid. `Range` requests with one
+/// or more byte ranges receive `206 Partial Content`, as
+/// `multipart/byteranges` for several ranges. Public media may be cached for
+/// an hour by anyone, and private media must be revalidated
+///
+/// If `MEDIA_REDIRECT` is set and the [store](crate::storage::MediaStore)
+/// supports it, the client is redirected to a short lived URL on the store
+/// instead (`307 Temporary Redirect`)
 ///
 /// # Returns
-/// ## Ok (200)
+/// ## Ok (200, 206, 304, 307)
 ///
 /// ## Err
 /// ```json
@@ -34,48 +72,129 @@ use crate::api::data::ObjectIdWrapper;
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 401 | Unauthorised. Private media |
-/// | 404 | Media not found or unclaimed |
+/// | 403 | Missing scope |
+/// | 404 | Media not found or unclaimed. Uploader pending deletion |
+/// | 416 | None of the ranges overlap the file |
 /// | 500 | Couldn't connect to database |
 ///
-#[get("/<id>")]
+/// # Example
+///
+/// `GET /api/media/6138ae1329e3d1d8a3c6a0f2?size=thumb`
+#[allow(clippy::too_many_arguments)]
+#[get("/<id>?<size>")]
 pub async fn get_media_auth(
     id: ObjectIdWrapper,
+    size: Option<Rendition>,
     token: TokenClaims,
+    request: MediaRequest<'_>,
     mongo_media: &State<mongodb::Collection<Media>>,
-) -> ApiResult<File> {
+    user_collection: &State<mongodb::Collection<User>>,
+    store: &State<Arc<dyn MediaStore>>,
+    config: &State<Config>,
+) -> ApiResult<MediaResponse> {
+    token.require(Scope::ReadPrivate)?;
     let oid = id.extract();
-    let filter =
-        doc! {MEDIA_ID: oid, MEDIA_STATUS : Status::Assigned };
-    let media = mongo_media
-        .find_one(filter, None)
-        .await?
-        .ok_or(ApiError::NotFound("Media"))?;
+    let media = get_assigned_media(oid, mongo_media, user_collection).await?;
     let condition =
-        (*media.visibility() == Visibility::Public) || (token.alias() == media.uploaded_by());
+        (*media.visibility() == Visibility::Public) || token.user_id() == media.uploaded_by();
 
     if condition {
-        Ok(rocket::tokio::fs::File::open(oid_to_path(&oid)).await?)
+        respond(&media, size, &request, store.as_ref(), config).await
     } else {
         Err(ApiError::Unauthorized("Private media"))
     }
 }
 
-#[get("/<id>", rank = 2)]
+#[get("/<id>?<size>", rank = 2)]
 pub async fn get_media(
     id: ObjectIdWrapper,
+    size: Option<Rendition>,
+    request: MediaRequest<'_>,
     mongo_media: &State<mongodb::Collection<Media>>,
-) -> ApiResult<File> {
+    user_collection: &State<mongodb::Collection<User>>,
+    store: &State<Arc<dyn MediaStore>>,
+    config: &State<Config>,
+) -> ApiResult<MediaResponse> {
     let oid = id.extract();
+    let media = get_assigned_media(oid, mongo_media, user_collection).await?;
+
+    if *media.visibility() == Visibility::Public {
+        respond(&media, size, &request, store.as_ref(), config).await
+    } else {
+        Err(ApiError::Unauthorized("Private media"))
+    }
+}
+
+/// Serves the media, or redirects to the store if
+/// [enabled](crate::config::Config::media_redirect)
+async fn respond(
+    media: &Media,
+    size: Option<Rendition>,
+    request: &MediaRequest<'_>,
+    store: &dyn MediaStore,
+    config: &Config,
+) -> ApiResult<MediaResponse> {
+    let (key, content_type) = media_file(media, size, store).await?;
+    if config.media_redirect {
+        if let Some(url) = store.presigned_url(&key, PRESIGNED_URL_TTL).await {
+            return Ok(redirect_media(url));
+        }
+    }
+    serve_media(store, &key, &content_type, media.visibility(), request).await
+}
+
+/// Key and content type of the requested rendition, or of the transcoded
+/// audio. Images uploaded before renditions existed and audio that hasn't
+/// been transcoded only have the original file
+async fn media_file(
+    media: &Media,
+    size: Option<Rendition>,
+    store: &dyn MediaStore,
+) -> ApiResult<(String, String)> {
+    // Unwrap is safe. Documents stored on the database always have an
+    // ObjectId
+    let oid = media.id().unwrap();
+    if media.audio().is_some() {
+        return Ok((transcoded_key(&oid), "audio/mp4".to_string()));
+    }
+    let rendition = size.unwrap_or_default();
+    if media.renditions().contains(&rendition) {
+        return Ok((rendition_key(&oid, rendition), "image/jpeg".to_string()));
+    }
+    let key = media_key(&oid);
+    // Media uploaded before the MIME type was stored is inspected again
+    let content_type = match media.mime() {
+        Some(mime) => mime.to_string(),
+        None => {
+            let mut head = Vec::new();
+            store
+                .get(&key, Some((0, INSPECTED_BYTES - 1)))
+                .await?
+                .read_to_end(&mut head)
+                .await?;
+            infer::get(&head)
+                .map_or("application/octet-stream", |x| x.mime_type())
+                .to_string()
+        }
+    };
+    Ok((key, content_type))
+}
+
+/// Media uploaded by users pending deletion is hidden
+async fn get_assigned_media(
+    oid: ObjectId,
+    mongo_media: &mongodb::Collection<Media>,
+    user_collection: &mongodb::Collection<User>,
+) -> ApiResult<Media> {
     let filter =
         doc! {MEDIA_ID: oid, MEDIA_STATUS : Status::Assigned };
     let media = mongo_media
         .find_one(filter, None)
         .await?
         .ok_or(ApiError::NotFound("Media"))?;
-
-    if *media.visibility() == Visibility::Public {
-        Ok(rocket::tokio::fs::File::open(oid_to_path(&oid)).await?)
+    if pending_deletion(media.uploaded_by(), user_collection).await? {
+        Err(ApiError::NotFound("Media"))
     } else {
-        Err(ApiError::Unauthorized("Private media"))
+        Ok(media)
     }
 }
@@ -1,12 +1,12 @@
 use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
 
-use crate::api::result::{ApiError, ApiResult};
+use crate::api::result::ApiResult;
 use crate::api::{MEDIA_FORMAT, MEDIA_ID, MEDIA_STATUS, MEDIA_UPLOADED_BY};
-use crate::mongo::media::{Format, Status};
-use crate::mongo::user::Alias;
+use crate::mongo::media::{Format, Rendition, Status};
 use chrono::Utc;
 use crate::api::media::post::FILE_TTL;
+use crate::storage::MediaStore;
 
 /// Data Structures used on this module
 mod data;
@@ -14,13 +14,20 @@ mod data;
 pub mod get;
 /// POST /api/media
 pub mod post;
+/// Image renditions and audio transcoding
+pub mod process;
+/// Range and conditional requests
+mod stream;
+/// Background audio transcoding
+pub mod transcode;
 
-const MEDIA_ROOT_FOLDER: &str = "media/";
+/// Folder for files that are being processed
+const TEMP_FOLDER: &str = "temp/";
 
 pub async fn claim_media_filter(
     oid: &ObjectId,
     expected: &Format,
-    uploaded_by: &Alias,
+    uploaded_by: ObjectId,
 ) -> mongodb::bson::Document {
     doc! {
         MEDIA_ID: oid ,
@@ -30,11 +37,13 @@ pub async fn claim_media_filter(
     }
 }
 
-pub async fn delete_media(oid: &ObjectId) -> ApiResult<()> {
-    let path = oid_to_path(oid);
-    rocket::tokio::fs::remove_file(path)
-        .await
-        .map_err(ApiError::FileTransferError)
+/// Removes the media file, its renditions and its transcoded audio from the
+/// store. Missing objects are ignored
+pub async fn delete_media(store: &dyn MediaStore, oid: &ObjectId) -> ApiResult<()> {
+    for key in media_keys(oid) {
+        store.delete(&key).await?;
+    }
+    Ok(())
 }
 
 pub async fn claim_media_update() -> mongodb::bson::Document {
@@ -49,16 +58,42 @@ pub fn is_expired(oid:&ObjectId) -> bool {
     sum < Utc::now()
 }
 
-pub fn oid_to_path(oid: &mongodb::bson::oid::ObjectId) -> String {
-    format!("{}/{}.blob", oid_to_folder(oid), oid)
+/// Key of the original file on the [MediaStore]. Keys are spread over one
+/// folder per byte of the ObjectId
+pub fn media_key(oid: &ObjectId) -> String {
+    format!("{}/{}.blob", oid_to_prefix(oid), oid)
+}
+
+/// Key of an image rendition. Renditions are stored next to the original
+pub fn rendition_key(oid: &ObjectId, rendition: Rendition) -> String {
+    format!("{}/{}.{}.jpg", oid_to_prefix(oid), oid, rendition.name())
+}
+
+/// Key of a [transcoded](crate::api::media::transcode) audio file. Stored next to the
+/// original
+pub fn transcoded_key(oid: &ObjectId) -> String {
+    format!("{}/{}.stream.m4a", oid_to_prefix(oid), oid)
+}
+
+/// Every object that may be stored for the media: the original, its
+/// renditions and its transcoded audio
+pub fn media_keys(oid: &ObjectId) -> Vec<String> {
+    let mut keys = vec![media_key(oid)];
+    keys.extend(Rendition::ALL.iter().map(|x| rendition_key(oid, *x)));
+    keys.push(transcoded_key(oid));
+    keys
+}
+
+/// Local file used while media is being processed. Media processors can only
+/// read and write local files
+pub fn temp_path(oid: &ObjectId, name: &str) -> String {
+    format!("{}{}.{}", TEMP_FOLDER, oid, name)
 }
 
-fn oid_to_folder(oid: &mongodb::bson::oid::ObjectId) -> String {
+fn oid_to_prefix(oid: &ObjectId) -> String {
     oid.bytes()
         .iter()
-        .fold(MEDIA_ROOT_FOLDER.to_string(), |mut acc, n| {
-            acc.push('/');
-            acc.push_str(n.to_string().as_str());
-            acc
-        })
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join("/")
 }
@@ -1,15 +1,22 @@
+use std::sync::Arc;
+
 use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
 use mongodb::Collection;
 use rocket::fs::TempFile;
 use rocket::serde::json::serde_json::json;
 use rocket::serde::json::{Json, Value};
 use rocket::State;
 
-use crate::api::media::oid_to_folder;
+use crate::api::media::process::{create_renditions, queue_transcoding};
+use crate::api::media::{delete_media, media_key, temp_path};
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
+use crate::config::Config;
+use crate::mongo::access_token::Scope;
 use crate::api::MEDIA_ID;
 use crate::mongo::media::{Format, Media};
+use crate::storage::MediaStore;
 
 #[cfg(debug_assertions)]
 pub const FILE_TTL: u64 = 3600;
@@ -21,13 +28,20 @@ pub const FILE_TTL: u64 = 60;
 ///
 /// Uploads the file to the server and stores it temporarly. The file **must**
 /// be claimed before the Time To Live expires, otherwise the server will delete
-/// the file. You can claim a file by using it as an *user avatar* or *post*
+/// the file. You can claim a file by using it as an *user avatar* or *post*.
+/// Unclaimed files are removed by the [media GC](crate::gc)
 ///
 /// The full list of supported file formats is [here](crate::mongo::media::Format)
 ///
 ///
 /// > Note: The key attribute on the response is the media ID. Don't loose it!!
 ///
+/// Images are resized into several
+/// [renditions](crate::mongo::media::Rendition) before responding. The
+/// renditions don't keep the image metadata, such as its location. Audio is
+/// [transcoded](crate::api::media::transcode) in the background, and posts using it stay
+/// hidden until it is ready
+///
 /// ## Audio
 /// - mp3
 ///
@@ -51,9 +65,10 @@ pub const FILE_TTL: u64 = 60;
 ///
 /// | Code | Description |
 /// | -----| ----------- |
-/// | 400 | Invalid file type |
+/// | 400 | Invalid file type. Image can't be decoded |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
-/// | 500 | Couldn't connect to database. Couldn't store file|
+/// | 500 | Couldn't connect to database. Couldn't store or process file |
 ///
 /// # Example
 ///
@@ -70,54 +85,53 @@ pub async fn upload(
     token: TokenClaims,
     mut file: TempFile<'_>,
     mongo: &State<Collection<Media>>,
+    config: &State<Config>,
+    store: &State<Arc<dyn MediaStore>>,
 ) -> ApiResult<Json<Value>> {
+    token.require(Scope::MediaUpload)?;
     // inspect file
-    let file_type: Format = file
+    let mime = file
         .path()
         .ok_or(ApiError::InternalServerError("Couldn't inspect file"))
         .map(infer::get_from_path)??
-        .ok_or(ApiError::BadRequest("Unknown file format"))
-        .map(|x| x.mime_type().parse())??;
+        .ok_or(ApiError::BadRequest("Unknown file format"))?
+        .mime_type();
+    let file_type: Format = mime.parse()?;
 
     // insert document
-    let media = Media::new(token.alias().clone(), file_type);
+    let media = Media::new(token.user_id(), file_type, mime);
     let inserted = mongo.insert_one(media, None).await?;
     // Unwrap is safe. If the document has been inserted, it contains an oid
     let oid = inserted.inserted_id.as_object_id().unwrap();
-    // copy to folder
-    let folder = oid_to_folder(&oid);
-    let path = format!("{}/{}.blob", folder, oid);
-    let _ = rocket::tokio::fs::create_dir_all(&folder).await;
-    file.copy_to(&path).await?;
+    // store a local copy, which is processed afterwards
+    let local = temp_path(&oid, "blob");
+    file.copy_to(&local).await?;
+    let stored = store_media(oid, &local, file_type, mime, config, store.as_ref(), mongo).await;
+    let _ = rocket::tokio::fs::remove_file(&local).await;
+    if let Err(e) = stored {
+        let _ = mongo.delete_one(doc! {MEDIA_ID: oid}, None).await;
+        let _ = delete_media(store.as_ref(), &oid).await;
+        return Err(e);
+    }
     let response = json!({ "key" : oid.to_string(), "TTL" : FILE_TTL });
-    timed_gc_routine(oid, path, (*mongo).clone()).await;
-
     Ok(Json(response))
 }
 
-/// Sets up a timed gc for a temporal file using its key. If the key is still
-/// present on the CacheFiles index, it will remove the entry and send it to
-/// the garbage collector routine
-///
-/// > NOTE: Although it is called *garbage collector*, it is **not** related to
-/// > memory management. This GC is used for scheduling file removals
-async fn timed_gc_routine(
-    oid: mongodb::bson::oid::ObjectId,
-    path: String,
-    collection: Collection<Media>,
-) {
-    rocket::tokio::spawn(async move {
-        // We wait the double to avoid race conditions. This keeps the server
-        // fast by avoiding synchronization
-        rocket::tokio::time::sleep(rocket::tokio::time::Duration::new(FILE_TTL * 2, 0)).await;
-        let result = collection.delete_one(doc! {MEDIA_ID: oid}, None).await;
-        match result {
-            Ok(x) if x.deleted_count == 1 => {
-                #[cfg(debug_assertions)]
-                println!("[GC]: Deleting {}", oid);
-                let _ = rocket::tokio::fs::remove_file(path).await;
-            }
-            _ => {}
+/// Puts the uploaded file on the store and processes it
+async fn store_media(
+    oid: ObjectId,
+    local: &str,
+    format: Format,
+    mime: &str,
+    config: &Config,
+    store: &dyn MediaStore,
+    media_collection: &Collection<Media>,
+) -> ApiResult<()> {
+    store.put(&media_key(&oid), local.as_ref(), mime).await?;
+    match format {
+        Format::Image => {
+            create_renditions(oid, local, &config.image_converter, store, media_collection).await
         }
-    });
+        Format::Audio => queue_transcoding(oid, media_collection).await,
+    }
 }
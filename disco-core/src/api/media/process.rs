@@ -0,0 +1,100 @@
+use std::process::{Command, Stdio};
+
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+
+use crate::api::media::{rendition_key, temp_path};
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::{MEDIA_ID, MEDIA_PROCESSING, MEDIA_RENDITIONS};
+use crate::mongo::media::{Media, Processing, Rendition};
+use crate::storage::MediaStore;
+
+/// Resources ImageMagick may use for a single rendition. Protects the server
+/// from decompression bombs
+const CONVERTER_LIMITS: [(&str, &str); 4] = [
+    ("memory", "256MiB"),
+    ("map", "512MiB"),
+    ("area", "128MP"),
+    ("time", "30"),
+];
+
+/// Creates every [Rendition] of an uploaded image from the local file at
+/// `source`, puts them on the store and lists them on the media document.
+/// Images are decoded, rotated, stripped of their metadata (such as GPS
+/// coordinates) and encoded again by `converter`, an ImageMagick compatible
+/// program. See [Config](crate::config::Config::image_converter)
+///
+/// Files that can't be decoded are rejected. Renditions that were already
+/// stored are removed by [delete_media](crate::api::media::delete_media)
+pub async fn create_renditions(
+    oid: ObjectId,
+    source: &str,
+    converter: &str,
+    store: &dyn MediaStore,
+    media_collection: &Collection<Media>,
+) -> ApiResult<()> {
+    for rendition in Rendition::ALL {
+        let target = temp_path(&oid, &format!("{}.jpg", rendition.name()));
+        let (converter, source, local) = (converter.to_string(), source.to_string(), target.clone());
+        let rendered =
+            rocket::tokio::task::spawn_blocking(move || render(&converter, &source, &local, rendition))
+                .await
+                .map_err(|_| ApiError::InternalServerError("Couldn't process image"));
+        let stored = match rendered {
+            Ok(Ok(_)) => store
+                .put(&rendition_key(&oid, rendition), target.as_ref(), "image/jpeg")
+                .await
+                .map_err(ApiError::from),
+            Ok(Err(e)) | Err(e) => Err(e),
+        };
+        let _ = rocket::tokio::fs::remove_file(&target).await;
+        stored?;
+    }
+
+    let update = doc! { "$set": { MEDIA_RENDITIONS: Rendition::ALL.to_vec() } };
+    media_collection
+        .update_one(doc! { MEDIA_ID: oid }, update, None)
+        .await?;
+    Ok(())
+}
+
+/// Runs the converter for a single rendition. Only the first frame of
+/// animated images is kept
+fn render(converter: &str, source: &str, target: &str, rendition: Rendition) -> ApiResult<()> {
+    let mut command = Command::new(converter);
+    for (resource, limit) in CONVERTER_LIMITS {
+        command.args(["-limit", resource, limit]);
+    }
+    let status = command
+        .arg(format!("{}[0]", source))
+        .args(["-auto-orient", "-strip"])
+        .arg("-resize")
+        .arg(format!("{0}x{0}>", rendition.size()))
+        .args(["-background", "white", "-flatten"])
+        .arg("-quality")
+        .arg(rendition.quality().to_string())
+        .arg(format!("jpeg:{}", target))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(x) if x.success() => Ok(()),
+        Ok(_) => Err(ApiError::BadRequest("Couldn't decode image")),
+        Err(e) => {
+            println!("[MEDIA]: Couldn't run {}: {}", converter, e);
+            Err(ApiError::InternalServerError("Couldn't process image"))
+        }
+    }
+}
+
+/// Hands a stored audio file to the [transcoding job](crate::api::media::transcode).
+/// Audio documents are inserted before their file is written, so the job
+/// must not pick them up until then
+pub async fn queue_transcoding(oid: ObjectId, media_collection: &Collection<Media>) -> ApiResult<()> {
+    let filter = doc! { MEDIA_ID: oid, MEDIA_PROCESSING: Processing::Uploading };
+    let update = doc! { "$set": { MEDIA_PROCESSING: Processing::Pending } };
+    media_collection.update_one(filter, update, None).await?;
+    Ok(())
+}
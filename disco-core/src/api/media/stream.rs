@@ -0,0 +1,311 @@
+use std::convert::Infallible;
+use std::io::Cursor;
+
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::io::AsyncReadExt;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::mongo::visibility::Visibility;
+use crate::storage::{MediaStore, ObjectStream};
+
+/// Most ranges served on a single response. Requests asking for more are
+/// answered with the whole file
+const MAX_RANGES: usize = 16;
+
+/// Time (seconds) public media may be cached. Visibility may change, so it
+/// isn't cached forever
+const PUBLIC_MAX_AGE: u64 = 3600;
+
+/// Headers of a media request that change the response
+pub struct MediaRequest<'r> {
+    range: Option<&'r str>,
+    if_range: Option<&'r str>,
+    if_none_match: Option<&'r str>,
+    if_modified_since: Option<&'r str>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MediaRequest<'r> {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        Outcome::Success(MediaRequest {
+            range: headers.get_one("Range"),
+            if_range: headers.get_one("If-Range"),
+            if_none_match: headers.get_one("If-None-Match"),
+            if_modified_since: headers.get_one("If-Modified-Since"),
+        })
+    }
+}
+
+/// A media file, the requested ranges of it or a redirect to the store
+pub struct MediaResponse {
+    status: Status,
+    headers: Vec<Header<'static>>,
+    /// Contents and their length
+    body: Option<(ObjectStream, u64)>,
+}
+
+impl<'r> Responder<'r, 'static> for MediaResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response.status(self.status);
+        for header in self.headers {
+            response.header(header);
+        }
+        // Sent with the given length instead of chunked
+        if let Some((stream, size)) = self.body {
+            response.raw_header("Content-Length", size.to_string());
+            response.streamed_body(stream);
+        }
+        Ok(response.finalize())
+    }
+}
+
+/// Byte ranges requested with the `Range` header
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRanges {
+    /// No range, or one that must be ignored
+    Whole,
+    /// None of the ranges overlap the file
+    Unsatisfiable,
+    /// Inclusive ranges, in the requested order
+    Parts(Vec<(u64, u64)>),
+}
+
+/// Serves the object `key` as `content_type`. Supports single and multiple
+/// byte ranges (`206 Partial Content`) and conditional requests with
+/// `If-None-Match`, `If-Modified-Since` and `If-Range`. Public media may be
+/// cached by anyone, while private media must be revalidated on every use
+pub async fn serve_media(
+    store: &dyn MediaStore,
+    key: &str,
+    content_type: &str,
+    visibility: &Visibility,
+    request: &MediaRequest<'_>,
+) -> ApiResult<MediaResponse> {
+    let info = store.stat(key).await?.ok_or(ApiError::NotFound("Media"))?;
+    let (size, etag) = (info.size, info.etag);
+    // HTTP dates don't have fractional seconds
+    let modified = Utc.timestamp(info.modified.timestamp(), 0);
+
+    let cache_control = match visibility {
+        Visibility::Public => format!("public, max-age={}", PUBLIC_MAX_AGE),
+        Visibility::Private => "private, no-cache".to_string(),
+    };
+    let mut headers = vec![
+        Header::new("ETag", etag.clone()),
+        Header::new("Last-Modified", http_date(&modified)),
+        Header::new("Cache-Control", cache_control),
+        Header::new("Accept-Ranges", "bytes"),
+    ];
+    if not_modified(request, &etag, &modified) {
+        return Ok(MediaResponse {
+            status: Status::NotModified,
+            headers,
+            body: None,
+        });
+    }
+
+    let ranges = match request.range {
+        Some(range) if if_range_matches(request.if_range, &etag, &modified) => {
+            parse_range(range, size)
+        }
+        _ => ByteRanges::Whole,
+    };
+    let response = match ranges {
+        ByteRanges::Whole => {
+            headers.push(Header::new("Content-Type", content_type.to_string()));
+            MediaResponse {
+                status: Status::Ok,
+                headers,
+                body: Some((store.get(key, None).await?, size)),
+            }
+        }
+        ByteRanges::Unsatisfiable => {
+            headers.push(Header::new("Content-Range", format!("bytes */{}", size)));
+            MediaResponse {
+                status: Status::RangeNotSatisfiable,
+                headers,
+                body: None,
+            }
+        }
+        ByteRanges::Parts(parts) if parts.len() == 1 => {
+            let (start, end) = parts[0];
+            headers.push(Header::new("Content-Type", content_type.to_string()));
+            headers.push(Header::new(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, size),
+            ));
+            MediaResponse {
+                status: Status::PartialContent,
+                headers,
+                body: Some((store.get(key, Some((start, end))).await?, end - start + 1)),
+            }
+        }
+        ByteRanges::Parts(parts) => {
+            let boundary = ObjectId::new().to_hex();
+            let mut body: ObjectStream = Box::pin(Cursor::new(Vec::new()));
+            let mut length = 0;
+            for (start, end) in parts {
+                let head = format!(
+                    "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    boundary, content_type, start, end, size
+                );
+                let part = store.get(key, Some((start, end))).await?;
+                length += head.len() as u64 + end - start + 1;
+                body = Box::pin(body.chain(Cursor::new(head.into_bytes())).chain(part));
+            }
+            let tail = format!("\r\n--{}--\r\n", boundary);
+            length += tail.len() as u64;
+            body = Box::pin(body.chain(Cursor::new(tail.into_bytes())));
+            headers.push(Header::new(
+                "Content-Type",
+                format!("multipart/byteranges; boundary={}", boundary),
+            ));
+            MediaResponse {
+                status: Status::PartialContent,
+                headers,
+                body: Some((body, length)),
+            }
+        }
+    };
+    Ok(response)
+}
+
+/// Sends the client to a [presigned URL](MediaStore::presigned_url) instead
+/// of serving the media. The URL expires, so the redirect isn't cached
+pub fn redirect_media(url: String) -> MediaResponse {
+    MediaResponse {
+        status: Status::TemporaryRedirect,
+        headers: vec![
+            Header::new("Location", url),
+            Header::new("Cache-Control", "no-store"),
+        ],
+        body: None,
+    }
+}
+
+/// Whether the client copy is still valid. `If-Modified-Since` is ignored
+/// when `If-None-Match` is present
+fn not_modified(request: &MediaRequest<'_>, etag: &str, modified: &DateTime<Utc>) -> bool {
+    if let Some(tags) = request.if_none_match {
+        tags.split(',')
+            .map(|x| x.trim())
+            .any(|x| x == "*" || x.trim_start_matches("W/") == etag)
+    } else if let Some(date) = request.if_modified_since.and_then(parse_http_date) {
+        *modified <= date
+    } else {
+        false
+    }
+}
+
+/// Whether the ranges should be served. `If-Range` holds either the ETag or
+/// the date of the copy the client already has part of
+fn if_range_matches(if_range: Option<&str>, etag: &str, modified: &DateTime<Utc>) -> bool {
+    match if_range.map(|x| x.trim()) {
+        None => true,
+        Some(tag) if tag.starts_with('"') => tag == etag,
+        // Weak ETags can't be used with ranges
+        Some(tag) if tag.starts_with("W/") => false,
+        Some(date) => parse_http_date(date).as_ref() == Some(modified),
+    }
+}
+
+/// Parses a `bytes` range header for a file of `size` bytes. Invalid headers
+/// and headers with more than [MAX_RANGES] ranges are ignored
+fn parse_range(header: &str, size: u64) -> ByteRanges {
+    let specs = match header.trim().strip_prefix("bytes=") {
+        Some(x) => x,
+        None => return ByteRanges::Whole,
+    };
+    let specs: Vec<&str> = specs.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return ByteRanges::Whole;
+    }
+
+    let mut parts = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (start, end) = match spec.split_once('-') {
+            Some(x) => x,
+            None => return ByteRanges::Whole,
+        };
+        let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // Last `end` bytes
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                if suffix == 0 || size == 0 {
+                    continue;
+                }
+                (size.saturating_sub(suffix), size - 1)
+            }
+            // From `start` to the end of the file
+            (Ok(start), Err(_)) if end.is_empty() => (start, u64::MAX),
+            (Ok(start), Ok(end)) if start <= end => (start, end),
+            _ => return ByteRanges::Whole,
+        };
+        if start < size {
+            parts.push((start, end.min(size - 1)));
+        }
+    }
+    if parts.is_empty() {
+        ByteRanges::Unsatisfiable
+    } else {
+        ByteRanges::Parts(parts)
+    }
+}
+
+/// Formats a date as an HTTP date (RFC 7231)
+fn http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(|x| x.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{http_date, parse_http_date, parse_range, ByteRanges};
+
+    #[test]
+    pub fn byte_ranges() {
+        let parts = |x: &[(u64, u64)]| ByteRanges::Parts(x.to_vec());
+        assert_eq!(parse_range("bytes=0-99", 1000), parts(&[(0, 99)]));
+        assert_eq!(parse_range("bytes=900-", 1000), parts(&[(900, 999)]));
+        assert_eq!(parse_range("bytes=-100", 1000), parts(&[(900, 999)]));
+        assert_eq!(parse_range("bytes=-5000", 1000), parts(&[(0, 999)]));
+        assert_eq!(parse_range("bytes=990-2000", 1000), parts(&[(990, 999)]));
+        assert_eq!(
+            parse_range("bytes=0-0, 500-599 ,-1", 1000),
+            parts(&[(0, 0), (500, 599), (999, 999)])
+        );
+        // Ranges outside the file are skipped
+        assert_eq!(parse_range("bytes=0-9,2000-", 1000), parts(&[(0, 9)]));
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRanges::Unsatisfiable);
+        // Invalid headers are ignored
+        assert_eq!(parse_range("bytes=99-0", 1000), ByteRanges::Whole);
+        assert_eq!(parse_range("bytes=a-b", 1000), ByteRanges::Whole);
+        assert_eq!(parse_range("bytes=", 1000), ByteRanges::Whole);
+        assert_eq!(parse_range("items=0-1", 1000), ByteRanges::Whole);
+        let many = format!("bytes={}", vec!["0-1"; 17].join(","));
+        assert_eq!(parse_range(&many, 1000), ByteRanges::Whole);
+    }
+
+    #[test]
+    pub fn http_dates() {
+        let date = Utc.ymd(1994, 11, 6).and_hms(8, 49, 37);
+        assert_eq!(http_date(&date), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(date));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
@@ -0,0 +1,313 @@
+use std::convert::TryFrom;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Instant;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::FindOneAndUpdateOptions;
+use mongodb::{Collection, Database};
+use rocket::serde::json::{serde_json, Value};
+use rocket::tokio::task::JoinError;
+use rocket::tokio::time::{sleep, Duration};
+use thiserror::Error;
+
+use crate::api::media::{media_key, temp_path, transcoded_key};
+use crate::config::Config;
+use crate::mongo::media::{AudioInfo, Media, Processing};
+use crate::storage::{MediaStore, StoreError};
+
+/// Time (seconds) between checks for pending audio
+#[cfg(debug_assertions)]
+const TRANSCODE_INTERVAL: u64 = 5;
+
+#[cfg(not(debug_assertions))]
+const TRANSCODE_INTERVAL: u64 = 15;
+
+/// Time (seconds) a job may run before it is considered abandoned and the
+/// audio is transcoded again
+const TRANSCODE_LEASE: i64 = 900;
+
+/// Time (seconds) `audio_transcoder` may run before it is killed and the
+/// audio is marked as failed. Shorter than the lease, so another job never
+/// picks it up while it is running
+const TRANSCODER_TIMEOUT: u64 = 600;
+
+/// Time (seconds) `audio_probe` may run before it is killed
+const PROBE_TIMEOUT: u64 = 30;
+
+/// EBU R128 loudness normalization, as used by most streaming services
+const LOUDNESS_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// Errors produced while transcoding audio
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+    #[error("Couldn't connect to database: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("Couldn't store file: {0}")]
+    File(#[from] std::io::Error),
+    #[error("Couldn't access media: {0}")]
+    Store(#[from] StoreError),
+    #[error("Couldn't run {0}: {1}")]
+    Program(String, std::io::Error),
+    #[error("Transcoding task stopped: {0}")]
+    Task(#[from] JoinError),
+    #[error("{0} didn't finish in time")]
+    Timeout(String),
+    #[error("Couldn't decode audio")]
+    Decode,
+}
+
+/// Starts the background job that transcodes uploaded audio. Every file is
+/// loudness normalized and encoded as 128 kbps stereo AAC at 44.1 kHz, ready
+/// to be streamed. Its duration, sample rate and channels are stored on the
+/// media document, and the posts that use it become visible
+///
+/// Audio is transcoded by `audio_transcoder` and inspected by `audio_probe`,
+/// `ffmpeg` and `ffprobe` compatible programs. See
+/// [Config](crate::config::Config::audio_transcoder)
+///
+/// # Resuming
+///
+/// Each job holds a lease on its media for [TRANSCODE_LEASE] seconds. If the
+/// server stops in the middle of a job, the audio is picked up again once the
+/// lease is over. Audio that can't be decoded, takes longer than
+/// [TRANSCODER_TIMEOUT] seconds or whose original file is missing from the
+/// store is marked as failed, and its posts stay hidden. If the programs
+/// can't be run or the store can't be reached, the audio keeps waiting
+pub fn spawn(db: Database, config: &Config, store: Arc<dyn MediaStore>) {
+    let transcoder = config.audio_transcoder.clone();
+    let probe = config.audio_probe.clone();
+    rocket::tokio::spawn(async move {
+        loop {
+            if let Err(e) = transcode_pending(&db, store.as_ref(), &transcoder, &probe).await {
+                println!("[TRANSCODE]: {}", e);
+            }
+            sleep(Duration::from_secs(TRANSCODE_INTERVAL)).await;
+        }
+    });
+}
+
+/// Transcodes audio until none is left waiting
+async fn transcode_pending(
+    db: &Database,
+    store: &dyn MediaStore,
+    transcoder: &str,
+    probe: &str,
+) -> Result<(), TranscodeError> {
+    let media = db.collection::<Media>("Media");
+    while let Some(next) = claim_audio(&media).await? {
+        // Unwrap is safe. Documents stored on the database always have an
+        // ObjectId
+        let oid = next.id().unwrap();
+        let result = transcode_audio(store, transcoder, probe, oid).await;
+        match result {
+            Ok(info) => {
+                finish_audio(db, oid, info).await?;
+                #[cfg(debug_assertions)]
+                println!("[TRANSCODE]: Transcoded {}", oid);
+            }
+            // Trying again won't help
+            Err(e @ TranscodeError::Decode)
+            | Err(e @ TranscodeError::Timeout(_))
+            | Err(e @ TranscodeError::Store(StoreError::NotFound(_))) => {
+                let update = doc! {
+                    "$set": { "processing": Processing::Failed },
+                    "$unset": { "processing_started": "" }
+                };
+                media.update_one(doc! { "_id": oid }, update, None).await?;
+                println!("[TRANSCODE]: Couldn't transcode {}: {}", oid, e);
+            }
+            Err(e) => {
+                // Not the file's fault. Try again on the next run
+                let update = doc! {
+                    "$set": { "processing": Processing::Pending },
+                    "$unset": { "processing_started": "" }
+                };
+                media.update_one(doc! { "_id": oid }, update, None).await?;
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Takes the lease on the next audio waiting to be transcoded, or whose job
+/// was abandoned. Waiting audio goes first, then the oldest abandoned jobs
+async fn claim_audio(media: &Collection<Media>) -> Result<Option<Media>, TranscodeError> {
+    let now = DateTime::now();
+    let stale = DateTime::from_millis(now.timestamp_millis() - TRANSCODE_LEASE * 1000);
+    let filter = doc! {
+        "$or": [
+            { "processing": Processing::Pending },
+            { "processing": Processing::Running, "processing_started": { "$lte": stale } },
+        ]
+    };
+    let update = doc! {
+        "$set": { "processing": Processing::Running, "processing_started": now }
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! { "processing_started": 1, "_id": 1 })
+        .build();
+    Ok(media.find_one_and_update(filter, update, options).await?)
+}
+
+/// Stores the audio properties and shows the posts that were waiting for it
+async fn finish_audio(db: &Database, oid: ObjectId, info: AudioInfo) -> Result<(), TranscodeError> {
+    let update = doc! {
+        "$set": { "processing": Processing::Done, "audio": info },
+        "$unset": { "processing_started": "" }
+    };
+    db.collection::<Media>("Media")
+        .update_one(doc! { "_id": oid }, update, None)
+        .await?;
+    db.collection::<Document>("Posts")
+        .update_many(
+            doc! { "audio": oid },
+            doc! { "$set": { "processing": false } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Copies the original file from the store, transcodes it and stores the
+/// result next to the original. Local copies are removed afterwards
+async fn transcode_audio(
+    store: &dyn MediaStore,
+    transcoder: &str,
+    probe: &str,
+    oid: ObjectId,
+) -> Result<AudioInfo, TranscodeError> {
+    let source = temp_path(&oid, "source");
+    let target = temp_path(&oid, "stream.m4a");
+    let result = async {
+        store.fetch(&media_key(&oid), source.as_ref()).await?;
+        let (transcoder, probe) = (transcoder.to_string(), probe.to_string());
+        let (input, output) = (source.clone(), target.clone());
+        let info = rocket::tokio::task::spawn_blocking(move || {
+            transcode(&transcoder, &probe, &input, &output)
+        })
+        .await??;
+        store.put(&transcoded_key(&oid), target.as_ref(), "audio/mp4").await?;
+        Ok(info)
+    }
+    .await;
+    let _ = rocket::tokio::fs::remove_file(&source).await;
+    let _ = rocket::tokio::fs::remove_file(&target).await;
+    result
+}
+
+/// Transcodes the audio at `source` to `target` and inspects the result
+fn transcode(
+    transcoder: &str,
+    probe: &str,
+    source: &str,
+    target: &str,
+) -> Result<AudioInfo, TranscodeError> {
+    let program = |e| TranscodeError::Program(transcoder.to_string(), e);
+    let mut child = Command::new(transcoder)
+        .args(["-nostdin", "-y", "-v", "error", "-i"])
+        .arg(source)
+        .args(["-map", "0:a:0", "-af", LOUDNESS_FILTER])
+        .args(["-ar", "44100", "-ac", "2", "-c:a", "aac", "-b:a", "128k"])
+        .args(["-movflags", "+faststart", "-f", "mp4"])
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(program)?;
+    let status = wait_timeout(&mut child, TRANSCODER_TIMEOUT)
+        .map_err(program)?
+        .ok_or_else(|| TranscodeError::Timeout(transcoder.to_string()))?;
+    if !status.success() {
+        return Err(TranscodeError::Decode);
+    }
+
+    let program = |e| TranscodeError::Program(probe.to_string(), e);
+    let mut child = Command::new(probe)
+        .args(["-v", "error", "-select_streams", "a:0"])
+        .args(["-show_entries", "stream=sample_rate,channels:format=duration"])
+        .args(["-of", "json"])
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(program)?;
+    let status = wait_timeout(&mut child, PROBE_TIMEOUT)
+        .map_err(program)?
+        .ok_or_else(|| TranscodeError::Timeout(probe.to_string()))?;
+    if !status.success() {
+        return Err(TranscodeError::Decode);
+    }
+    let mut output = Vec::new();
+    // Unwrap is safe. stdout is piped
+    child.stdout.take().unwrap().read_to_end(&mut output).map_err(program)?;
+    parse_probe(&output).ok_or(TranscodeError::Decode)
+}
+
+/// Waits for `child` to exit. If it is still running after `timeout`
+/// seconds it is killed and `None` is returned. The output of the child
+/// must be small enough to fit on the pipe buffer, as it isn't read until
+/// the child exits
+fn wait_timeout(child: &mut Child, timeout: u64) -> std::io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Reads the properties of the first audio stream from the JSON output of
+/// `ffprobe`
+fn parse_probe(output: &[u8]) -> Option<AudioInfo> {
+    let value: Value = serde_json::from_slice(output).ok()?;
+    let stream = value.get("streams")?.get(0)?;
+    let seconds: f64 = value.get("format")?.get("duration")?.as_str()?.parse().ok()?;
+    Some(AudioInfo {
+        duration: (seconds * 1000.0).round() as i64,
+        sample_rate: stream.get("sample_rate")?.as_str()?.parse().ok()?,
+        channels: i32::try_from(stream.get("channels")?.as_i64()?).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::{parse_probe, wait_timeout};
+
+    #[test]
+    pub fn probe_output() {
+        let output = br#"{
+            "programs": [],
+            "streams": [{ "sample_rate": "44100", "channels": 2 }],
+            "format": { "duration": "183.024036" }
+        }"#;
+        let info = parse_probe(output).unwrap();
+        assert_eq!(info.duration, 183024);
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.channels, 2);
+
+        assert!(parse_probe(br#"{ "streams": [], "format": {} }"#).is_none());
+        assert!(parse_probe(b"not json").is_none());
+    }
+
+    #[test]
+    pub fn kills_stuck_programs() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        assert!(wait_timeout(&mut child, 0).unwrap().is_none());
+        let mut child = Command::new("true").spawn().unwrap();
+        assert!(wait_timeout(&mut child, 5).unwrap().unwrap().success());
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+/// /api/admin
+pub mod admin;
 /// Common datastructures
 mod data;
 /// /api/media
@@ -19,28 +21,75 @@ pub mod users;
 
 const USER_ALIAS: &str = "alias";
 const USER_ID: &str = "_id";
+const USER_ALIAS_NORMALIZED: &str = "alias_normalized";
 const USER_EMAIL: &str = "email";
+const USER_EMAIL_NORMALIZED: &str = "email_normalized";
 const USER_PASSWORD: &str = "password";
 const USER_DESCRIPTION: &str = "description";
 const USER_CREATION_DATE: &str = "creation_date";
 const USER_AVATAR: &str = "avatar";
+const USER_VERIFIED: &str = "verified";
+const USER_TOTP: &str = "totp";
+const USER_TOTP_SECRET: &str = "totp.secret";
+const USER_TOTP_ENABLED: &str = "totp.enabled";
+const USER_TOTP_RECOVERY_CODES: &str = "totp.recovery_codes";
+const USER_TOTP_LAST_STEP: &str = "totp.last_step";
+const USER_ROLE: &str = "role";
+const USER_STATUS: &str = "status";
+const USER_STATUS_STATE: &str = "status.state";
+const USER_STATUS_UNTIL: &str = "status.until";
+const USER_DELETE_AFTER: &str = "delete_after";
 
 const MEDIA_ID: &str = "_id";
 const MEDIA_UPLOADED_BY: &str = "uploaded_by";
 const MEDIA_STATUS: &str = "status";
 const MEDIA_FORMAT: &str = "format";
 const MEDIA_VISIBILITY: &str = "visibility";
+const MEDIA_RENDITIONS: &str = "renditions";
+const MEDIA_PROCESSING: &str = "processing";
 
 const SESSION_ID: &str = "_id";
-const SESSION_USER_ALIAS: &str = "user_alias";
+const SESSION_USER_ID: &str = "user_id";
 const SESSION_IP: &str = "ip";
 const SESSION_DATE: &str = "date";
+const SESSION_TOKEN: &str = "token";
+const SESSION_ROTATED: &str = "rotated";
+const SESSION_LAST_USED: &str = "last_used";
+const SESSION_EXPIRES: &str = "expires";
+
+const TICKET_ID: &str = "_id";
+const TICKET_TOKEN: &str = "token";
+const TICKET_USER_ID: &str = "user_id";
+const TICKET_PURPOSE: &str = "purpose";
+const TICKET_ATTEMPTS: &str = "attempts";
+
+const ACCESS_TOKEN_ID: &str = "_id";
+const ACCESS_TOKEN_TOKEN: &str = "token";
+const ACCESS_TOKEN_USER_ID: &str = "user_id";
+const ACCESS_TOKEN_LAST_USED: &str = "last_used";
+
+const ALIAS_RESERVATION_ALIAS_NORMALIZED: &str = "alias_normalized";
+const ALIAS_RESERVATION_USER_ID: &str = "user_id";
+const ALIAS_RESERVATION_EXPIRES: &str = "expires";
+
+const IDENTITY_ID: &str = "_id";
+const IDENTITY_PROVIDER: &str = "provider";
+const IDENTITY_SUBJECT: &str = "subject";
+const IDENTITY_USER_ID: &str = "user_id";
+
+const EXPORT_ID: &str = "_id";
+const EXPORT_USER_ID: &str = "user_id";
+const EXPORT_STATUS: &str = "status";
+const EXPORT_DATE: &str = "date";
+const EXPORT_EXPIRES: &str = "expires";
 
 const POSTS_ID: &str = "_id";
 const POSTS_TITLE: &str = "title";
 const POSTS_CAPTION: &str = "caption";
+const POSTS_AUTHOR_ID: &str = "author_id";
 const POSTS_AUTHOR: &str = "author";
 const POSTS_AUDIO: &str = "audio";
 const POSTS_PHOTO: &str = "photo";
 const POSTS_VISIBILITY: &str = "visibility";
 const POSTS_CREATION_DATE: &str = "creation_date";
+const POSTS_PROCESSING: &str = "processing";
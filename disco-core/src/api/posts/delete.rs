@@ -1,14 +1,18 @@
+use std::sync::Arc;
+
 use mongodb::bson::oid::ObjectId;
 use mongodb::{bson::doc, Collection};
 use rocket::State;
 
-use crate::api::media::oid_to_path;
+use crate::api::posts::delete_post_media;
 use crate::api::result::ApiError::BadRequest;
 use crate::api::result::ApiResult;
 use crate::api::users::auth::claims::TokenClaims;
-use crate::api::{POSTS_AUDIO, POSTS_AUTHOR, POSTS_ID, POSTS_PHOTO};
+use crate::mongo::access_token::Scope;
+use crate::api::{POSTS_AUTHOR_ID, POSTS_ID};
 use crate::mongo::media::Media;
 use crate::mongo::post::Post;
+use crate::storage::MediaStore;
 
 /// #  AUTH! `DELETE /api/posts/<id>`
 /// Deletes the post. If the user is not the author of the post, a `BadRequest`
@@ -28,6 +32,7 @@ use crate::mongo::post::Post;
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | Bad request |
+/// | 403 | Missing scope |
 /// | 404 | Media not found |
 /// | 500 | Couldn't connect to database |
 ///
@@ -40,25 +45,16 @@ pub async fn delete_post(
     token: TokenClaims,
     post_collection: &State<Collection<Post>>,
     media_collection: &State<Collection<Media>>,
+    store: &State<Arc<dyn MediaStore>>,
 ) -> ApiResult<()> {
+    token.require(Scope::PostsWrite)?;
     let oid = id.parse::<ObjectId>()?;
     // Delete post
-    let filter = doc! {POSTS_ID:oid, POSTS_AUTHOR:token.alias()};
+    let filter = doc! {POSTS_ID:oid, POSTS_AUTHOR_ID:token.user_id()};
     let post = post_collection
         .find_one_and_delete(filter, None)
         .await?
         .ok_or(BadRequest("Couldn't found the associated post"))?;
-    // Delete photo
-    let filter = doc! {POSTS_PHOTO:post.photo()};
-    let photo = media_collection.find_one_and_delete(filter, None).await;
-    if let Ok(Some(media)) = photo {
-        let _ = rocket::tokio::fs::remove_file(oid_to_path(&media.id().unwrap())).await;
-    }
-    // Delete audio
-    let filter = doc! {POSTS_AUDIO:post.audio()};
-    let audio = media_collection.find_one_and_delete(filter, None).await;
-    if let Ok(Some(media)) = audio {
-        let _ = rocket::tokio::fs::remove_file(oid_to_path(&media.id().unwrap())).await;
-    }
+    delete_post_media(&post, media_collection, store.as_ref()).await;
     Ok(())
 }
@@ -6,8 +6,11 @@ use rocket::State;
 use crate::api::data::{ApiPostResponse, ObjectIdWrapper};
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::pending_deletion;
+use crate::mongo::access_token::Scope;
 use crate::api::POSTS_ID;
 use crate::mongo::post::Post;
+use crate::mongo::user::User;
 use crate::mongo::visibility::Visibility;
 
 /// # `GET /api/posts/<id>`
@@ -18,7 +21,9 @@ use crate::mongo::visibility::Visibility;
 /// # Auth behaviour
 /// - If the user is not authenticated, only public post are available
 /// - If the user is authenticated, private posts uploaded by them are available
-/// too
+/// too. Personal access tokens need the `read:private` scope
+/// - Posts whose audio is still being [transcoded](crate::api::media::transcode) are only
+/// available to their author
 ///
 /// # Returns
 /// ## Ok (200)
@@ -32,7 +37,8 @@ use crate::mongo::visibility::Visibility;
 ///     "audio": String,
 ///     "photo": String,
 ///     "visibility": Visibility,
-///     "creation_date": String
+///     "creation_date": String,
+///     "processing": bool
 /// }
 /// ```
 ///
@@ -47,6 +53,7 @@ use crate::mongo::visibility::Visibility;
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | `id` isn't correctly formated |
+/// | 403 | Missing scope |
 /// | 404 | Post doesn't exist |
 /// | 500 | Couldn't connect to database |
 ///
@@ -63,16 +70,20 @@ use crate::mongo::visibility::Visibility;
 ///  "caption": "Hisoka wants gon booty",
 ///  "title": "Hunter x Hunter",
 ///  "visibility": "Public",
-///  "creation_date": "2021-09-06 16:13:02.797 UTC"
+///  "creation_date": "2021-09-06 16:13:02.797 UTC",
+///  "processing": false
 ///}
 /// ```
 #[get("/<id>", format = "json", rank = 2)]
 pub async fn get_post_content(
     id: ObjectIdWrapper,
     mongo: &State<Collection<Post>>,
+    user_collection: &State<Collection<User>>,
 ) -> ApiResult<Json<ApiPostResponse>> {
-    let post = get_post(id.extract(), mongo).await?;
-    if *post.visibility() == Visibility::Public {
+    let post = get_post(id.extract(), mongo, user_collection).await?;
+    if post.processing() {
+        Err(ApiError::NotFound("Post"))
+    } else if *post.visibility() == Visibility::Public {
         Ok(Json(ApiPostResponse::from(post)))
     } else {
         Err(ApiError::Unauthorized("Private post"))
@@ -84,21 +95,34 @@ pub async fn get_post_content_auth(
     token: TokenClaims,
     id: ObjectIdWrapper,
     mongo: &State<Collection<Post>>,
+    user_collection: &State<Collection<User>>,
 ) -> ApiResult<Json<ApiPostResponse>> {
-    let post = get_post(id.extract(), mongo).await?;
-    let condition = (*post.visibility() == Visibility::Public) || (token.alias() == post.author());
-
-    if condition {
+    token.require(Scope::ReadPrivate)?;
+    let post = get_post(id.extract(), mongo, user_collection).await?;
+    let owner = token.user_id() == post.author_id();
+    if post.processing() && !owner {
+        Err(ApiError::NotFound("Post"))
+    } else if *post.visibility() == Visibility::Public || owner {
         Ok(Json(ApiPostResponse::from(post)))
     } else {
         Err(ApiError::Unauthorized("Private post"))
     }
 }
 
-async fn get_post(oid: mongodb::bson::oid::ObjectId, mongo: &State<Collection<Post>>) -> ApiResult<Post> {
+/// Posts from users pending deletion are hidden
+async fn get_post(
+    oid: mongodb::bson::oid::ObjectId,
+    mongo: &State<Collection<Post>>,
+    user_collection: &Collection<User>,
+) -> ApiResult<Post> {
     let filter = doc! {POSTS_ID:oid};
-    mongo
+    let post = mongo
         .find_one(Some(filter), None)
         .await?
-        .ok_or(ApiError::NotFound("Post"))
+        .ok_or(ApiError::NotFound("Post"))?;
+    if pending_deletion(post.author_id(), user_collection).await? {
+        Err(ApiError::NotFound("Post"))
+    } else {
+        Ok(post)
+    }
 }
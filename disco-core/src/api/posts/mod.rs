@@ -1,3 +1,14 @@
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+
+use crate::api::media::delete_media;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::{MEDIA_ID, POSTS_ID, POSTS_PROCESSING};
+use crate::mongo::media::{Media, Processing};
+use crate::mongo::post::Post;
+use crate::storage::MediaStore;
+
 /// Data structures used on this module
 mod data;
 /// DELETE /api/posts
@@ -8,3 +19,63 @@ pub mod get;
 pub mod patch;
 /// POST /api/posts
 pub mod post;
+
+/// Deletes the photo and audio of a post that has already been deleted.
+/// Missing files are ignored
+pub async fn delete_post_media(
+    post: &Post,
+    media_collection: &Collection<Media>,
+    store: &dyn MediaStore,
+) {
+    for oid in [post.photo(), post.audio()] {
+        let filter = doc! { MEDIA_ID: oid };
+        let media = media_collection.find_one_and_delete(filter, None).await;
+        if let Ok(Some(media)) = media {
+            let _ = delete_media(store, &media.id().unwrap()).await;
+        }
+    }
+}
+
+/// Inserts a post whose media has already been claimed. Posts whose audio
+/// isn't [transcoded](crate::api::media::transcode) yet stay hidden until the job is done.
+/// Audio that couldn't be transcoded is rejected
+pub async fn insert_post(
+    post: Post,
+    post_collection: &Collection<Post>,
+    media_collection: &Collection<Media>,
+) -> ApiResult<ObjectId> {
+    let audio = post.audio();
+    let processing = audio_processing(&audio, media_collection).await?;
+    if processing == Processing::Failed {
+        return Err(ApiError::BadRequest("Couldn't decode audio"));
+    }
+    let pending = processing != Processing::Done;
+    let inserted = post_collection
+        .insert_one(post.with_processing(pending), None)
+        .await?;
+    // Unwrap is safe. Inserted posts always have an ObjectId
+    let oid = inserted.inserted_id.as_object_id().unwrap();
+    // The job may have finished before the post was inserted
+    if pending && audio_processing(&audio, media_collection).await? == Processing::Done {
+        post_collection
+            .update_one(
+                doc! { POSTS_ID: oid },
+                doc! { "$set": { POSTS_PROCESSING: false } },
+                None,
+            )
+            .await?;
+    }
+    Ok(oid)
+}
+
+/// Transcoding state of an audio file
+async fn audio_processing(
+    oid: &ObjectId,
+    media_collection: &Collection<Media>,
+) -> ApiResult<Processing> {
+    media_collection
+        .find_one(doc! { MEDIA_ID: oid }, None)
+        .await?
+        .map(|x| x.processing())
+        .ok_or(ApiError::NotFound("Media file"))
+}
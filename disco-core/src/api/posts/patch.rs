@@ -7,7 +7,8 @@ use rocket::State;
 use crate::api::posts::data::EditPostPayload;
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
-use crate::api::{POSTS_AUTHOR, POSTS_ID, MEDIA_VISIBILITY, MEDIA_ID};
+use crate::mongo::access_token::Scope;
+use crate::api::{POSTS_AUTHOR_ID, POSTS_ID, MEDIA_VISIBILITY, MEDIA_ID};
 use crate::mongo::post::Post;
 use crate::api::data::ObjectIdWrapper;
 use std::option::Option::Some;
@@ -32,6 +33,7 @@ use crate::mongo::media::Media;
 ///
 /// | Code | Description |
 /// | ---- | ----------- |
+/// | 403 | Missing scope |
 /// | 404 | Post not found |
 /// | 500 | Couldn't connect to database |
 #[patch("/<id>", format = "json", data = "<payload>")]
@@ -42,8 +44,9 @@ pub async fn edit_post(
     post_collection: &State<Collection<Post>>,
     media_collection:&State<Collection<Media>>
 ) -> ApiResult<()> {
+    token.require(Scope::PostsWrite)?;
     let oid = id.extract();
-    let filter = doc! {POSTS_AUTHOR:token.alias(),POSTS_ID:oid};
+    let filter = doc! {POSTS_AUTHOR_ID:token.user_id(),POSTS_ID:oid};
     let update = doc! {"$set": to_bson(&payload.0).unwrap()};
     let update_result = post_collection.find_one_and_update(filter, update, None).await?;
 
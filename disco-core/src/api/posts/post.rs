@@ -6,10 +6,14 @@ use rocket::State;
 
 use crate::api::media::{claim_media_update, unclaim_media_update, is_expired};
 use crate::api::posts::data::NewPostPayload;
+use crate::api::posts::insert_post;
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::locate_user_by_id;
+use crate::mongo::access_token::Scope;
 use crate::mongo::media::{Format, Media, Status};
 use crate::mongo::post::Post;
+use crate::mongo::user::User;
 use crate::api::{MEDIA_UPLOADED_BY, MEDIA_ID, MEDIA_FORMAT, MEDIA_STATUS};
 
 /// #  AUTH! `POST /api/posts/new`
@@ -28,6 +32,9 @@ use crate::api::{MEDIA_UPLOADED_BY, MEDIA_ID, MEDIA_FORMAT, MEDIA_STATUS};
 /// `audio` and `photo` must be two valid files pending to be claimed. Calling
 /// this route with claimed media keys will result on `NotFound`
 ///
+/// The post stays hidden from other users until its audio is
+/// [transcoded](crate::api::media::transcode). Audio that can't be decoded is rejected
+///
 /// # Returns
 /// ## Ok (201)
 ///
@@ -49,8 +56,10 @@ use crate::api::{MEDIA_UPLOADED_BY, MEDIA_ID, MEDIA_FORMAT, MEDIA_STATUS};
 ///
 /// | Code | Description |
 /// | -----| ----------- |
-/// | 400 | Bad request |
+/// | 400 | Bad request. Audio can't be decoded |
+/// | 403 | Missing scope |
 /// | 404 | Media not found |
+/// | 404 | User not found |
 /// | 500 | Couldn't connect to database |
 ///
 /// # Example
@@ -83,10 +92,12 @@ pub async fn new_post(
     payload: Json<NewPostPayload<'_>>,
     post_collection: &State<Collection<Post>>,
     media_collection: &State<Collection<Media>>,
+    user_collection: &State<Collection<User>>,
 ) -> ApiResult<Created<Value>> {
+    token.require(Scope::PostsWrite)?;
     let title = payload.title.parse()?;
     let caption = payload.caption.parse()?;
-    let author = token.alias().clone();
+    let author = locate_user_by_id(token.user_id(), user_collection).await?;
     let audio = payload.audio.parse()?;
     let photo = payload.photo.parse()?;
     let visibility = payload
@@ -98,37 +109,46 @@ pub async fn new_post(
         return Err(ApiError::BadRequest("Expired file"))
     }
 
-    let post = Post::new(title, caption, author, audio, photo, visibility);
+    let post = Post::new(
+        title,
+        caption,
+        token.user_id(),
+        author.alias().clone(),
+        audio,
+        photo,
+        visibility,
+    );
     // Claim files
     let query = doc! {
         "$or": [
             {MEDIA_ID:post.photo(), MEDIA_FORMAT: Format::Image},
             {MEDIA_ID:post.audio(), MEDIA_FORMAT: Format::Audio}
         ],
-        MEDIA_UPLOADED_BY: post.author(),
+        MEDIA_UPLOADED_BY: post.author_id(),
         MEDIA_STATUS: Status::Waiting
     };
     let update = claim_media_update().await;
     let update_result = media_collection.update_many(query,update,None).await?;
 
-    if update_result.modified_count == 2 {
-        // Insert post
-        let insert_result = post_collection.insert_one(&post, None).await?;
-        Ok(Created::new(
-            format!("/api/posts/{}", insert_result.inserted_id.to_string()))
-            .body(json!({
-                "status":"Created",
-                "message": "Post created",
-                "post_id": insert_result.inserted_id.as_object_id().map(|x| x.to_string())
-            }))
-        )
+    let inserted = if update_result.modified_count == 2 {
+        insert_post(post.clone(), post_collection, media_collection).await
     } else {
-        // Some of the media was not claimed, rollback changes
-        let update = unclaim_media_update().await;
-        let query = doc! {
-            "$or": [{MEDIA_ID:post.photo()},{MEDIA_ID:post.audio()}]
-        };
-        let _ = media_collection.update_many(query,update,None).await;
         Err(ApiError::BadRequest("The provided files did not exist or where already claimed"))
+    };
+    match inserted {
+        Ok(post_id) => Ok(Created::new(format!("/api/posts/{}", post_id)).body(json!({
+            "status":"Created",
+            "message": "Post created",
+            "post_id": post_id.to_string()
+        }))),
+        Err(e) => {
+            // Some of the media was not claimed, rollback changes
+            let update = unclaim_media_update().await;
+            let query = doc! {
+                "$or": [{MEDIA_ID:post.photo()},{MEDIA_ID:post.audio()}]
+            };
+            let _ = media_collection.update_many(query,update,None).await;
+            Err(e)
+        }
     }
 }
@@ -15,8 +15,20 @@ pub enum ApiError {
     #[error("Couldn't retrieve data from database")]
     DatabaseError(#[from] mongodb::error::Error),
     /// http 500
+    #[error("Couldn't connect to cache")]
+    CacheError(#[from] redis::RedisError),
+    /// http 500
+    #[error("Couldn't send email")]
+    MailError(#[from] crate::mailer::MailError),
+    /// http 502. Invalid ID tokens are http 401
+    #[error("{0}")]
+    IdentityProviderError(#[from] crate::oidc::OidcError),
+    /// http 500
     #[error("Couldn't store file")]
     FileTransferError(#[from] std::io::Error),
+    /// http 500. Missing objects are http 404
+    #[error("Couldn't access media store")]
+    StorageError(#[from] crate::storage::StoreError),
     /// http 400
     #[error("Invalid ID")]
     InvalidID(#[from] mongodb::bson::oid::Error),
@@ -47,6 +59,18 @@ pub enum ApiError {
     #[error("{0}")]
     /// http 400
     BadRequest(&'static str),
+    #[error("Missing scope {0}")]
+    /// http 403
+    MissingScope(crate::mongo::access_token::Scope),
+    #[error("Account {}", .0.state())]
+    /// http 403. Includes the reason and the appeal note
+    AccountRestricted(crate::mongo::user::AccountStatus),
+    #[error("Account pending deletion")]
+    /// http 403. Includes the date the account will be purged
+    PendingDeletion(mongodb::bson::DateTime),
+    #[error("Too many attempts. Try again in {0} seconds")]
+    /// http 429. Includes the `Retry-After` header
+    TooManyRequests(u64),
     #[error("{0}")]
     Other(&'static str, Status),
 }
@@ -61,23 +85,51 @@ impl<'r> Responder<'r, 'static> for ApiError {
             | ApiError::InvalidFormat(_)
             | ApiError::InvalidDate(_) => Status::BadRequest,
 
+            ApiError::StorageError(crate::storage::StoreError::NotFound(_)) => Status::NotFound,
             ApiError::DatabaseError(_)
+            | ApiError::CacheError(_)
+            | ApiError::MailError(_)
             | ApiError::InternalServerError(_)
-            | ApiError::FileTransferError(_) => Status::InternalServerError,
+            | ApiError::FileTransferError(_)
+            | ApiError::StorageError(_) => Status::InternalServerError,
+            ApiError::IdentityProviderError(crate::oidc::OidcError::InvalidToken(_)) => {
+                Status::Unauthorized
+            }
+            ApiError::IdentityProviderError(_) => Status::BadGateway,
             ApiError::Conflict(_) => Status::Conflict,
             ApiError::Unauthorized(_) => Status::Unauthorized,
             ApiError::NotFound(_) => Status::NotFound,
+            ApiError::TooManyRequests(_) => Status::TooManyRequests,
+            ApiError::MissingScope(_)
+            | ApiError::AccountRestricted(_)
+            | ApiError::PendingDeletion(_) => Status::Forbidden,
             ApiError::Other(_, x) => x,
         };
-        let body = json!({
+        let retry_after = match self {
+            ApiError::TooManyRequests(x) => Some(x),
+            _ => None,
+        };
+        let mut body = json!({
             "status": status.reason(),
             "message": format!("{}",self)
-        })
-        .to_string();
-        Response::build()
+        });
+        if let ApiError::AccountRestricted(x) = &self {
+            body["reason"] = json!(x.reason());
+            body["until"] = json!(x.until().map(|x| x.to_string()));
+            body["appeal"] = json!(x.appeal());
+        }
+        if let ApiError::PendingDeletion(x) = &self {
+            body["delete_after"] = json!(x.to_string());
+        }
+        let body = body.to_string();
+        let mut response = Response::build();
+        response
             .status(status)
             .header(ContentType::JSON)
-            .sized_body(body.len(), Cursor::new(body))
-            .ok()
+            .sized_body(body.len(), Cursor::new(body));
+        if let Some(x) = retry_after {
+            response.raw_header("Retry-After", x.to_string());
+        }
+        response.ok()
     }
 }
@@ -10,7 +10,8 @@ use serde::Deserialize;
 
 use crate::api::data::{ApiPostResponse, ApiUserResponse, ApiDate};
 use crate::api::result::ApiResult;
-use crate::api::{POSTS_CREATION_DATE, USER_CREATION_DATE, USER_ALIAS, POSTS_VISIBILITY, POSTS_TITLE, POSTS_CAPTION, POSTS_AUTHOR};
+use crate::api::users::hidden_users;
+use crate::api::{POSTS_CREATION_DATE, USER_CREATION_DATE, USER_ALIAS, POSTS_VISIBILITY, POSTS_TITLE, POSTS_CAPTION, POSTS_AUTHOR, POSTS_AUTHOR_ID, POSTS_PROCESSING, USER_DELETE_AFTER};
 use crate::mongo::post::Post;
 use crate::mongo::user::User;
 use crate::mongo::visibility::Visibility;
@@ -73,6 +74,7 @@ pub async fn search_post(
     date: ApiDate,
     block:usize,
     post_collection: &State<Collection<Post>>,
+    user_collection: &State<Collection<User>>,
     redis_cache: &State<MultiplexedConnection>
 ) -> ApiResult<Json<Vec<ApiPostResponse>>> {
     let date = date.extract().timestamp_millis();
@@ -85,10 +87,14 @@ pub async fn search_post(
         let response = serde_json::from_str(&hit).unwrap();
         Ok(Json(response))
     } else {
+        // Posts from suspended, banned or deleted users are hidden
+        let restricted = hidden_users(user_collection).await?;
         let filter_posts = vec![
             doc! { "$match": {
                 POSTS_CREATION_DATE:{ "$lte": date },
                 POSTS_VISIBILITY : Visibility::Public,
+                POSTS_PROCESSING: { "$ne": true },
+                POSTS_AUTHOR_ID: { "$nin": restricted },
                 "$or": [
                     {POSTS_TITLE: mongodb::bson::Regex{ pattern: s.to_string(), options: "".to_string() }},
                     {POSTS_CAPTION: mongodb::bson::Regex{ pattern: s.to_string(), options: "".to_string() }},
@@ -125,6 +131,7 @@ pub async fn search_user(
         let filter_users = vec![
             doc! { "$match": {
                 USER_CREATION_DATE:{ "$lte": date },
+                USER_DELETE_AFTER: { "$exists": false },
                 USER_ALIAS: mongodb::bson::Regex{ pattern: s.to_string(), options: "".to_string() }
             }},
             doc! { "$sort": { USER_CREATION_DATE: -1 } },
@@ -4,18 +4,25 @@ use crate::mongo::user::Session;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PublicSessionData {
+    id: Option<String>,
     ip: Option<String>,
+    user_agent: Option<String>,
     date: String,
+    last_used: String,
+    current: bool,
 }
 
 impl PublicSessionData {
-    pub fn new(ip: Option<String>, date: String) -> Self {
-        PublicSessionData { ip, date }
-    }
-    pub fn from_session(session: Session) -> Self {
+    /// Creates the public view of a session. `current` marks the session that
+    /// issued the token used on the request
+    pub fn from_session(session: Session, current: bool) -> Self {
         PublicSessionData {
+            id: session.id().map(|x| x.to_string()),
             ip: session.ip().clone(),
+            user_agent: session.user_agent().clone(),
             date: session.date().to_string(),
+            last_used: session.last_used().to_string(),
+            current,
         }
     }
 }
@@ -0,0 +1,57 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use rocket::State;
+
+use crate::api::data::ObjectIdWrapper;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::mongo::access_token::Scope;
+use crate::api::users::auth::revocation::revoke_session;
+use crate::api::{SESSION_ID, SESSION_USER_ID};
+use crate::mongo::session::Session;
+
+/// # AUTH! `DELETE /api/sessions/<id>`
+///
+/// Closes a single session from the current user. The session `id` can be
+/// found on [crate::api::sessions::get::get_user_sessions()]. Access tokens
+/// issued by the session stop working immediately
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `id` isn't correctly formated |
+/// | 403 | Missing scope |
+/// | 404 | Session not found |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `DELETE /api/sessions/6138ae1329e3d1d8a3c6a0f2`
+#[delete("/<id>")]
+pub async fn delete_session(
+    id: ObjectIdWrapper,
+    token: TokenClaims,
+    session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
+) -> ApiResult<()> {
+    token.require(Scope::Account)?;
+    let sid = id.extract();
+    let filter = doc! { SESSION_ID: sid, SESSION_USER_ID: token.user_id() };
+    session_collection
+        .find_one_and_delete(filter, None)
+        .await?
+        .ok_or(ApiError::NotFound("Session"))?;
+    revoke_session(redis, &sid).await?;
+    Ok(())
+}
@@ -7,19 +7,26 @@ use rocket::State;
 use crate::api::result::ApiResult;
 use crate::api::sessions::data::PublicSessionData;
 use crate::api::users::auth::claims::TokenClaims;
-use crate::api::SESSION_USER_ALIAS;
+use crate::mongo::access_token::Scope;
+use crate::api::{SESSION_EXPIRES, SESSION_USER_ID};
 use crate::mongo::user::Session;
 
 /// # AUTH! `GET /api/sessions`
-/// Returns all current sessions from the user
+/// Returns all current sessions from the user. The session used to
+/// authenticate this request is marked as `current`. Use the session `id` to
+/// [close it](crate::api::sessions::delete::delete_session)
 ///
 /// # Returns
 /// ## Ok (200)
 ///
 /// ```json
 /// [{
+///     "id": String,
 ///     "ip": String,
-///     "date": String
+///     "user_agent": String,
+///     "date": String,
+///     "last_used": String,
+///     "current": bool
 /// },
 ///
 /// ...]
@@ -35,6 +42,7 @@ use crate::mongo::user::Session;
 ///
 /// | Code | Description |
 /// | -----| ----------- |
+/// | 403 | Missing scope |
 /// | 500 | Couldn't connect to database |
 ///
 /// # Example
@@ -43,8 +51,12 @@ use crate::mongo::user::Session;
 ///
 /// ```json
 /// [{
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
 ///     "ip": "127.0.0.1",
-///     "date": "2021-09-08 12:36:51.077 UTC"
+///     "user_agent": "Mozilla/5.0 (X11; Linux x86_64; rv:92.0) Gecko/20100101 Firefox/92.0",
+///     "date": "2021-09-08 12:36:51.077 UTC",
+///     "last_used": "2021-09-08 12:40:12.318 UTC",
+///     "current": true
 /// }]
 /// ```
 #[get("/", format = "json")]
@@ -52,12 +64,18 @@ pub async fn get_user_sessions(
     session_collection: &State<Collection<Session>>,
     token: TokenClaims,
 ) -> ApiResult<Json<Vec<PublicSessionData>>> {
-    let filter = doc! { SESSION_USER_ALIAS : token.alias() };
+    token.require(Scope::Account)?;
+    let filter = doc! {
+        SESSION_USER_ID: token.user_id(),
+        SESSION_EXPIRES: { "$gt": mongodb::bson::DateTime::now() }
+    };
     let mut cursor = session_collection.find(filter, None).await?;
 
     let mut vec = Vec::new();
     while let Some(res) = cursor.next().await {
-        vec.push(PublicSessionData::from_session(res?));
+        let session = res?;
+        let current = session.id() == Some(token.session());
+        vec.push(PublicSessionData::from_session(session, current));
     }
     Ok(Json(vec))
 }
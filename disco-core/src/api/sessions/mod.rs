@@ -1,27 +1,38 @@
 use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
 use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use rocket::futures::StreamExt;
 use rocket::State;
 
-use crate::api::result::{ApiError, ApiResult};
-use crate::api::SESSION_USER_ALIAS;
+use crate::api::result::ApiResult;
+use crate::api::users::auth::revocation::revoke_session;
+use crate::api::SESSION_USER_ID;
 use crate::mongo::session::Session;
-use crate::mongo::user::Alias;
 
 /// Data Structures used on this module
 mod data;
+/// DELETE /api/sessions/
+pub mod delete;
 /// GET /api/sessions/
 pub mod get;
 /// POST /api/sessions/
 pub mod post;
 
+/// Closes every session from the user and revokes the access tokens they
+/// issued
 pub async fn delete_all_sessions_from(
-    user_alias: &Alias,
+    user_id: ObjectId,
     session_collection: &State<Collection<Session>>,
+    redis: &MultiplexedConnection,
 ) -> ApiResult<()> {
-    let filter = doc! { SESSION_USER_ALIAS: user_alias };
-    session_collection
-        .delete_many(filter, None)
-        .await
-        .map(|_| ())
-        .map_err(ApiError::DatabaseError)
+    let filter = doc! { SESSION_USER_ID: user_id };
+    let mut cursor = session_collection.find(filter.clone(), None).await?;
+    while let Some(session) = cursor.next().await {
+        if let Some(sid) = session?.id() {
+            revoke_session(redis, &sid).await?;
+        }
+    }
+    session_collection.delete_many(filter, None).await?;
+    Ok(())
 }
@@ -1,15 +1,18 @@
 use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
 use rocket::State;
 
 use crate::api::result::ApiResult;
 use crate::api::sessions::delete_all_sessions_from;
 use crate::api::users::auth::claims::TokenClaims;
+use crate::mongo::access_token::Scope;
 use crate::mongo::session::Session;
 
 /// # AUTH! `POST /api/sessions/delete`
 ///
 /// Deletes all sessions from the current user, included the current one. Can be
-/// used to log out on all browsers, for example
+/// used to log out on all browsers, for example. Access tokens issued by those
+/// sessions stop working immediately
 ///
 /// > Note: This is a no body post request, with no body response.
 ///
@@ -28,6 +31,7 @@ use crate::mongo::session::Session;
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | Body is not empty |
+/// | 403 | Missing scope |
 /// | 500 | Couldn't connect to database |
 ///
 /// # Example
@@ -37,6 +41,8 @@ use crate::mongo::session::Session;
 pub async fn delete_all_sessions(
     token: TokenClaims,
     session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
 ) -> ApiResult<()> {
-    delete_all_sessions_from(token.alias(), session_collection).await
+    token.require(Scope::Account)?;
+    delete_all_sessions_from(token.user_id(), session_collection, redis).await
 }
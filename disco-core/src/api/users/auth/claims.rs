@@ -1,13 +1,24 @@
+use std::ops::Deref;
+
 use chrono::Utc;
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{Header, Validation};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
 use rocket::http::Status;
 use rocket::request::Request;
 use rocket::request::{FromRequest, Outcome};
 use rocket::serde::json::serde_json::json;
 use rocket::serde::json::Value;
+use redis::aio::MultiplexedConnection;
 use serde::{Deserialize, Serialize};
 
-use crate::mongo::user::Alias;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::auth::revocation::{is_restricted, is_revoked};
+use crate::api::users::tokens::find_access_token;
+use crate::mongo::access_token::{AccessToken, Scope, ACCESS_TOKEN_PREFIX};
+use crate::mongo::token;
+use crate::mongo::user::{Role, User};
 
 /// JWT Time To Live
 #[cfg(debug_assertions)]
@@ -20,11 +31,49 @@ pub type EncryptedToken = String;
 pub type ExpiresIn = i64;
 
 /// Represents a JWT's payload. Visit <https://jwt.io> to learn more about JWT
+///
+/// The subject (`sub`) is the user id, which never changes, so tokens stay
+/// valid when the user changes their alias. Each token carries its own id
+/// (`jti`) and the id of the session that issued it (`sid`). Both can be [revoked](crate::api::users::auth::revocation)
+/// before the token expires
+///
+/// # Personal access tokens
+///
+/// The guard also accepts [personal access tokens](AccessToken). They are
+/// checked against the database on every request, and only grant the scopes
+/// chosen by the user. Routes must check the scope they need using
+/// [TokenClaims::require]
+///
+/// # Suspensions
+///
+/// Tokens owned by suspended or banned accounts are rejected with `403`. See
+/// [restrict_account](crate::api::users::auth::revocation::restrict_account).
+/// Personal access tokens are checked against the account status on the
+/// database as well, so they stay rejected if Redis loses the restriction
+///
+/// # Roles
+///
+/// Session tokens carry the [role](Role) the user had when the token was
+/// issued. Tokens are short lived, so role changes take effect on the next
+/// refresh. Routes restricted to privileged users use [ModeratorClaims] or
+/// [AdminClaims] instead
 #[derive(Debug, Serialize, Deserialize, Eq, PartialOrd, PartialEq, Ord)]
 pub struct TokenClaims {
-    sub: Alias,
+    // The JWT library expects `sub` to be a string
+    #[serde(with = "object_id_as_hex")]
+    sub: ObjectId,
+    jti: String,
+    #[serde(with = "object_id_as_hex")]
+    sid: ObjectId,
     exp: i64,
     iat: i64,
+    // Tokens issued before roles existed belong to regular users
+    #[serde(default)]
+    role: Role,
+    // Scopes granted by personal access tokens. Session tokens have every
+    // scope
+    #[serde(skip)]
+    scopes: Option<Vec<Scope>>,
 }
 
 #[rocket::async_trait]
@@ -36,12 +85,28 @@ impl<'r> FromRequest<'r> for TokenClaims {
             let authen_str = authen_header.to_string();
             if authen_str.starts_with("Bearer") {
                 let token = authen_str[6..authen_str.len()].trim();
-                if let Ok(token_data) = jsonwebtoken::decode::<TokenClaims>(
-                    token,
-                    &DecodingKey::from_secret(include_bytes!("secret.key")),
-                    &Validation::default(),
-                ) {
-                    return Outcome::Success(token_data.claims);
+                let claims = if token.starts_with(ACCESS_TOKEN_PREFIX) {
+                    match TokenClaims::from_access_token_request(request, token).await {
+                        Outcome::Success(x) => Some(x),
+                        x => return x,
+                    }
+                } else {
+                    let keyring = request.rocket().state::<Keyring>();
+                    keyring.and_then(|k| TokenClaims::decode(token, k))
+                };
+                if let Some(claims) = claims {
+                    // Without Redis, revocations and suspensions can't be
+                    // checked, so the token is rejected
+                    let redis = match request.rocket().state::<MultiplexedConnection>() {
+                        Some(x) => x,
+                        None => {
+                            return Outcome::Failure((
+                                Status::InternalServerError,
+                                json!({"status": Status::InternalServerError.reason(), "message": "Couldn't verify token"}),
+                            ))
+                        }
+                    };
+                    return TokenClaims::check_redis(redis, claims).await;
                 }
             }
         } else {
@@ -56,24 +121,150 @@ impl<'r> FromRequest<'r> for TokenClaims {
 }
 
 impl TokenClaims {
-    /// Creates a new JWT that is linked to the user ID on the database
-    pub fn new_encrypted(alias: Alias) -> (ExpiresIn, EncryptedToken) {
+    /// Creates a new JWT that is linked to the user ID and session on the
+    /// database. The token is signed with the current key from the keyring
+    pub fn new_encrypted(
+        user_id: ObjectId,
+        role: Role,
+        session: ObjectId,
+        keyring: &Keyring,
+    ) -> (ExpiresIn, EncryptedToken) {
         let created = Utc::now().timestamp();
         let expires = created + TTL_AUTH;
 
         let claims = TokenClaims {
-            sub: alias,
+            sub: user_id,
+            jti: token::generate(),
+            sid: session,
             exp: expires,
             iat: created,
+            role,
+            scopes: None,
         };
 
-        jsonwebtoken::encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(include_bytes!("secret.key")),
-        )
-        .map(|x| (TTL_AUTH, x))
-        .expect("Token generation failed")
+        let key = keyring.current();
+        let mut header = Header::new(key.algorithm());
+        header.kid = Some(key.kid().to_string());
+        // Unwrap is safe. The keyring checks the current key can sign tokens
+        jsonwebtoken::encode(&header, &claims, key.encoding().unwrap())
+            .map(|x| (TTL_AUTH, x))
+            .expect("Token generation failed")
+    }
+
+    /// Verifies the token signature using the key referenced by its `kid`
+    /// header
+    pub fn decode(token: &str, keyring: &Keyring) -> Option<TokenClaims> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        let key = keyring.find(header.kid.as_deref())?;
+        if header.alg != key.algorithm() {
+            return None;
+        }
+        jsonwebtoken::decode::<TokenClaims>(token, key.decoding(), &Validation::new(key.algorithm()))
+            .map(|x| x.claims)
+            .ok()
+    }
+
+    /// Rejects revoked tokens and tokens owned by suspended or banned
+    /// accounts
+    async fn check_redis(
+        redis: &MultiplexedConnection,
+        claims: TokenClaims,
+    ) -> Outcome<TokenClaims, Value> {
+        let checks = async {
+            Ok::<_, redis::RedisError>((
+                is_revoked(redis, &claims).await?,
+                is_restricted(redis, &claims.user_id()).await?,
+            ))
+        };
+        match checks.await {
+            Ok((false, false)) => Outcome::Success(claims),
+            Ok((true, _)) => Outcome::Failure((
+                Status::Unauthorized,
+                json!({"status": Status::Unauthorized.reason(), "message": "Revoked token"}),
+            )),
+            Ok((_, true)) => Outcome::Failure((
+                Status::Forbidden,
+                json!({"status": Status::Forbidden.reason(), "message": "Account suspended"}),
+            )),
+            Err(_) => Outcome::Failure((
+                Status::InternalServerError,
+                json!({"status": Status::InternalServerError.reason(), "message": "Couldn't verify token"}),
+            )),
+        }
+    }
+
+    async fn from_access_token_request(
+        request: &Request<'_>,
+        token: &str,
+    ) -> Outcome<TokenClaims, Value> {
+        let rocket = request.rocket();
+        let (collection, user_collection) = match (
+            rocket.state::<Collection<AccessToken>>(),
+            rocket.state::<Collection<User>>(),
+        ) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return Outcome::Forward(()),
+        };
+        match find_access_token(collection, user_collection, token).await {
+            // Checked on the database too, as access tokens outlive the
+            // restrictions kept on Redis
+            Ok(Some((_, user))) if !user.status().is_active() => Outcome::Failure((
+                Status::Forbidden,
+                json!({"status": Status::Forbidden.reason(), "message": "Account suspended"}),
+            )),
+            Ok(Some((x, _))) => Outcome::Success(TokenClaims::from_access_token(&x)),
+            Ok(None) => Outcome::Failure((
+                Status::Unauthorized,
+                json!({"status": Status::Unauthorized.reason(), "message": "Invalid token"}),
+            )),
+            Err(_) => Outcome::Failure((
+                Status::InternalServerError,
+                json!({"status": Status::InternalServerError.reason(), "message": "Couldn't verify token"}),
+            )),
+        }
+    }
+
+    /// Claims for a personal access token. Both `jti` and `sid` are the
+    /// access token id. Access tokens never carry privileged roles
+    pub fn from_access_token(access_token: &AccessToken) -> TokenClaims {
+        // Unwrap is safe. Access tokens stored on the database always contain
+        // an id
+        let id = access_token.id().unwrap();
+        TokenClaims {
+            sub: access_token.user_id(),
+            jti: id.to_string(),
+            sid: id,
+            exp: access_token
+                .expires()
+                .map(|x| x.timestamp_millis() / 1000)
+                .unwrap_or(i64::MAX),
+            iat: access_token.date().timestamp_millis() / 1000,
+            role: Role::User,
+            scopes: Some(access_token.scopes().clone()),
+        }
+    }
+
+    /// Checks if the token grants the scope
+    pub fn has(&self, scope: Scope) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
+
+    /// Fails with [ApiError::MissingScope] if the token doesn't grant the
+    /// scope
+    pub fn require(&self, scope: Scope) -> ApiResult<()> {
+        if self.has(scope) {
+            Ok(())
+        } else {
+            Err(ApiError::MissingScope(scope))
+        }
+    }
+
+    /// Checks if this is a personal access token instead of a session token
+    pub fn is_access_token(&self) -> bool {
+        self.scopes.is_some()
     }
 
     pub fn created(&self) -> i64 {
@@ -82,7 +273,116 @@ impl TokenClaims {
     pub fn expires(&self) -> i64 {
         self.exp
     }
-    pub fn alias(&self) -> &Alias {
-        &self.sub
+    /// Id of the user that owns the token
+    pub fn user_id(&self) -> ObjectId {
+        self.sub
+    }
+    pub fn role(&self) -> Role {
+        self.role
+    }
+    /// Unique token id
+    pub fn id(&self) -> &str {
+        &self.jti
+    }
+    /// Session that issued this token. For personal access tokens, the access
+    /// token id
+    pub fn session(&self) -> ObjectId {
+        self.sid
+    }
+}
+
+/// Request guard for routes restricted to moderators and admins. Fails with
+/// `403` if the user doesn't have the role. Personal access tokens are never
+/// accepted
+#[derive(Debug)]
+pub struct ModeratorClaims(TokenClaims);
+
+/// Request guard for routes restricted to admins. See [ModeratorClaims]
+#[derive(Debug)]
+pub struct AdminClaims(TokenClaims);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ModeratorClaims {
+    type Error = Value;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        privileged_claims(request, Role::Moderator)
+            .await
+            .map(ModeratorClaims)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminClaims {
+    type Error = Value;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        privileged_claims(request, Role::Admin).await.map(AdminClaims)
+    }
+}
+
+impl Deref for ModeratorClaims {
+    type Target = TokenClaims;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for AdminClaims {
+    type Target = TokenClaims;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+async fn privileged_claims(request: &Request<'_>, role: Role) -> Outcome<TokenClaims, Value> {
+    match request.guard::<TokenClaims>().await {
+        Outcome::Success(claims) if claims.role() >= role && !claims.is_access_token() => {
+            Outcome::Success(claims)
+        }
+        Outcome::Success(_) => Outcome::Failure((
+            Status::Forbidden,
+            json!({"status": Status::Forbidden.reason(), "message": "Insufficient privileges"}),
+        )),
+        Outcome::Failure(x) => Outcome::Failure(x),
+        Outcome::Forward(x) => Outcome::Forward(x),
+    }
+}
+
+/// Writes ObjectIds as plain hex strings on JWT payloads
+mod object_id_as_hex {
+    use mongodb::bson::oid::ObjectId;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(oid: &ObjectId, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&oid.to_hex())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ObjectId, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::TokenClaims;
+    use crate::api::users::auth::keys::{Keyring, SigningKey};
+    use crate::mongo::user::Role;
+
+    #[test]
+    pub fn token_round_trip() {
+        let keyring = Keyring::new(SigningKey::random());
+        let (user, session) = (ObjectId::new(), ObjectId::new());
+        let (_, token) = TokenClaims::new_encrypted(user, Role::default(), session, &keyring);
+        let claims = TokenClaims::decode(&token, &keyring).unwrap();
+        assert_eq!(claims.user_id(), user);
+        assert_eq!(claims.sid, session);
+        let other = Keyring::new(SigningKey::random());
+        assert!(TokenClaims::decode(&token, &other).is_none());
     }
 }
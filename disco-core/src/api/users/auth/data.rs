@@ -5,7 +5,7 @@ use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
 use serde::{Deserialize, Serialize};
 
-use crate::mongo::user::{User, UserError};
+use crate::mongo::user::{Alias, Email, Password, PasswordPolicy, User, UserError};
 use crate::mongo::IntoDocument;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,18 +15,35 @@ pub struct UserSingUp<'a> {
     pub password: &'a str,
 }
 
-impl IntoDocument<User> for UserSingUp<'_> {
+impl UserSingUp<'_> {
+    /// Pairs the request with the password policy it must follow
+    pub fn with_policy(&self, policy: &PasswordPolicy) -> PolicySignUp {
+        PolicySignUp {
+            alias: self.alias.to_string(),
+            email: self.email.to_string(),
+            password: self.password.to_string(),
+            policy: policy.clone(),
+        }
+    }
+}
+
+/// A sign up request checked against a [PasswordPolicy]. Owns its fields, so
+/// it can be validated away from the request
+pub struct PolicySignUp {
+    alias: String,
+    email: String,
+    password: String,
+    policy: PasswordPolicy,
+}
+
+impl IntoDocument<User> for PolicySignUp {
     type Err = UserError;
 
     fn validate(&self) -> Result<User, Self::Err> {
-        let UserSingUp {
-            alias,
-            email,
-            password,
-        } = self;
-        let alias = alias.parse()?;
-        let email = email.parse()?;
-        let password = password.parse()?;
+        let alias: Alias = self.alias.parse()?;
+        let email: Email = self.email.parse()?;
+        let personal = [alias.alias(), email.email()];
+        let password = Password::new(&self.password, &self.policy, &personal)?;
         Ok(User::new(alias, email, password))
     }
 }
@@ -47,6 +64,27 @@ impl<'r> FromRequest<'r> for IpAdd {
     }
 }
 
+/// Max stored length for the `User-Agent` header
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+pub struct UserAgent {
+    pub user_agent: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("User-Agent") {
+            None => Outcome::Forward(()),
+            Some(user_agent) => Outcome::Success(UserAgent {
+                user_agent: user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect(),
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserLogInEmail<'a> {
     pub email: &'a str,
@@ -59,11 +97,44 @@ pub struct UserLogInAlias<'a> {
     pub password: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserResendVerification<'a> {
+    pub alias: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserForgotPassword<'a> {
+    pub alias: Option<&'a str>,
+    pub email: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserResetPassword<'a> {
+    pub token: &'a str,
+    pub password: &'a str,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserLogInRefreshToken<'a> {
     pub refresh_token: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserRestore<'a> {
+    pub alias: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub password: &'a str,
+    pub code: Option<&'a str>,
+    pub recovery_code: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserLogInMfa<'a> {
+    pub mfa_token: &'a str,
+    pub code: Option<&'a str>,
+    pub recovery_code: Option<&'a str>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JoinedRefreshToken {
     // Session token
@@ -73,8 +144,3 @@ pub struct JoinedRefreshToken {
     // joined field
     pub users: Vec<User>,
 }
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RefreshJWT {
-    pub refresh_token: ObjectId,
-}
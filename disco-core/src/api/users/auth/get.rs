@@ -0,0 +1,67 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::http::Status;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::Value;
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::{TICKET_PURPOSE, TICKET_TOKEN, USER_ID, USER_VERIFIED};
+use crate::mongo::ticket::{Purpose, Ticket};
+use crate::mongo::token;
+use crate::mongo::user::User;
+
+/// # `GET /api/users/auth/verify?token=<token>`
+/// Verifies the user email. The token is sent by email after
+/// [signing up](crate::api::users::auth::post::signup) and can only be used
+/// once
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "status": "Ok",
+///     "message": "Email verified"
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 401 | Invalid or expired token |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `GET /api/users/auth/verify?token=3q2-7wFhJ8u1aVxqkQm0bZ4tYw9cN5rLkV2sHf6pXgE`
+#[get("/verify?<token>")]
+pub async fn verify_email(
+    token: &str,
+    ticket_collection: &State<Collection<Ticket>>,
+    user_collection: &State<Collection<User>>,
+) -> ApiResult<Value> {
+    let filter = doc! { TICKET_TOKEN: token::hash(token), TICKET_PURPOSE: Purpose::VerifyEmail };
+    let ticket = ticket_collection
+        .find_one_and_delete(filter, None)
+        .await?
+        .filter(|x| !x.is_expired())
+        .ok_or(ApiError::Unauthorized("Invalid token"))?;
+
+    let filter = doc! { USER_ID: ticket.user_id() };
+    let update = doc! { "$set": { USER_VERIFIED: true } };
+    let result = user_collection.update_one(filter, update, None).await?;
+    if result.matched_count == 1 {
+        Ok(json!({"status": Status::Ok.reason(), "message": "Email verified"}))
+    } else {
+        Err(ApiError::NotFound("User"))
+    }
+}
@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Default time (in seconds) a previous key is still accepted after the
+/// rotation
+pub const DEFAULT_ROTATION_GRACE: i64 = 3600;
+
+/// Environment variable prefix for the key used to sign new tokens
+const CURRENT_KEY_PREFIX: &str = "JWT";
+/// Environment variable prefix for the key that is being rotated out
+const PREVIOUS_KEY_PREFIX: &str = "JWT_PREVIOUS";
+
+/// Errors produced while loading the JWT keyring
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("Unknown JWT algorithm {0}")]
+    UnknownAlgorithm(String),
+    #[error("Missing {0}")]
+    MissingKey(String),
+    #[error("Invalid {0}: {1}")]
+    InvalidKey(String, jsonwebtoken::errors::Error),
+    #[error("Invalid {0}")]
+    InvalidNumber(String),
+    #[error("Invalid {0}. Expected an RFC 3339 date")]
+    InvalidDate(String),
+    #[error("Couldn't read key file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A key used to sign or verify JWTs. Keys are identified by their `kid`,
+/// which is included on the header of every issued token
+pub struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+}
+
+impl SigningKey {
+    /// Creates a new HMAC key from a shared secret
+    pub fn from_secret(kid: Option<String>, algorithm: Algorithm, secret: &[u8]) -> SigningKey {
+        SigningKey {
+            kid: kid.unwrap_or_else(|| derive_kid(secret)),
+            algorithm,
+            encoding: Some(EncodingKey::from_secret(secret)),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    /// Creates a new asymmetric key from PEM encoded files. If the private key
+    /// is missing the key can only be used to verify tokens
+    pub fn from_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        private: Option<&[u8]>,
+        public: &[u8],
+    ) -> jsonwebtoken::errors::Result<SigningKey> {
+        let (encoding, decoding) = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => (
+                private.map(EncodingKey::from_rsa_pem).transpose()?,
+                DecodingKey::from_rsa_pem(public)?,
+            ),
+            Algorithm::ES256 | Algorithm::ES384 => (
+                private.map(EncodingKey::from_ec_pem).transpose()?,
+                DecodingKey::from_ec_pem(public)?,
+            ),
+            Algorithm::EdDSA => (
+                private.map(EncodingKey::from_ed_pem).transpose()?,
+                DecodingKey::from_ed_pem(public)?,
+            ),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                return Ok(SigningKey::from_secret(kid, algorithm, public))
+            }
+        };
+        Ok(SigningKey {
+            kid: kid.unwrap_or_else(|| derive_kid(public)),
+            algorithm,
+            encoding,
+            decoding,
+        })
+    }
+
+    /// Generates a random HS256 secret. Tokens signed with this key won't
+    /// survive a server restart
+    pub fn random() -> SigningKey {
+        let secret: [u8; 32] = rand::thread_rng().gen();
+        SigningKey::from_secret(None, Algorithm::HS256, &secret)
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+    pub fn encoding(&self) -> Option<&EncodingKey> {
+        self.encoding.as_ref()
+    }
+    pub fn decoding(&self) -> &DecodingKey {
+        &self.decoding
+    }
+}
+
+/// Set of keys known by the server. New tokens are always signed with the
+/// current key, while tokens signed with the previous key are still accepted
+/// until its grace window closes. This allows rotating keys without logging
+/// out every user
+pub struct Keyring {
+    current: SigningKey,
+    previous: Option<(SigningKey, DateTime<Utc>)>,
+}
+
+impl Keyring {
+    /// Creates a keyring that only contains the given signing key. The key
+    /// must contain a private part
+    pub fn new(current: SigningKey) -> Keyring {
+        Keyring {
+            current,
+            previous: None,
+        }
+    }
+
+    /// Accepts tokens signed with `previous` until `until`
+    pub fn with_previous(mut self, previous: SigningKey, until: DateTime<Utc>) -> Keyring {
+        self.previous = Some((previous, until));
+        self
+    }
+
+    /// Loads the keyring from the environment. See `init_keyring` for the list of
+    /// variables. If no key is configured, a random one is generated
+    pub fn from_env() -> Result<Keyring, KeyError> {
+        let current = match load_key(CURRENT_KEY_PREFIX)? {
+            Some(key) if key.encoding().is_some() => key,
+            Some(_) => return Err(KeyError::MissingKey(format!("{}_KEY", CURRENT_KEY_PREFIX))),
+            None => {
+                println!("[JWT]: No signing key configured. Using a random key");
+                SigningKey::random()
+            }
+        };
+        let keyring = Keyring::new(current);
+        match load_key(PREVIOUS_KEY_PREFIX)? {
+            Some(previous) => {
+                let grace = match std::env::var(format!("{}_GRACE", PREVIOUS_KEY_PREFIX)) {
+                    Ok(x) => x.parse().map_err(|_| {
+                        KeyError::InvalidNumber(format!("{}_GRACE", PREVIOUS_KEY_PREFIX))
+                    })?,
+                    Err(_) => DEFAULT_ROTATION_GRACE,
+                };
+                // Counted from the rotation, so restarts don't extend it
+                let name = format!("{}_ROTATED_AT", PREVIOUS_KEY_PREFIX);
+                let rotated_at =
+                    std::env::var(&name).map_err(|_| KeyError::MissingKey(name.clone()))?;
+                let rotated_at = DateTime::parse_from_rfc3339(rotated_at.trim())
+                    .map_err(|_| KeyError::InvalidDate(name))?
+                    .with_timezone(&Utc);
+                Ok(keyring.with_previous(previous, rotated_at + Duration::seconds(grace)))
+            }
+            None => Ok(keyring),
+        }
+    }
+
+    /// Key used to sign new tokens
+    pub fn current(&self) -> &SigningKey {
+        &self.current
+    }
+
+    /// Looks for the key that signed a token. Tokens without `kid` are
+    /// verified with the current key
+    pub fn find(&self, kid: Option<&str>) -> Option<&SigningKey> {
+        match kid {
+            None => Some(&self.current),
+            Some(kid) if kid == self.current.kid() => Some(&self.current),
+            Some(kid) => match &self.previous {
+                Some((key, until)) if key.kid() == kid && Utc::now() < *until => Some(key),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Reads a key from the environment variables that start with `prefix`:
+///
+/// - `<prefix>_ALGORITHM`: Defaults to `HS256`
+/// - `<prefix>_KID`: Defaults to a hash of the key
+/// - `<prefix>_KEY` or `<prefix>_KEY_FILE`: Secret for HMAC algorithms or PEM
+///   private key for asymmetric algorithms
+/// - `<prefix>_PUBLIC_KEY` or `<prefix>_PUBLIC_KEY_FILE`: PEM public key for
+///   asymmetric algorithms
+fn load_key(prefix: &str) -> Result<Option<SigningKey>, KeyError> {
+    let algorithm = match std::env::var(format!("{}_ALGORITHM", prefix)) {
+        Ok(x) => Algorithm::from_str(&x).map_err(|_| KeyError::UnknownAlgorithm(x))?,
+        Err(_) => Algorithm::HS256,
+    };
+    let kid = std::env::var(format!("{}_KID", prefix)).ok();
+    let private = read_var(&format!("{}_KEY", prefix))?;
+    let public = read_var(&format!("{}_PUBLIC_KEY", prefix))?;
+
+    match (algorithm, private, public) {
+        (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512, Some(secret), _) => {
+            Ok(Some(SigningKey::from_secret(kid, algorithm, &secret)))
+        }
+        (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512, None, _) => Ok(None),
+        (_, private, Some(public)) => {
+            SigningKey::from_pem(kid, algorithm, private.as_deref(), &public)
+                .map(Some)
+                .map_err(|e| KeyError::InvalidKey(format!("{}_KEY", prefix), e))
+        }
+        (_, Some(_), None) => Err(KeyError::MissingKey(format!("{}_PUBLIC_KEY", prefix))),
+        (_, None, None) => Ok(None),
+    }
+}
+
+/// Reads the value of `name`, or the contents of the file pointed by
+/// `name_FILE`. Trailing whitespace, such as the newline most editors add at
+/// the end of files, is removed
+fn read_var(name: &str) -> Result<Option<Vec<u8>>, KeyError> {
+    if let Ok(value) = std::env::var(name) {
+        Ok(Some(value.into_bytes()))
+    } else if let Ok(path) = std::env::var(format!("{}_FILE", name)) {
+        Ok(Some(trim_end(std::fs::read(path)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn trim_end(mut content: Vec<u8>) -> Vec<u8> {
+    while content.last().map(|x| x.is_ascii_whitespace()) == Some(true) {
+        content.pop();
+    }
+    content
+}
+
+/// Derives a short, stable identifier from the key material
+fn derive_kid(material: &[u8]) -> String {
+    Sha256::digest(material)
+        .iter()
+        .take(8)
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::Algorithm;
+
+    use super::{trim_end, Keyring, SigningKey};
+
+    #[test]
+    pub fn find_current() {
+        let keyring = Keyring::new(SigningKey::from_secret(None, Algorithm::HS256, b"secret"));
+        let kid = keyring.current().kid().to_string();
+        assert!(keyring.find(Some(&kid)).is_some());
+        assert!(keyring.find(None).is_some());
+        assert!(keyring.find(Some("unknown")).is_none());
+    }
+
+    #[test]
+    pub fn previous_grace() {
+        let current = SigningKey::from_secret(Some("new".to_string()), Algorithm::HS256, b"new");
+        let previous = SigningKey::from_secret(Some("old".to_string()), Algorithm::HS256, b"old");
+        let until = Utc::now() + Duration::seconds(60);
+        let keyring = Keyring::new(current).with_previous(previous, until);
+        assert!(keyring.find(Some("old")).is_some());
+
+        let current = SigningKey::from_secret(Some("new".to_string()), Algorithm::HS256, b"new");
+        let previous = SigningKey::from_secret(Some("old".to_string()), Algorithm::HS256, b"old");
+        let until = Utc::now() - Duration::seconds(1);
+        let keyring = Keyring::new(current).with_previous(previous, until);
+        assert!(keyring.find(Some("old")).is_none());
+    }
+
+    #[test]
+    pub fn key_file_newline() {
+        assert_eq!(trim_end(b"secret\r\n".to_vec()), b"secret");
+        assert_eq!(trim_end(b"-----END PUBLIC KEY-----\n".to_vec()), b"-----END PUBLIC KEY-----");
+        assert_eq!(trim_end(Vec::new()), b"");
+    }
+}
@@ -1,8 +1,178 @@
+use std::net::IpAddr;
+
+use mongodb::Collection;
+use rocket::http::Status;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::auth::response::{LoginResponse, MfaChallengeResponse, TokenResponse};
+use crate::config::Config;
+use crate::mailer::{Mail, Mailer};
+use crate::mongo::session::Session;
+use crate::mongo::ticket::{
+    Purpose, Ticket, MFA_CHALLENGE_TTL, RESET_PASSWORD_TTL, VERIFY_EMAIL_TTL,
+};
+use crate::mongo::token;
+use crate::mongo::user::User;
+
 /// JWT claims
 pub mod claims;
-/// Datastructures for serializing and deserializing data
-mod data;
+/// Datastructures for serializing and deserializing data, and request guards
+/// for client information
+pub mod data;
+/// GET /api/users/auth
+pub mod get;
+/// JWT signing and verification keys
+pub mod keys;
 /// POST /api/users/auth
 pub mod post;
 /// Response for auth API
 pub mod response;
+/// Access token denylist
+pub mod revocation;
+/// Failed login counters and lockouts
+pub mod throttle;
+
+// helper functions
+
+/// Opens a new session for the user and returns its tokens
+pub async fn create_session(
+    user: User,
+    session_collection: &Collection<Session>,
+    keyring: &Keyring,
+    ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> ApiResult<TokenResponse> {
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    let user_id = user.id().unwrap();
+    let refresh_token = token::generate();
+    let session = Session::new(
+        user_id,
+        ip.map(|x| x.to_string()),
+        user_agent,
+        &refresh_token,
+    );
+    let inserted = session_collection.insert_one(&session, None).await?;
+    // Unwrap is safe. Inserted sessions always have an ObjectId
+    let session_id = inserted.inserted_id.as_object_id().unwrap();
+    let (expires, payload) =
+        TokenClaims::new_encrypted(user_id, user.role(), session_id, keyring);
+    Ok(TokenResponse::new(expires, refresh_token, payload))
+}
+
+/// Stores a new MFA challenge for the user. The session is created once the
+/// challenge is completed on [post::login_mfa]
+pub async fn issue_mfa_challenge(
+    user: &User,
+    ticket_collection: &Collection<Ticket>,
+) -> ApiResult<LoginResponse> {
+    let token = issue_ticket(user, Purpose::MfaChallenge, MFA_CHALLENGE_TTL, ticket_collection).await?;
+    Ok(LoginResponse::MfaRequired(MfaChallengeResponse::new(MFA_CHALLENGE_TTL, token)))
+}
+
+/// Rejects users that haven't verified their email, if the server requires it
+pub fn verify_email_status(user: &User, config: &Config) -> ApiResult<()> {
+    if config.require_verified_email && !user.verified() {
+        Err(ApiError::Other("Email not verified", Status::Forbidden))
+    } else {
+        Ok(())
+    }
+}
+
+/// Rejects users whose account has been suspended, banned or is pending
+/// deletion. See [post::restore]
+pub fn verify_account_status(user: &User) -> ApiResult<()> {
+    if !user.status().is_active() {
+        Err(ApiError::AccountRestricted(user.status().clone()))
+    } else if let Some(x) = user.delete_after() {
+        Err(ApiError::PendingDeletion(x))
+    } else {
+        Ok(())
+    }
+}
+
+/// Stores a new ticket for the user and returns its token
+async fn issue_ticket(
+    user: &User,
+    purpose: Purpose,
+    ttl: i64,
+    ticket_collection: &Collection<Ticket>,
+) -> ApiResult<String> {
+    let token = token::generate();
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    let ticket = Ticket::new(user.id().unwrap(), purpose, &token, ttl);
+    ticket_collection.insert_one(ticket, None).await?;
+    Ok(token)
+}
+
+/// Creates a new email verification ticket for the user and sends the link by
+/// email
+pub async fn send_verification_email(
+    user: &User,
+    ticket_collection: &Collection<Ticket>,
+    mailer: &dyn Mailer,
+    config: &Config,
+) -> ApiResult<()> {
+    let token = issue_ticket(user, Purpose::VerifyEmail, VERIFY_EMAIL_TTL, ticket_collection).await?;
+    mailer.send(verification_mail(user, &token, &config.public_url)).await?;
+    Ok(())
+}
+
+/// Email with the verification link for `token`
+fn verification_mail(user: &User, token: &str, public_url: &str) -> Mail {
+    let body = format!(
+        "Hi {},\n\nPlease verify your fuzzy-disco account by opening the following link:\n\n{}/api/users/auth/verify?token={}\n\nThe link expires in {} hours",
+        user.alias().alias(),
+        public_url,
+        token,
+        VERIFY_EMAIL_TTL / 3600
+    );
+    Mail::new(user.email().email(), "Verify your email", body)
+}
+
+/// Creates a new password reset ticket for the user and sends the token by
+/// email
+async fn send_password_reset_email(
+    user: &User,
+    ticket_collection: &Collection<Ticket>,
+    mailer: &dyn Mailer,
+) -> ApiResult<()> {
+    let token =
+        issue_ticket(user, Purpose::ResetPassword, RESET_PASSWORD_TTL, ticket_collection).await?;
+    let body = format!(
+        "Hi {},\n\nSomeone asked to reset the password of your fuzzy-disco account. If it wasn't you, ignore this email.\n\nYour reset token is:\n\n{}\n\nThe token expires in {} minutes",
+        user.alias().alias(),
+        token,
+        RESET_PASSWORD_TTL / 60
+    );
+    let mail = Mail::new(user.email().email(), "Reset your password", body);
+    mailer.send(mail).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::verification_mail;
+    use crate::mailer::{MailboxMailer, Mailer};
+    use crate::mongo::user::User;
+
+    #[rocket::async_test]
+    pub async fn verification_mail_is_captured() {
+        let json = "{\"alias\":\"Altair-Bueno\",\"email\":\"hello@world.com\",\"password\":\"$2b$12$NpqbpxgCy2EN6sdm/3YB4eRGfn1LdPbeMPHoxHW3bpQqAiytYDn46\",\"creation_date\":{\"$date\":{\"$numberLong\":\"1630711570146\"}}}";
+        let user: User = serde_json::from_str(json).unwrap();
+        let folder = std::env::temp_dir().join(format!("mailbox-{}", ObjectId::new()));
+        let mailer = MailboxMailer::new(&folder);
+        let mail = verification_mail(&user, "abc", "http://127.0.0.1:8000");
+        mailer.send(mail).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&folder).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.starts_with("To: hello@world.com\nSubject: Verify your email\n"));
+        assert!(content.contains("http://127.0.0.1:8000/api/users/auth/verify?token=abc"));
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -45,3 +45,48 @@ impl<'r, 'o> Responder<'r, 'static> for TokenResponse {
             .ok()
     }
 }
+
+/// Returned instead of a [TokenResponse] when the user has two-factor
+/// authentication enabled. The `mfa_token` must be sent back together with a
+/// TOTP or recovery code
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaChallengeResponse {
+    mfa_token: String,
+    expires_in: i64,
+}
+
+impl MfaChallengeResponse {
+    pub fn new(expires_in: i64, mfa_token: String) -> MfaChallengeResponse {
+        MfaChallengeResponse {
+            mfa_token,
+            expires_in,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for MfaChallengeResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let value = json!(
+            {
+                "status": "mfa_required",
+                "mfa_token": self.mfa_token,
+                "expires_in": self.expires_in,
+            }
+        );
+        let body = rocket::serde::json::serde_json::to_string(&value).unwrap();
+        Response::build()
+            .status(Status::Ok)
+            .header(ContentType::JSON)
+            .raw_header("Cache-Control", "no-store")
+            .raw_header("Pragma", "no-cache")
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// Response for password logins
+#[derive(Debug, Responder)]
+pub enum LoginResponse {
+    Token(TokenResponse),
+    MfaRequired(MfaChallengeResponse),
+}
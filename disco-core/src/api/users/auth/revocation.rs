@@ -0,0 +1,69 @@
+use mongodb::bson::oid::ObjectId;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, RedisResult};
+
+use crate::api::users::auth::claims::{TokenClaims, TTL_AUTH};
+
+/// Access tokens are short lived, so revoked sessions only need to be
+/// remembered until every token issued for them has expired
+const REVOKED_SESSION_TTL: usize = TTL_AUTH as usize + 1;
+
+fn session_key(sid: &ObjectId) -> String {
+    format!("revoked:session:{}", sid)
+}
+
+fn token_key(jti: &str) -> String {
+    format!("revoked:token:{}", jti)
+}
+
+fn account_key(user_id: &ObjectId) -> String {
+    format!("restricted:account:{}", user_id)
+}
+
+/// Revokes every access token issued for the given session
+pub async fn revoke_session(redis: &MultiplexedConnection, sid: &ObjectId) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    redis.set_ex(session_key(sid), 1, REVOKED_SESSION_TTL).await
+}
+
+/// Revokes a single access token until it expires
+pub async fn revoke_token(redis: &MultiplexedConnection, claims: &TokenClaims) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    let ttl = (claims.expires() - chrono::Utc::now().timestamp()).max(1) as usize;
+    redis.set_ex(token_key(claims.id()), 1, ttl).await
+}
+
+/// Checks if the token or the session that issued it have been revoked
+pub async fn is_revoked(redis: &MultiplexedConnection, claims: &TokenClaims) -> RedisResult<bool> {
+    let mut redis = redis.clone();
+    let found: usize = redis
+        .exists(&[session_key(&claims.session()), token_key(claims.id())])
+        .await?;
+    Ok(found > 0)
+}
+
+/// Rejects every token of a suspended or banned account. Suspensions pass
+/// their remaining time as `ttl`, bans last until they are lifted
+pub async fn restrict_account(
+    redis: &MultiplexedConnection,
+    user_id: &ObjectId,
+    ttl: Option<usize>,
+) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    match ttl {
+        Some(ttl) => redis.set_ex(account_key(user_id), 1, ttl.max(1)).await,
+        None => redis.set(account_key(user_id), 1).await,
+    }
+}
+
+/// Accepts the tokens of a reinstated account again
+pub async fn lift_restriction(redis: &MultiplexedConnection, user_id: &ObjectId) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    redis.del(account_key(user_id)).await
+}
+
+/// Checks if the account that owns the token has been suspended or banned
+pub async fn is_restricted(redis: &MultiplexedConnection, user_id: &ObjectId) -> RedisResult<bool> {
+    let mut redis = redis.clone();
+    redis.exists(account_key(user_id)).await
+}
@@ -0,0 +1,171 @@
+use std::net::IpAddr;
+
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, RedisResult};
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::mongo::user::User;
+
+/// Failed logins allowed for an account before it gets locked
+const ACCOUNT_FREE_ATTEMPTS: i64 = 5;
+
+/// Failed logins allowed from an IP before it gets locked. Higher than the
+/// account limit, as many users may share the same IP
+const IP_FREE_ATTEMPTS: i64 = 20;
+
+/// Time (seconds) failed attempts are remembered since the last failure
+const FAILURE_WINDOW: usize = 3600;
+
+/// Lockout time (seconds) after the first attempt over the limit. Each new
+/// failure doubles it
+const LOCKOUT_BASE: u64 = 2;
+
+/// Maximum lockout time (seconds)
+const LOCKOUT_MAX: u64 = 900;
+
+/// Counters of failed logins
+const LOGIN: &str = "login";
+
+/// Counters of password reset requests. Kept apart from logins, so asking for
+/// resets doesn't lock the account out
+const RESET: &str = "reset";
+
+fn failures_key(action: &str, subject: &str) -> String {
+    format!("{}:failures:{}", action, subject)
+}
+
+fn lock_key(action: &str, subject: &str) -> String {
+    format!("{}:lock:{}", action, subject)
+}
+
+/// Accounts are counted by id, so changing the alias doesn't reset them
+fn account_subject(user: &User) -> String {
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    format!("user:{}", user.id().unwrap())
+}
+
+fn subjects(account: Option<String>, ip: Option<IpAddr>) -> Vec<(String, i64)> {
+    let mut subjects = Vec::new();
+    if let Some(account) = account {
+        subjects.push((account, ACCOUNT_FREE_ATTEMPTS));
+    }
+    if let Some(ip) = ip {
+        subjects.push((format!("ip:{}", ip), IP_FREE_ATTEMPTS));
+    }
+    subjects
+}
+
+/// Lockout time (seconds) after `failures` consecutive failed attempts
+fn lockout(failures: i64, free: i64) -> u64 {
+    if failures < free {
+        0
+    } else {
+        let exponent = (failures - free).min(16) as u32;
+        LOCKOUT_BASE.saturating_mul(2u64.pow(exponent)).min(LOCKOUT_MAX)
+    }
+}
+
+/// Fails with [ApiError::TooManyRequests] if any of the subjects is locked out
+async fn check(
+    redis: &MultiplexedConnection,
+    action: &str,
+    subjects: &[(String, i64)],
+) -> ApiResult<()> {
+    let mut redis = redis.clone();
+    let mut retry_after = 0;
+    for (subject, _) in subjects {
+        let ttl: i64 = redis.ttl(lock_key(action, subject)).await?;
+        retry_after = retry_after.max(ttl);
+    }
+    if retry_after > 0 {
+        Err(ApiError::TooManyRequests(retry_after as u64))
+    } else {
+        Ok(())
+    }
+}
+
+/// Counts an attempt for every subject, locking out the ones that exceed the
+/// allowed attempts
+async fn record(
+    redis: &MultiplexedConnection,
+    action: &str,
+    subjects: &[(String, i64)],
+) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    for (subject, free) in subjects {
+        let key = failures_key(action, subject);
+        let (failures,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, FAILURE_WINDOW)
+            .ignore()
+            .query_async(&mut redis)
+            .await?;
+        let lockout = lockout(failures, *free);
+        if lockout > 0 {
+            redis
+                .set_ex::<_, _, ()>(lock_key(action, subject), 1, lockout as usize)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Fails with [ApiError::TooManyRequests] if the account or the IP are
+/// locked out
+pub async fn check_lockout(
+    redis: &MultiplexedConnection,
+    user: Option<&User>,
+    ip: Option<IpAddr>,
+) -> ApiResult<()> {
+    check(redis, LOGIN, &subjects(user.map(account_subject), ip)).await
+}
+
+/// Counts a failed login for the account and the IP, locking them out if
+/// they exceed the allowed attempts
+pub async fn record_failure(
+    redis: &MultiplexedConnection,
+    user: Option<&User>,
+    ip: Option<IpAddr>,
+) -> RedisResult<()> {
+    record(redis, LOGIN, &subjects(user.map(account_subject), ip)).await
+}
+
+/// Clears the failed attempts of an account after a successful login. IP
+/// counters are left untouched, so an attacker can't reset them by logging
+/// into their own account
+pub async fn clear_failures(redis: &MultiplexedConnection, user: &User) -> RedisResult<()> {
+    let mut redis = redis.clone();
+    let subject = account_subject(user);
+    redis
+        .del(&[failures_key(LOGIN, &subject), lock_key(LOGIN, &subject)])
+        .await
+}
+
+/// Counts a password reset request for the requested account and the IP,
+/// with the same limits as failed logins. `account` is the alias or email as
+/// requested, so accounts that don't exist are limited the same way and the
+/// response doesn't tell them apart
+pub async fn throttle_reset(
+    redis: &MultiplexedConnection,
+    account: &str,
+    ip: Option<IpAddr>,
+) -> ApiResult<()> {
+    let subjects = subjects(Some(account.to_string()), ip);
+    check(redis, RESET, &subjects).await?;
+    record(redis, RESET, &subjects).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lockout, ACCOUNT_FREE_ATTEMPTS, LOCKOUT_BASE, LOCKOUT_MAX};
+
+    #[test]
+    pub fn lockout_grows_exponentially() {
+        assert_eq!(lockout(ACCOUNT_FREE_ATTEMPTS - 1, ACCOUNT_FREE_ATTEMPTS), 0);
+        assert_eq!(lockout(ACCOUNT_FREE_ATTEMPTS, ACCOUNT_FREE_ATTEMPTS), LOCKOUT_BASE);
+        assert_eq!(lockout(ACCOUNT_FREE_ATTEMPTS + 2, ACCOUNT_FREE_ATTEMPTS), LOCKOUT_BASE * 4);
+        assert_eq!(lockout(i64::MAX, ACCOUNT_FREE_ATTEMPTS), LOCKOUT_MAX);
+    }
+}
@@ -1,5 +1,14 @@
+use rocket::response::Redirect;
+use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
-use crate::api::data::ObjectIdWrapper;
+use crate::api::data::{ApiUserResponse, ObjectIdWrapper};
+
+/// Public user information, or a redirect when the alias was changed recently
+#[derive(Debug, Responder)]
+pub enum UserInfoResponse {
+    Found(Json<ApiUserResponse>),
+    Moved(Redirect),
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdatePassword<'a> {
@@ -7,6 +16,11 @@ pub struct UpdatePassword<'a> {
     pub password: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateAlias<'a> {
+    pub alias: &'a str,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateUser<'a> {
     pub email: Option<&'a str>,
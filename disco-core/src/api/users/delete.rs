@@ -1,23 +1,31 @@
-use std::option::Option::Some;
-
+use mongodb::bson::DateTime;
 use mongodb::{bson::doc, Collection};
-use rocket::futures::StreamExt;
+use redis::aio::MultiplexedConnection;
 use rocket::http::Status;
 use rocket::serde::json::serde_json::json;
 use rocket::serde::json::Value;
 use rocket::State;
 
-use crate::api::media::delete_media;
 use crate::api::result::{ApiError, ApiResult};
+use crate::api::sessions::delete_all_sessions_from;
 use crate::api::users::auth::claims::{TokenClaims};
-use crate::api::{MEDIA_UPLOADED_BY, POSTS_AUTHOR, SESSION_USER_ALIAS, USER_ALIAS};
-use crate::mongo::media::Media;
-use crate::mongo::post::Post;
+use crate::config::Config;
+use crate::mongo::access_token::Scope;
+use crate::api::{ACCESS_TOKEN_USER_ID, USER_DELETE_AFTER, USER_ID};
+use crate::mongo::access_token::AccessToken;
 use crate::mongo::session::Session;
 use crate::mongo::user::User;
 
 /// # AUTH! `DELETE /api/users`
-/// Deletes the current authenticated user from the database
+/// Schedules the current authenticated user for deletion. The account is
+/// hidden right away: its profile, posts and media can't be found anymore,
+/// every session is closed and personal access tokens are deleted
+///
+/// The user can [restore](crate::api::users::auth::post::restore) the account
+/// during the next
+/// [ACCOUNT_DELETION_DAYS](crate::config::Config::account_deletion_days) days.
+/// After that, the account and everything it owns is removed by the
+/// [purge job](crate::purge)
 ///
 /// # Returns
 /// ## Ok (200)
@@ -25,7 +33,8 @@ use crate::mongo::user::User;
 /// ```json
 /// {
 ///     "status": "Ok",
-///     "message": "User deleted"
+///     "message": "User scheduled for deletion",
+///     "delete_after": String
 /// }
 /// ```
 ///
@@ -39,39 +48,35 @@ use crate::mongo::user::User;
 ///
 /// | Code | Description |
 /// | -----| ----------- |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
 /// | 500 | Couldn't connect to database |
 #[delete("/")]
 pub async fn delete_user(
     token: TokenClaims,
     user_collection: &State<Collection<User>>,
-    media_collection: &State<Collection<Media>>,
     session_collection: &State<Collection<Session>>,
-    post_collection: &State<Collection<Post>>,
+    access_token_collection: &State<Collection<AccessToken>>,
+    redis: &State<MultiplexedConnection>,
+    config: &State<Config>,
 ) -> ApiResult<Value> {
-    let bearer_token_alias = token.alias();
-    // Delete the user
-    let filter = doc! {USER_ALIAS: bearer_token_alias };
-    let count = user_collection.delete_one(filter, None).await?;
-    if count.deleted_count == 0 {
-        Err(ApiError::NotFound("User"))
-    } else {
-        // TODO user may still be able to publish posts. Need a GC for that
-        // Delete user sessions
-        let filter = doc! { SESSION_USER_ALIAS: token.alias() };
-        session_collection.delete_many(filter, None).await?;
-        // Delete user posts
-        let filter = doc! { POSTS_AUTHOR:token.alias() };
-        post_collection.delete_many(filter, None).await?;
-        // Delete all media uploaded by user
-        let filter = doc! { MEDIA_UPLOADED_BY: token.alias() };
-        let mut remove_list = media_collection.find(Some(filter.clone()), None).await?;
-
-        while let Some(next) = remove_list.next().await {
-            let _ = delete_media(&next?.id().unwrap()).await;
-        }
-        media_collection.delete_many(filter, None).await?;
-
-        Ok(json!({"status": Status::Ok.reason(), "message": "User deleted"}))
+    token.require(Scope::Account)?;
+    let now = DateTime::now().timestamp_millis();
+    let delete_after = DateTime::from_millis(now + config.account_deletion_days * 3600 * 24 * 1000);
+    let filter = doc! { USER_ID: token.user_id(), USER_DELETE_AFTER: { "$exists": false } };
+    let update = doc! { "$set": { USER_DELETE_AFTER: delete_after } };
+    let result = user_collection.update_one(filter, update, None).await?;
+    if result.matched_count == 0 {
+        return Err(ApiError::NotFound("User"));
     }
+    // Only the credentials can be used to restore the account
+    delete_all_sessions_from(token.user_id(), session_collection, redis).await?;
+    let filter = doc! { ACCESS_TOKEN_USER_ID: token.user_id() };
+    access_token_collection.delete_many(filter, None).await?;
+
+    Ok(json!({
+        "status": Status::Ok.reason(),
+        "message": "User scheduled for deletion",
+        "delete_after": delete_after.to_string()
+    }))
 }
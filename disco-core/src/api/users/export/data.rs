@@ -0,0 +1,103 @@
+use rocket::http::{ContentType, Header};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::export::{Export, ExportStatus};
+use crate::mongo::media::{Format, Media};
+use crate::mongo::session::Session;
+use crate::mongo::visibility::Visibility;
+use crate::storage::ObjectStream;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublicExportData {
+    id: Option<String>,
+    status: ExportStatus,
+    date: String,
+    expires: String,
+    // single use link. Only avaliable while the archive is ready
+    download: Option<String>,
+}
+
+impl PublicExportData {
+    pub fn new(export: Export, download: Option<String>) -> Self {
+        PublicExportData {
+            id: export.id().map(|x| x.to_string()),
+            status: export.status(),
+            date: export.date().to_string(),
+            expires: export.expires().to_string(),
+            download,
+        }
+    }
+}
+
+/// Session stored on `sessions.json`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportSessionData {
+    id: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    date: String,
+    last_used: String,
+    expires: String,
+}
+
+impl From<Session> for ExportSessionData {
+    fn from(session: Session) -> Self {
+        ExportSessionData {
+            id: session.id().map(|x| x.to_string()),
+            ip: session.ip().clone(),
+            user_agent: session.user_agent().clone(),
+            date: session.date().to_string(),
+            last_used: session.last_used().to_string(),
+            expires: session.expires().to_string(),
+        }
+    }
+}
+
+/// Media file stored on `media.json`. `file` is the path of the blob inside
+/// the archive
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportMediaData {
+    pub id: String,
+    pub format: Format,
+    pub visibility: Visibility,
+    pub file: String,
+}
+
+impl ExportMediaData {
+    pub fn new(media: &Media, file: String) -> Self {
+        ExportMediaData {
+            id: media.id().map(|x| x.to_string()).unwrap_or_default(),
+            format: media.format(),
+            visibility: media.visibility().clone(),
+            file,
+        }
+    }
+}
+
+/// Payload of a download link. `sub` is the export id and `purpose` tells it
+/// apart from other JWTs signed with the same keys
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DownloadClaims {
+    pub sub: String,
+    pub exp: i64,
+    pub purpose: String,
+}
+
+/// Export archive sent as an attachment
+pub struct ExportArchive {
+    pub file: ObjectStream,
+    pub content_type: ContentType,
+    pub disposition: Header<'static>,
+}
+
+impl<'r> Responder<'r, 'static> for ExportArchive {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(self.content_type)
+            .header(self.disposition)
+            .streamed_body(self.file)
+            .ok()
+    }
+}
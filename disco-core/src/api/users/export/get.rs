@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use rocket::http::{ContentType, Header};
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::api::data::ObjectIdWrapper;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::export::data::{ExportArchive, PublicExportData};
+use crate::api::users::export::{decode_download, download_link};
+use crate::api::{EXPORT_EXPIRES, EXPORT_ID, EXPORT_STATUS, EXPORT_USER_ID};
+use crate::mongo::access_token::Scope;
+use crate::mongo::export::{export_key, Export, ExportStatus};
+use crate::storage::{MediaStore, StoreError};
+
+/// # AUTH! `GET /api/users/export/<id>`
+/// Returns the status of an export. Once the archive is ready, the response
+/// includes a single use `download` link. The link is the same on every
+/// request, until it is used or the export expires. Requires the `account`
+/// scope
+///
+/// | Status | Description |
+/// | ------ | ----------- |
+/// | `pending` | The archive is being built |
+/// | `ready` | The archive can be [downloaded](crate::api::users::export::get::download_export) |
+/// | `downloaded` | The archive has already been downloaded |
+/// | `failed` | The archive couldn't be built. Request a new one |
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "id": String,
+///     "status": String,
+///     "date": String,
+///     "expires": String,
+///     "download": String     // Optional
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid ID |
+/// | 403 | Missing scope |
+/// | 404 | Export doesn't exist or has expired |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `GET /api/users/export/6138ae1329e3d1d8a3c6a0f2`
+///
+/// ```json
+/// {
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
+///     "status": "ready",
+///     "date": "2021-09-08 12:36:51.077 UTC",
+///     "expires": "2021-09-15 12:36:51.077 UTC",
+///     "download": "/api/users/export/download?token=eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9..."
+/// }
+/// ```
+#[get("/<id>")]
+pub async fn get_export(
+    id: ObjectIdWrapper,
+    token: TokenClaims,
+    export_collection: &State<Collection<Export>>,
+    keyring: &State<Keyring>,
+) -> ApiResult<Json<PublicExportData>> {
+    token.require(Scope::Account)?;
+    let id = id.extract();
+    let filter = doc! {
+        EXPORT_ID: id,
+        EXPORT_USER_ID: token.user_id(),
+        EXPORT_EXPIRES: { "$gt": DateTime::now() }
+    };
+    let export = export_collection
+        .find_one(filter, None)
+        .await?
+        .ok_or(ApiError::NotFound("Export"))?;
+    let download = match export.status() {
+        ExportStatus::Ready => Some(download_link(id, &export, keyring)),
+        _ => None,
+    };
+    Ok(Json(PublicExportData::new(export, download)))
+}
+
+/// # `GET /api/users/export/download?<token>`
+/// Downloads an export archive using the single use link returned by
+/// [get_export](crate::api::users::export::get::get_export). The link doesn't
+/// require authentication. Once it is used, the archive is removed from the
+/// store by the [purge job](crate::purge)
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// The `tar` archive, as an attachment
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 404 | Invalid or used link. Export has expired |
+/// | 500 | Couldn't connect to database or media store |
+#[get("/download?<token>")]
+pub async fn download_export(
+    token: &str,
+    export_collection: &State<Collection<Export>>,
+    keyring: &State<Keyring>,
+    store: &State<Arc<dyn MediaStore>>,
+) -> ApiResult<ExportArchive> {
+    let id = decode_download(token, keyring).ok_or(ApiError::NotFound("Export"))?;
+    let filter = doc! { EXPORT_ID: id, EXPORT_STATUS: ExportStatus::Ready };
+    export_collection
+        .find_one(filter.clone(), None)
+        .await?
+        .filter(|x| !x.is_expired())
+        .ok_or(ApiError::NotFound("Export"))?;
+    // The archive is opened before the link is used, so a missing archive
+    // doesn't waste it
+    let file = match store.get(&export_key(&id), None).await {
+        Ok(file) => file,
+        Err(StoreError::NotFound(_)) => return Err(ApiError::NotFound("Export")),
+        Err(e) => return Err(e.into()),
+    };
+
+    let update = doc! { "$set": { EXPORT_STATUS: ExportStatus::Downloaded } };
+    let result = export_collection.update_one(filter, update, None).await?;
+    if result.modified_count == 0 {
+        return Err(ApiError::NotFound("Export"));
+    }
+
+    Ok(ExportArchive {
+        file,
+        content_type: ContentType::new("application", "x-tar"),
+        disposition: Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"fuzzy-disco-{}.tar\"", id),
+        ),
+    })
+}
@@ -0,0 +1,271 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use rocket::futures::StreamExt;
+use rocket::serde::json::serde_json;
+use thiserror::Error;
+
+use crate::api::data::ApiPostResponse;
+use crate::api::media::{media_key, temp_path};
+use crate::api::result::ApiError;
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::export::data::{DownloadClaims, ExportMediaData, ExportSessionData};
+use crate::api::users::{locate_user_by_id, private_user_info};
+use crate::api::{
+    EXPORT_ID, EXPORT_STATUS, MEDIA_UPLOADED_BY, POSTS_AUTHOR_ID, POSTS_CREATION_DATE,
+    SESSION_USER_ID,
+};
+use crate::mongo::export::{export_key, Export, ExportStatus};
+use crate::mongo::media::Media;
+use crate::mongo::post::Post;
+use crate::mongo::session::Session;
+use crate::mongo::user::User;
+use crate::storage::{MediaStore, StoreError};
+
+/// Datastructures for serializing and deserializing data
+mod data;
+/// GET /api/users/export
+pub mod get;
+/// POST /api/users/export
+pub mod post;
+
+/// Errors produced while building an export archive. The export is marked as
+/// failed
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Couldn't connect to database: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("Couldn't write archive: {0}")]
+    File(#[from] std::io::Error),
+    #[error("Couldn't read media: {0}")]
+    Store(#[from] StoreError),
+    #[error("Couldn't serialize data: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Archive task failed: {0}")]
+    Task(#[from] rocket::tokio::task::JoinError),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+}
+
+/// `purpose` of the [download links](download_link)
+const DOWNLOAD_PURPOSE: &str = "export";
+
+/// Single use link to download the archive of a ready export. The link
+/// carries a JWT signed with the [Keyring] until the export expires, so
+/// nothing is stored and every request returns the same link. The export
+/// status makes it single use
+pub fn download_link(id: ObjectId, export: &Export, keyring: &Keyring) -> String {
+    let claims = DownloadClaims {
+        sub: id.to_string(),
+        exp: export.expires().timestamp_millis() / 1000,
+        purpose: DOWNLOAD_PURPOSE.to_string(),
+    };
+    let key = keyring.current();
+    let mut header = jsonwebtoken::Header::new(key.algorithm());
+    header.kid = Some(key.kid().to_string());
+    // Unwrap is safe. The keyring checks the current key can sign tokens
+    let token = jsonwebtoken::encode(&header, &claims, key.encoding().unwrap())
+        .expect("Token generation failed");
+    format!("/api/users/export/download?token={}", token)
+}
+
+/// Export id of a valid download link token
+fn decode_download(token: &str, keyring: &Keyring) -> Option<ObjectId> {
+    let header = jsonwebtoken::decode_header(token).ok()?;
+    let key = keyring.find(header.kid.as_deref())?;
+    if header.alg != key.algorithm() {
+        return None;
+    }
+    let validation = jsonwebtoken::Validation::new(key.algorithm());
+    jsonwebtoken::decode::<DownloadClaims>(token, key.decoding(), &validation)
+        .ok()
+        .map(|x| x.claims)
+        .filter(|x| x.purpose == DOWNLOAD_PURPOSE)
+        .and_then(|x| x.sub.parse().ok())
+}
+
+/// Builds the export archive on a background task. See
+/// [request_export](crate::api::users::export::post::request_export) for the
+/// archive contents
+#[allow(clippy::too_many_arguments)]
+fn spawn_export(
+    export_id: ObjectId,
+    user_id: ObjectId,
+    export_collection: Collection<Export>,
+    user_collection: Collection<User>,
+    post_collection: Collection<Post>,
+    media_collection: Collection<Media>,
+    session_collection: Collection<Session>,
+    store: Arc<dyn MediaStore>,
+) {
+    rocket::tokio::spawn(async move {
+        let staging = temp_path(&export_id, "export");
+        let archive = temp_path(&export_id, "export.tar");
+        let key = export_key(&export_id);
+        let result = async {
+            build_archive(
+                user_id,
+                &staging,
+                &archive,
+                &user_collection,
+                &post_collection,
+                &media_collection,
+                &session_collection,
+                store.as_ref(),
+            )
+            .await?;
+            store.put(&key, Path::new(&archive), "application/x-tar").await?;
+            Ok::<_, ExportError>(())
+        }
+        .await;
+        let _ = rocket::tokio::fs::remove_dir_all(&staging).await;
+        let _ = rocket::tokio::fs::remove_file(&archive).await;
+        let status = match result {
+            Ok(_) => ExportStatus::Ready,
+            Err(e) => {
+                println!("[EXPORT]: Export {} failed: {}", export_id, e);
+                ExportStatus::Failed
+            }
+        };
+        let filter = doc! { EXPORT_ID: export_id, EXPORT_STATUS: ExportStatus::Pending };
+        let update = doc! { "$set": { EXPORT_STATUS: status } };
+        match export_collection.update_one(filter, update, None).await {
+            // The lease ran out and the export was marked as failed, or the
+            // export was removed with its user. Nobody can download it
+            Ok(x) if x.matched_count == 0 && status == ExportStatus::Ready => {
+                if let Err(e) = store.delete(&key).await {
+                    println!("[EXPORT]: Couldn't remove archive {}: {}", export_id, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => println!("[EXPORT]: Couldn't update export {}: {}", export_id, e),
+        }
+    });
+}
+
+/// Gathers everything stored about the user and writes the archive to the
+/// local file `archive`. Media files are copied from the store to `staging`
+/// first
+#[allow(clippy::too_many_arguments)]
+async fn build_archive(
+    user_id: ObjectId,
+    staging: &str,
+    archive: &str,
+    user_collection: &Collection<User>,
+    post_collection: &Collection<Post>,
+    media_collection: &Collection<Media>,
+    session_collection: &Collection<Session>,
+    store: &dyn MediaStore,
+) -> Result<(), ExportError> {
+    let user = locate_user_by_id(user_id, user_collection).await?;
+
+    let options = FindOptions::builder()
+        .sort(doc! { POSTS_CREATION_DATE: -1 })
+        .build();
+    let mut cursor = post_collection
+        .find(doc! { POSTS_AUTHOR_ID: user_id }, options)
+        .await?;
+    let mut posts = Vec::new();
+    while let Some(post) = cursor.next().await {
+        posts.push(ApiPostResponse::from(post?));
+    }
+
+    let mut cursor = session_collection
+        .find(doc! { SESSION_USER_ID: user_id }, None)
+        .await?;
+    let mut sessions = Vec::new();
+    while let Some(session) = cursor.next().await {
+        sessions.push(ExportSessionData::from(session?));
+    }
+
+    let mut cursor = media_collection
+        .find(doc! { MEDIA_UPLOADED_BY: user_id }, None)
+        .await?;
+    rocket::tokio::fs::create_dir_all(staging).await?;
+    let mut media = Vec::new();
+    while let Some(next) = cursor.next().await {
+        let next = next?;
+        // Unwrap is safe. Documents stored on the database always have an
+        // ObjectId
+        let oid = next.id().unwrap();
+        let local = PathBuf::from(staging).join(oid.to_string());
+        match store.fetch(&media_key(&oid), &local).await {
+            Ok(_) => media.push((next, local)),
+            // Unclaimed media that has already been removed
+            Err(StoreError::NotFound(_)) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let files = vec![
+        ("profile.json", serde_json::to_vec_pretty(&private_user_info(&user))?),
+        ("posts.json", serde_json::to_vec_pretty(&posts)?),
+        ("sessions.json", serde_json::to_vec_pretty(&sessions)?),
+    ];
+    let archive = archive.to_string();
+    rocket::tokio::task::spawn_blocking(move || write_archive(&archive, files, media)).await?
+}
+
+/// Writes the tar archive to `path`. Media files are read from their local
+/// copies, stored under `media/` with the extension of their format, and
+/// listed on `media.json`
+fn write_archive(
+    path: &str,
+    mut files: Vec<(&'static str, Vec<u8>)>,
+    media: Vec<(Media, PathBuf)>,
+) -> Result<(), ExportError> {
+    let mut archive = tar::Builder::new(std::fs::File::create(path)?);
+
+    let mut manifest = Vec::with_capacity(media.len());
+    for (media, source) in media.iter() {
+        // Unwrap is safe. Documents stored on the database always have an
+        // ObjectId
+        let oid = media.id().unwrap();
+        let extension = infer::get_from_path(source)?
+            .map(|x| x.extension())
+            .unwrap_or("blob");
+        let name = format!("media/{}.{}", oid, extension);
+        archive.append_path_with_name(source, &name)?;
+        manifest.push(ExportMediaData::new(media, name));
+    }
+    files.push(("media.json", serde_json::to_vec_pretty(&manifest)?));
+
+    let mtime = mongodb::bson::DateTime::now().timestamp_millis() / 1000;
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, name, content.as_slice())?;
+    }
+    archive.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::{decode_download, download_link};
+    use crate::api::users::auth::keys::{Keyring, SigningKey};
+    use crate::mongo::export::Export;
+
+    #[test]
+    pub fn stable_download_link() {
+        let keyring = Keyring::new(SigningKey::random());
+        let export = Export::new(ObjectId::new());
+        let id = ObjectId::new();
+        let link = download_link(id, &export, &keyring);
+        assert_eq!(link, download_link(id, &export, &keyring));
+
+        let token = link.rsplit('=').next().unwrap();
+        assert_eq!(decode_download(token, &keyring), Some(id));
+        let other = Keyring::new(SigningKey::random());
+        assert_eq!(decode_download(token, &other), None);
+    }
+}
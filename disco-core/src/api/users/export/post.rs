@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::http::Status;
+use rocket::response::status::Accepted;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::Value;
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::export::spawn_export;
+use crate::api::{EXPORT_DATE, EXPORT_STATUS, EXPORT_USER_ID};
+use crate::mongo::access_token::Scope;
+use crate::mongo::export::{lease_cutoff, Export, ExportStatus};
+use crate::mongo::media::Media;
+use crate::mongo::post::Post;
+use crate::mongo::session::Session;
+use crate::mongo::user::User;
+use crate::storage::MediaStore;
+
+/// # AUTH! `POST /api/users/export`
+/// Starts building an archive with every piece of data stored about the user.
+/// The archive is built in the background. Use the returned `id` to
+/// [check its status](crate::api::users::export::get::get_export) and get a
+/// download link. Requires the `account` scope
+///
+/// The archive is a `tar` file with the following contents
+///
+/// | File | Description |
+/// | ---- | ----------- |
+/// | `profile.json` | Same as [get_full_user_info](crate::api::users::get::get_full_user_info) |
+/// | `posts.json` | Every post, public and private |
+/// | `sessions.json` | Open sessions |
+/// | `media.json` | Uploaded media files and their path on the archive |
+/// | `media/<id>.<extension>` | Uploaded media files |
+///
+/// The archive is kept on the [media store](crate::storage), so any server
+/// can send it. It can be downloaded once, until the
+/// [expiration date](crate::mongo::export::EXPORT_TTL). Then it is removed.
+/// Exports that take longer than [EXPORT_LEASE](crate::mongo::export::EXPORT_LEASE)
+/// to build are marked as failed
+///
+/// # Returns
+/// ## Accepted (202)
+///
+/// ```json
+/// {
+///     "id": String,
+///     "expires": String
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 403 | Missing scope |
+/// | 409 | Another export is being built |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/export`
+///
+/// ```json
+/// {
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
+///     "expires": "2021-09-15 12:36:51.077 UTC"
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+#[post("/")]
+pub async fn request_export(
+    token: TokenClaims,
+    export_collection: &State<Collection<Export>>,
+    user_collection: &State<Collection<User>>,
+    post_collection: &State<Collection<Post>>,
+    media_collection: &State<Collection<Media>>,
+    session_collection: &State<Collection<Session>>,
+    store: &State<Arc<dyn MediaStore>>,
+) -> ApiResult<Accepted<Value>> {
+    token.require(Scope::Account)?;
+    // Abandoned exports don't hold back new ones
+    let filter = doc! {
+        EXPORT_USER_ID: token.user_id(),
+        EXPORT_STATUS: ExportStatus::Pending,
+        EXPORT_DATE: { "$gt": lease_cutoff() }
+    };
+    if export_collection.count_documents(filter, None).await? > 0 {
+        return Err(ApiError::Other("Another export is being built", Status::Conflict));
+    }
+    let export = Export::new(token.user_id());
+    let expires = export.expires();
+    let inserted = export_collection.insert_one(export, None).await?;
+    // Unwrap is safe. Inserted exports always have an ObjectId
+    let id = inserted.inserted_id.as_object_id().unwrap();
+    spawn_export(
+        id,
+        token.user_id(),
+        (*export_collection).clone(),
+        (*user_collection).clone(),
+        (*post_collection).clone(),
+        (*media_collection).clone(),
+        (*session_collection).clone(),
+        (*store).clone(),
+    );
+    Ok(Accepted(Some(json!({
+        "id": id.to_string(),
+        "expires": expires.to_string()
+    }))))
+}
@@ -1,12 +1,17 @@
 use mongodb::bson::doc;
 use mongodb::Collection;
-use rocket::serde::json::serde_json::json;
 use rocket::serde::json::{Json, Value};
 use rocket::State;
 
+use rocket::response::Redirect;
+
 use crate::api::data::ApiUserResponse;
-use crate::api::result::ApiResult;
+use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::data::UserInfoResponse;
+use crate::api::users::{find_reservation, locate_user, locate_user_by_id, private_user_info};
+use crate::mongo::access_token::Scope;
+use crate::mongo::alias_reservation::AliasReservation;
 use crate::mongo::user::{Alias, User};
 
 /// # `GET /api/users/<alias>`
@@ -23,6 +28,13 @@ use crate::mongo::user::{Alias, User};
 /// }
 /// ```
 ///
+/// ## Redirect (307)
+///
+/// The user [changed their alias](crate::api::users::post::update_user_alias)
+/// recently. The `Location` header points to the new alias. Users that are
+/// pending deletion, suspended or banned aren't redirected, so their new alias
+/// isn't revealed
+///
 /// ## Err
 /// ```json
 /// {
@@ -34,7 +46,7 @@ use crate::mongo::user::{Alias, User};
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | `alias` isn't correctly formated |
-/// | 404 | User doesn't exist |
+/// | 404 | User doesn't exist or is pending deletion |
 /// | 500 | Couldn't connect to database |
 ///
 /// # Example
@@ -52,9 +64,26 @@ use crate::mongo::user::{Alias, User};
 pub async fn get_user_info(
     alias: Alias,
     mongo: &State<Collection<User>>,
-) -> ApiResult<Json<ApiUserResponse>> {
-    let user = crate::api::users::locate_user(&alias, mongo).await?;
-    Ok(Json(ApiUserResponse::from(user)))
+    reservation_collection: &State<Collection<AliasReservation>>,
+) -> ApiResult<UserInfoResponse> {
+    match locate_user(&alias, mongo).await {
+        Ok(user) if user.pending_deletion() => Err(ApiError::NotFound("User")),
+        Ok(user) => Ok(UserInfoResponse::Found(Json(ApiUserResponse::from(user)))),
+        Err(ApiError::NotFound(x)) => match find_reservation(&alias, reservation_collection).await? {
+            Some(reservation) => {
+                let user = locate_user_by_id(reservation.user_id(), mongo).await?;
+                if user.pending_deletion() || !user.status().is_active() {
+                    return Err(ApiError::NotFound(x));
+                }
+                Ok(UserInfoResponse::Moved(Redirect::temporary(format!(
+                    "/api/users/{}",
+                    user.alias().alias()
+                ))))
+            }
+            None => Err(ApiError::NotFound(x)),
+        },
+        Err(e) => Err(e),
+    }
 }
 
 /// # AUTH! `GET /api/users`
@@ -70,7 +99,8 @@ pub async fn get_user_info(
 ///     "email": String,
 ///     "creation_date": Date,
 ///     "description": String,
-///     "avatar": String
+///     "avatar": String,
+///     "totp": bool
 /// }
 /// ```
 ///
@@ -84,6 +114,7 @@ pub async fn get_user_info(
 ///
 /// | Code | Description |
 /// | -----| ----------- |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
 /// | 500 | Couldn't connect to database |
 ///
@@ -97,7 +128,8 @@ pub async fn get_user_info(
 ///   "email": "e@hello.es",
 ///   "creation_date": "2021-09-06 16:13:02.797 UTC",
 ///   "description" : "My cool profile"
-///   "avatar": "a2352ef"
+///   "avatar": "a2352ef",
+///   "totp": false
 /// }
 /// ```
 #[get("/")]
@@ -105,12 +137,7 @@ pub async fn get_full_user_info(
     mongo: &State<Collection<User>>,
     token: TokenClaims,
 ) -> ApiResult<Value> {
-    let user = crate::api::users::locate_user(token.alias(), mongo).await?;
-    Ok(json!({
-        "alias": user.alias(),
-        "email": user.email(),
-        "creation_date": user.creation_date().to_string(),
-        "description": user.description(),
-        "avatar": user.avatar().map(|x| x.to_string())
-    }))
+    token.require(Scope::ReadPrivate)?;
+    let user = locate_user_by_id(token.user_id(), mongo).await?;
+    Ok(private_user_info(&user))
 }
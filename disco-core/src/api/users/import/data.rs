@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::visibility::Visibility;
+
+/// Post stored on `posts.json`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportPost {
+    pub id: Option<String>,
+    pub title: String,
+    pub caption: String,
+    pub audio: String,
+    pub photo: String,
+    pub visibility: Visibility,
+}
+
+/// Media file stored on `media.json`. Only the file is read, its format is
+/// inspected again
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportMedia {
+    pub id: String,
+    pub file: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportedPost {
+    pub id: Option<String>,
+    pub post_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportFailure {
+    pub id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedPost>,
+    pub failed: Vec<ImportFailure>,
+}
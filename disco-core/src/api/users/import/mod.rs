@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+use rocket::serde::json::serde_json;
+use rocket::serde::json::Value;
+
+use crate::api::media::process::{create_renditions, queue_transcoding};
+use crate::api::media::{claim_media_update, delete_media, media_key};
+use crate::api::posts::insert_post;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::import::data::ImportPost;
+use crate::api::{MEDIA_ID, MEDIA_VISIBILITY};
+use crate::mongo::media::{Format, Media};
+use crate::mongo::post::Post;
+use crate::mongo::user::User;
+use crate::storage::MediaStore;
+
+/// Datastructures for serializing and deserializing data
+mod data;
+/// POST /api/users/import
+pub mod post;
+
+/// Name of the data limit for imported archives. See `Rocket.toml`
+const IMPORT_LIMIT_NAME: &str = "import";
+
+/// Largest archive accepted when `Rocket.toml` doesn't set the limit (MiB)
+const IMPORT_DEFAULT_LIMIT: u64 = 200;
+
+/// JSON files read from an export archive. Media files are unpacked on a
+/// temporary folder
+struct UnpackedArchive {
+    posts: Vec<u8>,
+    media: Option<Vec<u8>>,
+}
+
+/// Reads the archive created by
+/// [request_export](crate::api::users::export::post::request_export). Files
+/// under `media/` are unpacked on `folder`, and the rest are ignored
+fn unpack_archive(archive: &Path, folder: &Path) -> ApiResult<UnpackedArchive> {
+    let invalid = |_| ApiError::BadRequest("Invalid archive");
+    let mut archive = tar::Archive::new(std::fs::File::open(archive)?);
+    let mut posts = None;
+    let mut media = None;
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(invalid)?.into_owned();
+        if path == Path::new("posts.json") || path == Path::new("media.json") {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).map_err(invalid)?;
+            if path == Path::new("posts.json") {
+                posts = Some(content);
+            } else {
+                media = Some(content);
+            }
+        } else if path.parent() == Some(Path::new("media")) {
+            // file_name is never `..`, so entries can't escape the folder
+            if let Some(name) = path.file_name() {
+                entry.unpack(folder.join(name))?;
+            }
+        }
+    }
+    let posts = posts.ok_or(ApiError::BadRequest("Missing posts.json"))?;
+    Ok(UnpackedArchive { posts, media })
+}
+
+/// Unpacked file for each media id listed on `media.json`
+fn media_files(manifest: &[Value], folder: &Path) -> HashMap<String, PathBuf> {
+    manifest
+        .iter()
+        .filter_map(|x| serde_json::from_value(x.clone()).ok())
+        .filter_map(|x: data::ImportMedia| {
+            let path = Path::new(&x.file);
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) if parent == Path::new("media") => {
+                    Some((x.id, folder.join(name)))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Re-creates a post and its media for `author`. Every field is validated
+/// again, the media format is inspected from the file and images are resized
+/// like new uploads. Nothing is left
+/// behind if the post can't be imported
+async fn import_post(
+    post: ImportPost,
+    files: &HashMap<String, PathBuf>,
+    author: &User,
+    converter: &str,
+    store: &dyn MediaStore,
+    post_collection: &Collection<Post>,
+    media_collection: &Collection<Media>,
+) -> ApiResult<ObjectId> {
+    // Unwrap is safe. Users stored on the database always have an ObjectId
+    let author_id = author.id().unwrap();
+    let title = post.title.parse()?;
+    let caption = post.caption.parse()?;
+
+    let audio = import_media(&post.audio, Format::Audio, files, author_id, converter, store, media_collection).await?;
+    let photo = match import_media(&post.photo, Format::Image, files, author_id, converter, store, media_collection).await {
+        Ok(x) => x,
+        Err(e) => {
+            discard_media(&audio, store, media_collection).await;
+            return Err(e);
+        }
+    };
+
+    let new_post = Post::new(
+        title,
+        caption,
+        author_id,
+        author.alias().clone(),
+        audio,
+        photo,
+        post.visibility.clone(),
+    );
+    let mut update = claim_media_update().await;
+    update
+        .get_document_mut("$set")
+        // Unwrap is safe. The update always sets the media status
+        .unwrap()
+        .insert(MEDIA_VISIBILITY, post.visibility);
+    let query = doc! { MEDIA_ID: { "$in": [audio, photo] } };
+    let result = match media_collection.update_many(query, update, None).await {
+        Ok(_) => insert_post(new_post, post_collection, media_collection).await,
+        Err(e) => Err(e.into()),
+    };
+    if result.is_err() {
+        discard_media(&audio, store, media_collection).await;
+        discard_media(&photo, store, media_collection).await;
+    }
+    result
+}
+
+/// Stores the file listed with `id` on `media.json` as a new media file. The
+/// media isn't claimed until the post is inserted
+async fn import_media(
+    id: &str,
+    expected: Format,
+    files: &HashMap<String, PathBuf>,
+    user_id: ObjectId,
+    converter: &str,
+    store: &dyn MediaStore,
+    media_collection: &Collection<Media>,
+) -> ApiResult<ObjectId> {
+    let source = files.get(id).ok_or(ApiError::NotFound("Media file"))?;
+    let mime = match infer::get_from_path(source) {
+        Ok(kind) => kind
+            .ok_or(ApiError::BadRequest("Unknown file format"))?
+            .mime_type(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ApiError::NotFound("Media file"))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let format: Format = mime.parse()?;
+    if format != expected {
+        return Err(ApiError::BadRequest("Unexpected media format"));
+    }
+
+    let inserted = media_collection
+        .insert_one(Media::new(user_id, format, mime), None)
+        .await?;
+    // Unwrap is safe. If the document has been inserted, it contains an oid
+    let oid = inserted.inserted_id.as_object_id().unwrap();
+    if let Err(e) = store.put(&media_key(&oid), source, mime).await {
+        discard_media(&oid, store, media_collection).await;
+        return Err(e.into());
+    }
+    // Unwrap is safe. Unpacked files have UTF-8 names
+    let local = source.to_str().unwrap();
+    let processed = match format {
+        Format::Image => create_renditions(oid, local, converter, store, media_collection).await,
+        Format::Audio => queue_transcoding(oid, media_collection).await,
+    };
+    if let Err(e) = processed {
+        discard_media(&oid, store, media_collection).await;
+        return Err(e);
+    }
+    Ok(oid)
+}
+
+/// Removes a media file created during an import that failed
+async fn discard_media(oid: &ObjectId, store: &dyn MediaStore, media_collection: &Collection<Media>) {
+    let _ = delete_media(store, oid).await;
+    let _ = media_collection.delete_one(doc! { MEDIA_ID: oid }, None).await;
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::unpack_archive;
+
+    #[test]
+    pub fn unpacks_posts_and_media() {
+        let root = std::env::temp_dir().join(format!("import-{}", ObjectId::new()));
+        let folder = root.join("media");
+        std::fs::create_dir_all(&folder).unwrap();
+        let archive = root.join("archive.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+        for (name, content) in [
+            ("posts.json", "[]"),
+            ("sessions.json", "[]"),
+            ("media/a.png", "png"),
+            ("media/nested/b.png", "png"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap();
+
+        let unpacked = unpack_archive(&archive, &folder).unwrap();
+        assert_eq!(unpacked.posts, b"[]");
+        assert!(unpacked.media.is_none());
+        assert!(folder.join("a.png").exists());
+        assert!(!folder.join("b.png").exists());
+        assert!(!root.join("sessions.json").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::Status;
+use rocket::serde::json::serde_json;
+use rocket::serde::json::{Json, Value};
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::import::data::{ImportFailure, ImportPost, ImportReport, ImportedPost};
+use crate::api::users::import::{
+    import_post, media_files, unpack_archive, IMPORT_DEFAULT_LIMIT, IMPORT_LIMIT_NAME,
+};
+use crate::api::users::locate_user_by_id;
+use crate::config::Config;
+use crate::mongo::access_token::Scope;
+use crate::mongo::media::Media;
+use crate::mongo::post::Post;
+use crate::mongo::user::User;
+use crate::storage::MediaStore;
+
+/// # AUTH! `POST /api/users/import`
+/// Imports the posts from an archive created by
+/// [request_export](crate::api::users::export::post::request_export), usually
+/// on another server. The body is the `tar` archive. Requires the `account`
+/// scope
+///
+/// Each post is created again under the current user, together with its audio
+/// and photo. Titles, captions and media formats are validated as if they were
+/// new, and posts are dated at the import. A post that can't be imported is
+/// listed on `failed` and doesn't stop the import. Only `posts.json`,
+/// `media.json` and `media/` are read
+///
+/// The archive size is limited by the `import` limit on `Rocket.toml`
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "imported": [{
+///         "id": String,       // Id on the archive
+///         "post_id": String
+///     }],
+///     "failed": [{
+///         "id": String,       // Optional. Id on the archive
+///         "message": String
+///     }]
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid archive. Missing or malformed `posts.json` |
+/// | 403 | Missing scope |
+/// | 404 | User doesn't exist |
+/// | 413 | Archive too large |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/import`
+///
+/// ```json
+/// {
+///     "imported": [{
+///         "id": "6138ae1329e3d1d8a3c6a0f2",
+///         "post_id": "6141b0d929e3d1d8a3c6a1c7"
+///     }],
+///     "failed": [{
+///         "id": "6138ae5e29e3d1d8a3c6a0f9",
+///         "message": "Unexpected media format"
+///     }]
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+#[post("/", data = "<data>")]
+pub async fn import_archive(
+    data: Data<'_>,
+    limits: &Limits,
+    token: TokenClaims,
+    user_collection: &State<Collection<User>>,
+    post_collection: &State<Collection<Post>>,
+    media_collection: &State<Collection<Media>>,
+    store: &State<Arc<dyn MediaStore>>,
+    config: &State<Config>,
+) -> ApiResult<Json<ImportReport>> {
+    token.require(Scope::Account)?;
+    let author = locate_user_by_id(token.user_id(), user_collection).await?;
+
+    let name = format!("temp/import-{}", ObjectId::new());
+    let archive = format!("{}.tar", name);
+    let limit = limits
+        .get(IMPORT_LIMIT_NAME)
+        .unwrap_or_else(|| IMPORT_DEFAULT_LIMIT.mebibytes());
+    let result = match data.open(limit).into_file(&archive).await {
+        Ok(file) if file.is_complete() => {
+            let converter = &config.image_converter;
+            let store = store.as_ref();
+            import(&archive, &name, &author, converter, store, post_collection, media_collection)
+                .await
+        }
+        Ok(_) => Err(ApiError::Other("Archive too large", Status::PayloadTooLarge)),
+        Err(e) => Err(e.into()),
+    };
+    let _ = rocket::tokio::fs::remove_file(&archive).await;
+    let _ = rocket::tokio::fs::remove_dir_all(&name).await;
+    result.map(Json)
+}
+
+/// Unpacks the archive on `folder` and imports every post
+async fn import(
+    archive: &str,
+    folder: &str,
+    author: &User,
+    converter: &str,
+    store: &dyn MediaStore,
+    post_collection: &Collection<Post>,
+    media_collection: &Collection<Media>,
+) -> ApiResult<ImportReport> {
+    rocket::tokio::fs::create_dir_all(folder).await?;
+    let (archive, folder) = (archive.to_string(), folder.to_string());
+    let (unpacked, folder) = rocket::tokio::task::spawn_blocking(move || {
+        unpack_archive(archive.as_ref(), folder.as_ref()).map(|x| (x, folder))
+    })
+    .await
+    .map_err(|_| ApiError::InternalServerError("Couldn't unpack archive"))??;
+
+    let invalid = |_| ApiError::BadRequest("Invalid posts.json");
+    let posts: Vec<Value> = serde_json::from_slice(&unpacked.posts).map_err(invalid)?;
+    let manifest: Vec<Value> = match unpacked.media {
+        Some(x) => serde_json::from_slice(&x).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let files = media_files(&manifest, folder.as_ref());
+
+    let mut report = ImportReport::default();
+    for post in posts {
+        let id = post.get("id").and_then(|x| x.as_str()).map(|x| x.to_string());
+        let result = match serde_json::from_value::<ImportPost>(post) {
+            Ok(post) => {
+                import_post(
+                    post,
+                    &files,
+                    author,
+                    converter,
+                    store,
+                    post_collection,
+                    media_collection,
+                )
+                .await
+            }
+            Err(_) => Err(ApiError::BadRequest("Invalid post")),
+        };
+        match result {
+            Ok(post_id) => report.imported.push(ImportedPost {
+                id,
+                post_id: post_id.to_string(),
+            }),
+            Err(e) => report.failed.push(ImportFailure {
+                id,
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok(report)
+}
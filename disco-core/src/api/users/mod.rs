@@ -1,9 +1,17 @@
-use mongodb::bson::doc;
-use mongodb::Collection;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::{ClientSession, Collection};
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::Value;
 use rocket::State;
 
 use crate::api::result::{ApiError, ApiResult};
-use crate::api::USER_ALIAS;
+use crate::api::{
+    ALIAS_RESERVATION_ALIAS_NORMALIZED, ALIAS_RESERVATION_EXPIRES, USER_ALIAS_NORMALIZED,
+    USER_DELETE_AFTER, USER_ID, USER_STATUS_STATE, USER_STATUS_UNTIL,
+};
+use crate::mongo::alias_reservation::AliasReservation;
 use crate::mongo::user::{Alias, User};
 
 /// /api/users/auth
@@ -12,19 +20,29 @@ pub mod auth;
 mod data;
 /// DELETE /api/users
 pub mod delete;
+/// /api/users/export
+pub mod export;
 /// GET /api/users/
 pub mod get;
+/// /api/users/import
+pub mod import;
 /// PUT /api/users
 pub mod post;
+/// /api/users/oidc
+pub mod oidc;
 /// /api/users/\<alias>/posts
 pub mod posts;
+/// /api/users/tokens
+pub mod tokens;
+/// /api/users/totp
+pub mod totp;
 
 // helper functions
 
 async fn locate_user(alias: &Alias, mongo: &State<Collection<User>>) -> ApiResult<User> {
     let result = mongo
         .find_one(
-            doc! {USER_ALIAS: alias },
+            doc! {USER_ALIAS_NORMALIZED: alias.normalized() },
             None,
         )
         .await?;
@@ -33,3 +51,110 @@ async fn locate_user(alias: &Alias, mongo: &State<Collection<User>>) -> ApiResul
         Some(x) => Ok(x),
     }
 }
+
+/// Finds the user with the given id, such as the owner of a token
+pub async fn locate_user_by_id(id: ObjectId, mongo: &Collection<User>) -> ApiResult<User> {
+    let result = mongo.find_one(doc! { USER_ID: id }, None).await?;
+    match result {
+        None => Err(ApiError::NotFound("User")),
+        Some(x) => Ok(x),
+    }
+}
+
+/// Everything stored about the user except the hashed password and the TOTP
+/// secret
+fn private_user_info(user: &User) -> Value {
+    json!({
+        "alias": user.alias(),
+        "email": user.email(),
+        "creation_date": user.creation_date().to_string(),
+        "description": user.description(),
+        "avatar": user.avatar().map(|x| x.to_string()),
+        "totp": user.totp_enabled()
+    })
+}
+
+/// Maps duplicate key errors on the user unique indexes to the field that is
+/// already taken
+fn user_write_error(error: mongodb::error::Error) -> ApiError {
+    match &*error.kind {
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000 => {
+            if e.message.contains("email_normalized") {
+                ApiError::Conflict("Email")
+            } else {
+                ApiError::Conflict("User Alias")
+            }
+        }
+        _ => ApiError::DatabaseError(error),
+    }
+}
+
+/// Ids of the users that are suspended, banned or pending deletion right now.
+/// Their public posts are hidden
+pub async fn hidden_users(user_collection: &Collection<User>) -> ApiResult<Vec<Bson>> {
+    let filter = doc! {
+        "$or": [
+            { USER_STATUS_STATE: "banned" },
+            { USER_STATUS_STATE: "suspended", USER_STATUS_UNTIL: { "$gt": DateTime::now() } },
+            { USER_DELETE_AFTER: { "$exists": true } }
+        ]
+    };
+    Ok(user_collection.distinct(USER_ID, filter, None).await?)
+}
+
+/// Checks if the user asked to delete their account. Their posts and media
+/// are hidden until the account is purged or restored
+pub async fn pending_deletion(user_id: ObjectId, user_collection: &Collection<User>) -> ApiResult<bool> {
+    let filter = doc! { USER_ID: user_id, USER_DELETE_AFTER: { "$exists": true } };
+    Ok(user_collection.count_documents(filter, None).await? > 0)
+}
+
+/// Filter for an unexpired reservation of the alias. See [AliasReservation]
+fn reservation_filter(alias: &Alias) -> Document {
+    doc! {
+        ALIAS_RESERVATION_ALIAS_NORMALIZED: alias.normalized(),
+        ALIAS_RESERVATION_EXPIRES: { "$gt": DateTime::now() }
+    }
+}
+
+/// Looks for an unexpired reservation of the alias
+async fn find_reservation(
+    alias: &Alias,
+    reservation_collection: &Collection<AliasReservation>,
+) -> ApiResult<Option<AliasReservation>> {
+    Ok(reservation_collection.find_one(reservation_filter(alias), None).await?)
+}
+
+/// Fails with `409` if the reservation belongs to somebody else
+fn reserved_for(reservation: Option<AliasReservation>, owner: Option<ObjectId>) -> ApiResult<()> {
+    match reservation {
+        Some(x) if owner != Some(x.user_id()) => Err(ApiError::Conflict("User Alias")),
+        _ => Ok(()),
+    }
+}
+
+/// Fails with `409` if another user gave up the alias recently. `owner` is the
+/// id of the user that wants the alias, if any. Users can take back their old
+/// aliases
+pub async fn check_alias_reservation(
+    alias: &Alias,
+    owner: Option<ObjectId>,
+    reservation_collection: &Collection<AliasReservation>,
+) -> ApiResult<()> {
+    reserved_for(find_reservation(alias, reservation_collection).await?, owner)
+}
+
+/// Like [check_alias_reservation], inside the transaction that takes the
+/// alias. A concurrent rename that reserves the alias makes one of the
+/// transactions fail, instead of both taking it
+pub async fn check_alias_reservation_with_session(
+    alias: &Alias,
+    owner: Option<ObjectId>,
+    reservation_collection: &Collection<AliasReservation>,
+    session: &mut ClientSession,
+) -> ApiResult<()> {
+    let reservation = reservation_collection
+        .find_one_with_session(reservation_filter(alias), None, session)
+        .await?;
+    reserved_for(reservation, owner)
+}
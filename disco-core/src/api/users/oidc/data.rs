@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Stored on Redis while the user is on the identity provider
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorizationState {
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+}
+
+/// An external account that hasn't been linked to any user yet. Stored on
+/// Redis until the user chooses an alias
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: String,
+    pub email_verified: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcSignUp<'a> {
+    pub oidc_token: &'a str,
+    pub alias: &'a str,
+}
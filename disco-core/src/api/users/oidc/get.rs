@@ -0,0 +1,208 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use rocket::response::Redirect;
+use rocket::serde::json::serde_json::json;
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::data::{IpAdd, UserAgent};
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::auth::response::LoginResponse;
+use crate::api::users::auth::{
+    create_session, issue_mfa_challenge, verify_account_status, verify_email_status,
+};
+use crate::api::users::oidc::data::{AuthorizationState, PendingIdentity};
+use crate::api::users::oidc::response::OidcLoginResponse;
+use crate::api::users::oidc::{
+    pending_key, redirect_uri, state_key, store, take, AUTHORIZATION_TTL, PENDING_IDENTITY_TTL,
+};
+use crate::api::{IDENTITY_PROVIDER, IDENTITY_SUBJECT, USER_ID};
+use crate::config::Config;
+use crate::mongo::identity::Identity;
+use crate::mongo::session::Session;
+use crate::mongo::ticket::Ticket;
+use crate::mongo::token;
+use crate::mongo::user::{Alias, Email, User};
+use crate::oidc::Providers;
+
+/// # `GET /api/users/oidc/<provider>/login`
+/// Redirects the user to the identity provider login page. After logging in,
+/// the provider sends the user back to [callback]
+///
+/// The authorization code flow with PKCE is used. The provider must be
+/// configured on `OIDC_PROVIDERS`
+///
+/// # Returns
+/// ## Ok (303)
+///
+/// Redirects to the provider authorization endpoint
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 404 | Provider not found |
+/// | 500 | Couldn't connect to cache |
+/// | 502 | Couldn't connect to the identity provider |
+///
+/// # Example
+///
+/// `GET /api/users/oidc/google/login`
+#[get("/<provider>/login")]
+pub async fn login(
+    provider: &str,
+    providers: &State<Providers>,
+    redis: &State<MultiplexedConnection>,
+    config: &State<Config>,
+) -> ApiResult<Redirect> {
+    let provider = providers
+        .get(provider)
+        .ok_or(ApiError::NotFound("Provider"))?;
+    let state = token::generate();
+    let authorization = AuthorizationState {
+        provider: provider.name().to_string(),
+        code_verifier: token::generate(),
+        nonce: token::generate(),
+    };
+    let url = provider
+        .authorization_url(
+            &redirect_uri(config, provider.name()),
+            &state,
+            &authorization.nonce,
+            &authorization.code_verifier,
+        )
+        .await?;
+    store(redis, &state_key(&state), &authorization, AUTHORIZATION_TTL).await?;
+    Ok(Redirect::to(url))
+}
+
+/// # `GET /api/users/oidc/<provider>/callback?code=<code>&state=<state>`
+/// Finishes the login started on [login]. The identity provider redirects the
+/// user here
+///
+/// If the external account is already linked to a user, a session is created
+/// just like [crate::api::users::auth::post::login_alias()] does, including
+/// the two-factor authentication challenge. Otherwise the user must choose an
+/// alias using [crate::api::users::oidc::post::signup()] within
+/// [PENDING_IDENTITY_TTL] seconds
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// Same as [crate::api::users::auth::post::login_alias()], or
+///
+/// ```json
+/// {
+///     "status": "alias_required",
+///     "oidc_token": String,
+///     "expires_in": i64,
+///     "suggested_alias": String  // Optional
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | The provider didn't share a valid email |
+/// | 401 | Invalid state, the login was cancelled or the ID token is invalid |
+/// | 403 | Email not verified. Account suspended or banned |
+/// | 404 | Provider or user not found |
+/// | 500 | Couldn't connect to database |
+/// | 502 | Couldn't connect to the identity provider |
+#[allow(clippy::too_many_arguments)]
+#[get("/<provider>/callback?<code>&<state>")]
+pub async fn callback(
+    provider: &str,
+    code: Option<&str>,
+    state: &str,
+    providers: &State<Providers>,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    ticket_collection: &State<Collection<Ticket>>,
+    identity_collection: &State<Collection<Identity>>,
+    redis: &State<MultiplexedConnection>,
+    keyring: &State<Keyring>,
+    config: &State<Config>,
+    ip: Option<IpAdd>,
+    user_agent: Option<UserAgent>,
+) -> ApiResult<OidcLoginResponse> {
+    let provider = providers
+        .get(provider)
+        .ok_or(ApiError::NotFound("Provider"))?;
+    let authorization: AuthorizationState = take(redis, &state_key(state))
+        .await?
+        .filter(|x: &AuthorizationState| x.provider == provider.name())
+        .ok_or(ApiError::Unauthorized("Invalid state"))?;
+    // Providers send `error` instead of `code` when the user cancels
+    let code = code.ok_or(ApiError::Unauthorized("Login cancelled"))?;
+    let claims = provider
+        .exchange(
+            code,
+            &redirect_uri(config, provider.name()),
+            &authorization.code_verifier,
+            &authorization.nonce,
+        )
+        .await?;
+
+    let filter = doc! { IDENTITY_PROVIDER: provider.name(), IDENTITY_SUBJECT: &claims.sub };
+    if let Some(identity) = identity_collection.find_one(filter, None).await? {
+        let user = user_collection
+            .find_one(doc! { USER_ID: identity.user_id() }, None)
+            .await?
+            .ok_or(ApiError::NotFound("User"))?;
+        verify_email_status(&user, config)?;
+        verify_account_status(&user)?;
+        if user.totp_enabled() {
+            let challenge = issue_mfa_challenge(&user, ticket_collection).await?;
+            return Ok(OidcLoginResponse::Login(challenge));
+        }
+        let response = create_session(
+            user,
+            session_collection,
+            keyring,
+            ip.map(|x| x.ip),
+            user_agent.map(|x| x.user_agent),
+        )
+        .await?;
+        return Ok(OidcLoginResponse::Login(LoginResponse::Token(response)));
+    }
+
+    // First login. Users are identified by email, so one is required
+    let email = claims
+        .email
+        .as_deref()
+        .ok_or(ApiError::BadRequest("The provider didn't share an email"))?
+        .parse::<Email>()?;
+    let pending = PendingIdentity {
+        provider: provider.name().to_string(),
+        subject: claims.sub.clone(),
+        email: email.email().to_string(),
+        email_verified: claims.email_verified,
+    };
+    let oidc_token = token::generate();
+    store(redis, &pending_key(&token::hash(&oidc_token)), &pending, PENDING_IDENTITY_TTL).await?;
+    let suggested_alias = claims
+        .preferred_username
+        .as_deref()
+        .and_then(|x| x.parse::<Alias>().ok());
+    Ok(OidcLoginResponse::AliasRequired(json!({
+        "status": "alias_required",
+        "oidc_token": oidc_token,
+        "expires_in": PENDING_IDENTITY_TTL,
+        "suggested_alias": suggested_alias
+    })))
+}
@@ -0,0 +1,69 @@
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::api::result::ApiResult;
+use crate::config::Config;
+
+/// Datastructures for serializing and deserializing data
+mod data;
+/// GET /api/users/oidc
+pub mod get;
+/// POST /api/users/oidc
+pub mod post;
+/// Response for OpenID Connect login
+pub mod response;
+
+/// Time (seconds) the user has to log in on the identity provider
+const AUTHORIZATION_TTL: usize = 60 * 10;
+
+/// Time (seconds) the user has to choose an alias after their first login
+const PENDING_IDENTITY_TTL: usize = 60 * 10;
+
+// helper functions
+
+fn state_key(state: &str) -> String {
+    format!("oidc:state:{}", state)
+}
+
+fn pending_key(token_hash: &str) -> String {
+    format!("oidc:pending:{}", token_hash)
+}
+
+/// URL the identity provider sends the user back to
+fn redirect_uri(config: &Config, provider: &str) -> String {
+    format!("{}/api/users/oidc/{}/callback", config.public_url, provider)
+}
+
+async fn store<T: Serialize>(
+    redis: &MultiplexedConnection,
+    key: &str,
+    value: &T,
+    ttl: usize,
+) -> ApiResult<()> {
+    let mut redis = redis.clone();
+    // Unwrap is safe. Plain structs always serialize
+    let value = serde_json::to_string(value).unwrap();
+    redis.set_ex::<_, _, ()>(key, value, ttl).await?;
+    Ok(())
+}
+
+async fn load<T: DeserializeOwned>(redis: &MultiplexedConnection, key: &str) -> ApiResult<Option<T>> {
+    let mut redis = redis.clone();
+    let value: Option<String> = redis.get(key).await?;
+    Ok(value.and_then(|x| serde_json::from_str(&x).ok()))
+}
+
+/// Loads the value and removes it, so it can only be used once
+async fn take<T: DeserializeOwned>(redis: &MultiplexedConnection, key: &str) -> ApiResult<Option<T>> {
+    let mut redis = redis.clone();
+    let (value,): (Option<String>,) = redis::pipe()
+        .atomic()
+        .get(key)
+        .del(key)
+        .ignore()
+        .query_async(&mut redis)
+        .await?;
+    Ok(value.and_then(|x| serde_json::from_str(&x).ok()))
+}
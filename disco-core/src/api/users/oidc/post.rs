@@ -0,0 +1,127 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::data::{IpAdd, UserAgent};
+use crate::api::users::auth::keys::Keyring;
+use crate::api::users::auth::response::TokenResponse;
+use crate::api::users::auth::create_session;
+use crate::api::users::oidc::data::{OidcSignUp, PendingIdentity};
+use crate::api::users::oidc::{load, pending_key};
+use crate::api::users::{check_alias_reservation, user_write_error};
+use crate::api::IDENTITY_ID;
+use crate::config::Config;
+use crate::mongo::alias_reservation::AliasReservation;
+use crate::mongo::identity::Identity;
+use crate::mongo::session::Session;
+use crate::mongo::token;
+use crate::mongo::user::{Alias, Email, Password, User};
+
+/// # `POST /api/users/oidc/signup`
+/// Creates a new user for an external account that logged in for the first
+/// time on [crate::api::users::oidc::get::callback()], and opens a session
+///
+/// ```json
+/// {
+///     "oidc_token": String,
+///     "alias": String
+/// }
+/// ```
+///
+/// The user email is the one shared by the identity provider, and it is
+/// considered verified if the provider says so. The user gets a random
+/// password. Use [crate::api::users::auth::post::forgot_password()] to set one
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// Same as [crate::api::users::auth::post::login_alias()]
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `alias` isn't correctly formated |
+/// | 401 | Invalid or expired `oidc_token` |
+/// | 409 | Another user already has or recently had the same alias, or the same email |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/oidc/signup`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "oidc_token": "3q2-7wFhJ8u1aVxqkQm0bZ4tYw9cN5rLkV2sHf6pXgE",
+///     "alias": "Altair-Bueno"
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+#[post("/signup", format = "json", data = "<info>")]
+pub async fn signup(
+    info: Json<OidcSignUp<'_>>,
+    user_collection: &State<Collection<User>>,
+    session_collection: &State<Collection<Session>>,
+    identity_collection: &State<Collection<Identity>>,
+    reservation_collection: &State<Collection<AliasReservation>>,
+    redis: &State<MultiplexedConnection>,
+    keyring: &State<Keyring>,
+    ip: Option<IpAdd>,
+    user_agent: Option<UserAgent>,
+    config: &State<Config>,
+) -> ApiResult<TokenResponse> {
+    let alias = info.alias.parse::<Alias>()?;
+    check_alias_reservation(&alias, None, reservation_collection).await?;
+    let key = pending_key(&token::hash(info.oidc_token));
+    let pending: PendingIdentity = load(redis, &key)
+        .await?
+        .ok_or(ApiError::Unauthorized("Invalid token"))?;
+    let email = pending.email.parse::<Email>()?;
+    // Nobody knows this password. The user can set one with forgot_password
+    let password = Password::hash(&token::generate(), config.password_policy.cost)?;
+    let user = User::new(alias, email, password).with_verified(pending.email_verified);
+
+    // The unique index on the identity stops two signups with the same token.
+    // The pending identity is kept until the user is created, so the user can
+    // pick another alias if this one is taken
+    // Unwrap is safe. New users always have an ObjectId
+    let identity = Identity::new(
+        &pending.provider,
+        &pending.subject,
+        user.id().unwrap(),
+        Some(pending.email.clone()),
+    );
+    let inserted = identity_collection
+        .insert_one(&identity, None)
+        .await
+        .map_err(|_| ApiError::Unauthorized("Invalid token"))?;
+    if let Err(e) = user_collection.insert_one(&user, None).await {
+        identity_collection
+            .delete_one(doc! { IDENTITY_ID: inserted.inserted_id }, None)
+            .await?;
+        return Err(user_write_error(e));
+    }
+    let mut connection = redis.inner().clone();
+    connection.del::<_, ()>(&key).await?;
+
+    create_session(
+        user,
+        session_collection,
+        keyring,
+        ip.map(|x| x.ip),
+        user_agent.map(|x| x.user_agent),
+    )
+    .await
+}
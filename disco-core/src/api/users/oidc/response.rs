@@ -0,0 +1,11 @@
+use rocket::serde::json::Value;
+
+use crate::api::users::auth::response::LoginResponse;
+
+/// Response for the identity provider callback. Users that log in for the
+/// first time must choose an alias before a session is created
+#[derive(Debug, Responder)]
+pub enum OidcLoginResponse {
+    Login(LoginResponse),
+    AliasRequired(Value),
+}
@@ -1,7 +1,12 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use mongodb::{bson::doc, Collection};
-use rocket::serde::json::Json;
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::{Client, Collection};
+use redis::aio::MultiplexedConnection;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::{Json, Value};
 use rocket::State;
 
 use crate::api::media::{claim_media_filter, claim_media_update, delete_media, is_expired};
@@ -9,13 +14,29 @@ use crate::api::result::ApiError::InternalServerError;
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::sessions::delete_all_sessions_from;
 use crate::api::users::auth::claims::TokenClaims;
-use crate::api::users::data::{AvatarPictureID, UpdatePassword, UpdateUser};
-use crate::api::{MEDIA_ID, USER_ALIAS, USER_AVATAR, USER_PASSWORD};
+use crate::api::users::auth::send_verification_email;
+use crate::mongo::access_token::Scope;
+use crate::api::users::data::{AvatarPictureID, UpdateAlias, UpdatePassword, UpdateUser};
+use crate::api::users::{
+    check_alias_reservation_with_session, locate_user_by_id, user_write_error,
+};
+use crate::api::{
+    ALIAS_RESERVATION_ALIAS_NORMALIZED, MEDIA_ID, POSTS_AUTHOR, POSTS_AUTHOR_ID, TICKET_PURPOSE,
+    TICKET_USER_ID, USER_ALIAS, USER_ALIAS_NORMALIZED, USER_AVATAR, USER_DESCRIPTION, USER_EMAIL,
+    USER_EMAIL_NORMALIZED, USER_ID, USER_PASSWORD, USER_VERIFIED,
+};
+use crate::config::Config;
+use crate::mailer::Mailer;
+use crate::mongo::alias_reservation::AliasReservation;
 use crate::mongo::media::{Format, Media};
-use crate::mongo::user::{Description, Email, Password, Session, User};
+use crate::mongo::post::Post;
+use crate::mongo::ticket::{Purpose, Ticket};
+use crate::mongo::user::{Alias, Description, Email, Password, Session, User};
+use crate::storage::MediaStore;
 
 /// # AUTH! `POST /api/users/update/password`
-/// Changes the user password to another one
+/// Changes the user password to another one. Every session is closed and
+/// their access tokens are revoked
 ///
 /// ```json
 /// {
@@ -44,8 +65,9 @@ use crate::mongo::user::{Description, Email, Password, Session, User};
 ///
 /// | Code | Description |
 /// | -----| ----------- |
-/// | 400 | Bad request |
+/// | 400 | Bad request. Password doesn't meet the [requirements](crate::mongo::user::PasswordPolicy) |
 /// | 401 | Old password doesn't match |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
 /// | 500 | Couldn't connect to database |
 ///
@@ -68,22 +90,144 @@ pub async fn update_user_password(
     updated: Json<UpdatePassword<'_>>,
     user_collection: &State<Collection<User>>,
     session_collection: &State<Collection<Session>>,
+    redis: &State<MultiplexedConnection>,
+    config: &State<Config>,
     token: TokenClaims,
 ) -> ApiResult<()> {
-    let validated_document = updated.new_password.parse::<Password>()?;
-    let user = crate::api::users::locate_user(token.alias(), user_collection).await?;
+    token.require(Scope::Account)?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
+    // The old password is checked first, so only its owner can make the
+    // server check and hash new ones
+    match user.password().verify(updated.password).await {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::Unauthorized("Invalid password")),
+        Err(_) => return Err(InternalServerError("Couldn't hash password")),
+    }
+    let personal = [user.alias().alias(), user.email().email()];
+    let validated_document =
+        Password::create(updated.new_password, &config.password_policy, &personal).await?;
 
-    match user.password().validate(updated.password) {
-        Ok(true) => {
-            let filter = doc! { USER_ALIAS: user.alias() };
-            let update_op = doc! {"$set": { USER_PASSWORD: validated_document.password() }};
-            let _response = user_collection.update_one(filter, update_op, None).await?;
-            delete_all_sessions_from(user.alias(), session_collection).await?;
-            Ok(())
-        }
-        Ok(false) => Err(ApiError::Unauthorized("Invalid password")),
-        Err(_) => Err(InternalServerError("Couldn't hash password")),
+    let filter = doc! { USER_ID: token.user_id() };
+    let update_op = doc! {"$set": { USER_PASSWORD: validated_document.password() }};
+    let _response = user_collection.update_one(filter, update_op, None).await?;
+    delete_all_sessions_from(token.user_id(), session_collection, redis).await?;
+    Ok(())
+}
+
+/// # AUTH! `POST /api/users/update/alias`
+/// Changes the user alias. Other documents reference the user by id, so only
+/// the user and the author alias copied on their posts are updated. Both are
+/// updated on a single transaction, so the database must be a replica set
+///
+/// The old alias is reserved for
+/// [ALIAS_COOLDOWN](crate::mongo::alias_reservation::ALIAS_COOLDOWN) seconds.
+/// Nobody else can take it meanwhile, and
+/// [get_user_info](crate::api::users::get::get_user_info) redirects it to the
+/// new alias
+///
+/// ```json
+/// {
+///     "alias": String
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "alias": String
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `alias` isn't correctly formated or is the current alias |
+/// | 403 | Missing scope |
+/// | 404 | User doesn't exist |
+/// | 409 | Another user has or recently had the same alias |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/update/alias`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "alias": "Altair-Bueno"
+/// }
+/// ```
+#[post("/update/alias", format = "json", data = "<updated>")]
+pub async fn update_user_alias(
+    updated: Json<UpdateAlias<'_>>,
+    client: &State<Client>,
+    user_collection: &State<Collection<User>>,
+    post_collection: &State<Collection<Post>>,
+    reservation_collection: &State<Collection<AliasReservation>>,
+    token: TokenClaims,
+) -> ApiResult<Value> {
+    token.require(Scope::Account)?;
+    let new_alias = updated.alias.parse::<Alias>()?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
+    let old_alias = user.alias().clone();
+    if new_alias == old_alias {
+        return Err(ApiError::BadRequest("Same alias"));
+    }
+
+    let mut session = client.start_session(None).await?;
+    session.start_transaction(None).await?;
+    check_alias_reservation_with_session(
+        &new_alias,
+        Some(token.user_id()),
+        reservation_collection,
+        &mut session,
+    )
+    .await?;
+    let filter = doc! { USER_ID: token.user_id() };
+    let update = doc! {
+        "$set": { USER_ALIAS: &new_alias, USER_ALIAS_NORMALIZED: new_alias.normalized() }
+    };
+    user_collection
+        .update_one_with_session(filter, update, None, &mut session)
+        .await
+        .map_err(user_write_error)?;
+    post_collection
+        .update_many_with_session(
+            doc! { POSTS_AUTHOR_ID: token.user_id() },
+            doc! { "$set": { POSTS_AUTHOR: &new_alias } },
+            None,
+            &mut session,
+        )
+        .await?;
+    // Taking back an old alias removes its reservation. Expired reservations
+    // may still be waiting for the TTL index
+    let normalized = [new_alias.normalized(), old_alias.normalized()];
+    reservation_collection
+        .delete_many_with_session(
+            doc! { ALIAS_RESERVATION_ALIAS_NORMALIZED: { "$in": normalized.to_vec() } },
+            None,
+            &mut session,
+        )
+        .await?;
+    // Changing the case of the alias doesn't free it
+    if new_alias.normalized() != old_alias.normalized() {
+        let reservation = AliasReservation::new(old_alias.clone(), token.user_id());
+        reservation_collection
+            .insert_one_with_session(reservation, None, &mut session)
+            .await?;
     }
+    session.commit_transaction().await?;
+    Ok(json!({ "alias": new_alias }))
 }
 
 /// # AUTH! `POST /api/users/update/avatar`
@@ -112,6 +256,7 @@ pub async fn update_user_password(
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | Bad request |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
 /// | 500 | Couldn't connect to database |
 ///
@@ -126,7 +271,9 @@ pub async fn update_user_avatar(
     updated: Json<AvatarPictureID>,
     user_collection: &State<Collection<User>>,
     media_collection: &State<Collection<Media>>,
+    store: &State<Arc<dyn MediaStore>>,
 ) -> ApiResult<()> {
+    token.require(Scope::ProfileWrite)?;
     let avatar_id = {
         if let Some(id) = updated.0.media_id {
             let oid = id.extract();
@@ -134,7 +281,7 @@ pub async fn update_user_avatar(
                 return Err(ApiError::BadRequest("Expired file"))
             }
             // Claim media
-            let filter = claim_media_filter(&oid, &Format::Image, token.alias()).await;
+            let filter = claim_media_filter(&oid, &Format::Image, token.user_id()).await;
             let update = claim_media_update().await;
             let media = media_collection
                 .find_one_and_update(filter, update, None)
@@ -145,7 +292,7 @@ pub async fn update_user_avatar(
             None
         }
     };
-    let filter = doc! { USER_ALIAS: token.alias() };
+    let filter = doc! { USER_ID: token.user_id() };
     let update = doc! {"$set": { USER_AVATAR: avatar_id }};
     let user_before = user_collection
         .find_one_and_update(filter, update ,None)
@@ -156,7 +303,7 @@ pub async fn update_user_avatar(
         let filter = doc! { MEDIA_ID: avatar };
         let _ = media_collection.delete_one(filter,None).await?;
         if let Some(id) = user_before.avatar() {
-            let _ = delete_media(&id).await;
+            let _ = delete_media(store.as_ref(), &id).await;
         }
     }
     Ok(())
@@ -173,6 +320,9 @@ pub async fn update_user_avatar(
 /// }
 /// ```
 ///
+/// A new email must be verified again. A verification link is sent to it, and
+/// any pending verification or password reset ticket stops working
+///
 /// # Returns
 /// ## Ok (200)
 ///
@@ -187,7 +337,9 @@ pub async fn update_user_avatar(
 /// | Code | Description |
 /// | -----| ----------- |
 /// | 400 | Bad request |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
+/// | 409 | Another user already has the same email |
 /// | 500 | Couldn't connect to database |
 ///
 /// # Example
@@ -207,30 +359,58 @@ pub async fn update_user_avatar(
 pub async fn update_user_info(
     updated: Json<UpdateUser<'_>>,
     user_collection: &State<Collection<User>>,
+    ticket_collection: &State<Collection<Ticket>>,
+    mailer: &State<Box<dyn Mailer>>,
+    config: &State<Config>,
     token: TokenClaims,
 ) -> ApiResult<()> {
+    token.require(Scope::ProfileWrite)?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
     let mut dic = HashMap::new();
+    let mut email_changed = false;
     if let Some(s) = updated.email {
-        let _ = s.parse::<Email>()?;
-        dic.insert("email", s);
+        let email = s.parse::<Email>()?;
+        email_changed = email.normalized() != user.email().normalized();
+        dic.insert(USER_EMAIL_NORMALIZED, email.normalized());
+        dic.insert(USER_EMAIL, email.email().to_string());
     }
 
     if let Some(s) = updated.description {
         let _ = s.parse::<Description>()?;
-        dic.insert("description", s);
+        dic.insert(USER_DESCRIPTION, s.to_string());
     }
 
     // Unwrap is safe. Valid string slices
-    let update_doc = doc! {
-        "$set": mongodb::bson::to_bson(&dic).unwrap()
-    };
+    let mut set = mongodb::bson::to_document(&dic).unwrap();
+    if email_changed {
+        set.insert(USER_VERIFIED, false);
+    }
+    let update_doc = doc! { "$set": set };
 
-    let filter = doc! { USER_ALIAS: token.alias() };
-    let res = user_collection.update_one(filter, update_doc, None).await?;
+    let filter = doc! { USER_ID: token.user_id() };
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    let user = user_collection
+        .find_one_and_update(filter, update_doc, options)
+        .await
+        .map_err(user_write_error)?
+        .ok_or(ApiError::NotFound("User"))?;
 
-    if res.modified_count == 1 {
-        Ok(())
-    } else {
-        Err(ApiError::NotFound("User"))
+    if email_changed {
+        // Tickets sent to the old address can't be used anymore
+        let filter = doc! {
+            TICKET_USER_ID: token.user_id(),
+            TICKET_PURPOSE: { "$in": [Purpose::VerifyEmail, Purpose::ResetPassword] }
+        };
+        ticket_collection.delete_many(filter, None).await?;
+        // The email is already stored. The user can ask for another email later
+        let sent =
+            send_verification_email(&user, ticket_collection, mailer.inner().as_ref(), config)
+                .await;
+        if let Err(e) = sent {
+            println!("[MAIL]: Couldn't send verification email: {}", e);
+        }
     }
+    Ok(())
 }
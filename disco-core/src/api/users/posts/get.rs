@@ -11,17 +11,20 @@ use rocket::State;
 use crate::api::data::{ApiPostResponse, ApiDate};
 use crate::api::result::{ApiError, ApiResult};
 use crate::api::users::auth::claims::TokenClaims;
-use crate::api::{POSTS_AUTHOR, POSTS_CREATION_DATE, POSTS_VISIBILITY};
+use crate::mongo::access_token::Scope;
+use crate::api::users::locate_user;
+use crate::api::{POSTS_AUTHOR_ID, POSTS_CREATION_DATE, POSTS_PROCESSING, POSTS_VISIBILITY};
 use crate::mongo::post::Post;
-use crate::mongo::user::Alias;
+use crate::mongo::user::{Alias, User};
 use crate::mongo::visibility::Visibility;
 
 /// Block size for queries
 const BLOCK_SIZE:usize = 40;
 
 /// # `GET /api/users/<id>/posts?block=<usize>&date=<string>`
-/// Returns a list of public posts from the given user. The method receives the
-/// following query parameters:
+/// Returns a list of public posts from the given user. Posts whose audio is
+/// still being [transcoded](crate::api::media::transcode) are left out. The method
+/// receives the following query parameters:
 ///
 /// - `block`: Block number to get. Each block has [BLOCK_SIZE] posts
 /// - `date`: JSON formatted date, from where to start the query
@@ -56,15 +59,24 @@ pub async fn get_posts_from(
     block:usize,
     date: ApiDate,
     posts_collection: &State<Collection<Post>>,
+    user_collection: &State<Collection<User>>,
 ) -> ApiResult<Json<Vec<ApiPostResponse>>> {
+    // Posts reference their author by id
+    let user = locate_user(&alias, user_collection).await?;
+    // Posts from suspended, banned or deleted users are hidden
+    if !user.status().is_active() || user.pending_deletion() {
+        return Ok(Json(Vec::new()));
+    }
     let date = date.extract();
     let query = vec![
         // Look for posts from this author before eq the given date that are
         // public
         doc! { "$match": {
-            POSTS_AUTHOR: alias,
+            POSTS_AUTHOR_ID: user.id(),
             POSTS_CREATION_DATE: { "$lte": date },
-            POSTS_VISIBILITY: Visibility::Public
+            POSTS_VISIBILITY: Visibility::Public,
+            // Posts created before transcoding existed don't have the field
+            POSTS_PROCESSING: { "$ne": true }
         }},
         // Sort descending
         doc! { "$sort": { POSTS_CREATION_DATE : -1 } },
@@ -85,8 +97,9 @@ pub async fn get_posts_from(
 
 /// # AUTH! `GET /api/users/<id>/posts?private&block=<usize>&date=<string>`
 /// Returns a list of private posts from the given user. The user must be the
-/// same user that is authenticated. The method receives the following query
-/// parameters:
+/// same user that is authenticated. Posts whose audio is still being
+/// [transcoded](crate::api::media::transcode) are included. The method receives the
+/// following query parameters:
 ///
 /// - `block`: Block number to get. Each block has [BLOCK_SIZE] posts
 /// - `date`: JSON formatted date, from where to start the query
@@ -114,6 +127,7 @@ pub async fn get_posts_from(
 /// | ---- | ----------- |
 /// | 400 | Bad request |
 /// | 401 | Unauthorised |
+/// | 403 | Missing scope |
 /// | 404 | User doesn't exist |
 /// | 500 | Couldn't connect to database |
 #[get("/<alias>/posts?private&<block>&<date>")]
@@ -123,8 +137,11 @@ pub async fn get_private_posts_from(
     block: usize,
     date: ApiDate,
     posts_collection: &State<Collection<Post>>,
+    user_collection: &State<Collection<User>>,
 ) -> ApiResult<Json<Vec<ApiPostResponse>>> {
-    if alias != *token.alias() {
+    token.require(Scope::ReadPrivate)?;
+    let user = locate_user(&alias, user_collection).await?;
+    if user.id() != Some(token.user_id()) {
         return Err(ApiError::Unauthorized("You are not the owner"));
     }
     let date = date.extract();
@@ -132,7 +149,7 @@ pub async fn get_private_posts_from(
         // Look for posts from this author before eq the given date that are
         // public
         doc! { "$match": {
-            POSTS_AUTHOR: alias,
+            POSTS_AUTHOR_ID: token.user_id(),
             POSTS_CREATION_DATE: { "$lte": date },
             POSTS_VISIBILITY: Visibility::Private
         }},
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::access_token::{AccessToken, Scope};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewAccessToken<'a> {
+    pub name: &'a str,
+    pub scopes: Vec<&'a str>,
+    // days
+    pub expires_in: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublicAccessTokenData {
+    id: Option<String>,
+    name: String,
+    scopes: Vec<Scope>,
+    date: String,
+    last_used: Option<String>,
+    expires: Option<String>,
+}
+
+impl From<AccessToken> for PublicAccessTokenData {
+    fn from(access_token: AccessToken) -> Self {
+        PublicAccessTokenData {
+            id: access_token.id().map(|x| x.to_string()),
+            name: access_token.name().to_string(),
+            scopes: access_token.scopes().clone(),
+            date: access_token.date().to_string(),
+            last_used: access_token.last_used().map(|x| x.to_string()),
+            expires: access_token.expires().map(|x| x.to_string()),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::State;
+
+use crate::api::data::ObjectIdWrapper;
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::{ACCESS_TOKEN_ID, ACCESS_TOKEN_USER_ID};
+use crate::mongo::access_token::{AccessToken, Scope};
+
+/// # AUTH! `DELETE /api/users/tokens/<id>`
+/// Revokes a personal access token. It stops working immediately. Requires
+/// the `account` scope
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | `id` isn't correctly formated |
+/// | 403 | Missing scope |
+/// | 404 | Token not found |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `DELETE /api/users/tokens/6138ae1329e3d1d8a3c6a0f2`
+#[delete("/<id>")]
+pub async fn delete_access_token(
+    id: ObjectIdWrapper,
+    token: TokenClaims,
+    access_token_collection: &State<Collection<AccessToken>>,
+) -> ApiResult<()> {
+    token.require(Scope::Account)?;
+    let filter = doc! { ACCESS_TOKEN_ID: id.extract(), ACCESS_TOKEN_USER_ID: token.user_id() };
+    let result = access_token_collection.delete_one(filter, None).await?;
+    if result.deleted_count == 0 {
+        Err(ApiError::NotFound("Token"))
+    } else {
+        Ok(())
+    }
+}
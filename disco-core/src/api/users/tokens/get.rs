@@ -0,0 +1,77 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::futures::StreamExt;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::api::result::ApiResult;
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::tokens::data::PublicAccessTokenData;
+use crate::api::ACCESS_TOKEN_USER_ID;
+use crate::mongo::access_token::{AccessToken, Scope};
+
+/// # AUTH! `GET /api/users/tokens`
+/// Returns the personal access tokens of the user. Token secrets are never
+/// returned. Requires the `account` scope
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// [{
+///     "id": String,
+///     "name": String,
+///     "scopes": [String],
+///     "date": String,
+///     "last_used": String,  // Optional
+///     "expires": String     // Optional
+/// },
+///
+/// ...]
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 403 | Missing scope |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `GET /api/users/tokens`
+///
+/// ```json
+/// [{
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
+///     "name": "backup script",
+///     "scopes": ["read:private"],
+///     "date": "2021-09-08 12:36:51.077 UTC",
+///     "last_used": "2021-09-08 12:40:12.318 UTC",
+///     "expires": null
+/// }]
+/// ```
+#[get("/")]
+pub async fn get_access_tokens(
+    access_token_collection: &State<Collection<AccessToken>>,
+    token: TokenClaims,
+) -> ApiResult<Json<Vec<PublicAccessTokenData>>> {
+    token.require(Scope::Account)?;
+    let filter = doc! { ACCESS_TOKEN_USER_ID: token.user_id() };
+    let mut cursor = access_token_collection.find(filter, None).await?;
+
+    let mut vec = Vec::new();
+    while let Some(res) = cursor.next().await {
+        let access_token = res?;
+        if !access_token.is_expired() {
+            vec.push(PublicAccessTokenData::from(access_token));
+        }
+    }
+    Ok(Json(vec))
+}
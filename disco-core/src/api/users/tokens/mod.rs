@@ -0,0 +1,53 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+
+use crate::api::{
+    ACCESS_TOKEN_ID, ACCESS_TOKEN_LAST_USED, ACCESS_TOKEN_TOKEN, USER_DELETE_AFTER, USER_ID,
+};
+use crate::mongo::access_token::AccessToken;
+use crate::mongo::token;
+use crate::mongo::user::User;
+
+/// Datastructures for serializing and deserializing data
+mod data;
+/// DELETE /api/users/tokens
+pub mod delete;
+/// GET /api/users/tokens
+pub mod get;
+/// POST /api/users/tokens
+pub mod post;
+
+/// Longest name allowed for an access token
+const ACCESS_TOKEN_NAME_LENGTH: usize = 64;
+
+/// Longest lifetime (days) allowed for an access token
+const ACCESS_TOKEN_MAX_DAYS: i64 = 365 * 10;
+
+// helper functions
+
+/// Finds a valid access token by its secret and updates its last use date.
+/// The owner is returned too, so its status can be checked. Tokens of users
+/// that are pending deletion aren't valid
+pub async fn find_access_token(
+    access_token_collection: &Collection<AccessToken>,
+    user_collection: &Collection<User>,
+    secret: &str,
+) -> mongodb::error::Result<Option<(AccessToken, User)>> {
+    let filter = doc! { ACCESS_TOKEN_TOKEN: token::hash(secret) };
+    let access_token = match access_token_collection.find_one(filter, None).await? {
+        Some(x) if !x.is_expired() => x,
+        _ => return Ok(None),
+    };
+    let filter = doc! {
+        USER_ID: access_token.user_id(),
+        USER_DELETE_AFTER: { "$exists": false }
+    };
+    let user = match user_collection.find_one(filter, None).await? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let filter = doc! { ACCESS_TOKEN_ID: access_token.id() };
+    let update = doc! { "$set": { ACCESS_TOKEN_LAST_USED: mongodb::bson::DateTime::now() } };
+    access_token_collection.update_one(filter, update, None).await?;
+    Ok(Some((access_token, user)))
+}
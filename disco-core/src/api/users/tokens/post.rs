@@ -0,0 +1,127 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::response::status::Created;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::{Json, Value};
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::api::users::tokens::data::NewAccessToken;
+use crate::api::users::tokens::{ACCESS_TOKEN_MAX_DAYS, ACCESS_TOKEN_NAME_LENGTH};
+use crate::api::ACCESS_TOKEN_USER_ID;
+use crate::mongo::access_token::{AccessToken, Scope, MAX_ACCESS_TOKENS};
+
+/// # AUTH! `POST /api/users/tokens`
+/// Creates a new personal access token. Access tokens can be used instead of
+/// the access token returned by
+/// [login](crate::api::users::auth::post::login_alias()), and only grant
+/// the chosen scopes. Requires the `account` scope
+///
+/// ```json
+/// {
+///     "name": String,
+///     "scopes": [String],
+///     "expires_in": i64       // Optional. Days
+/// }
+/// ```
+///
+/// | Scope | Description |
+/// | ----- | ----------- |
+/// | `read:private` | Read private posts, media and profile data |
+/// | `posts:write` | Create, edit and delete posts |
+/// | `media:upload` | Upload media files |
+/// | `profile:write` | Update the user description, email and avatar |
+///
+/// The `account` scope can't be granted. The token **won't be shown again**
+///
+/// # Returns
+/// ## Ok (201)
+///
+/// ```json
+/// {
+///     "id": String,
+///     "token": String
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Invalid name, scope or expiration. Too many tokens |
+/// | 403 | Missing scope |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/tokens`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "name": "backup script",
+///     "scopes": ["read:private"],
+///     "expires_in": 90
+/// }
+/// ```
+///
+/// ## Response
+///
+/// ```json
+/// {
+///     "id": "6138ae1329e3d1d8a3c6a0f2",
+///     "token": "fdp_3q2-7wFhJ8u1aVxqkQm0bZ4tYw9cN5rLkV2sHf6pXgE"
+/// }
+/// ```
+#[post("/", format = "json", data = "<info>")]
+pub async fn new_access_token(
+    info: Json<NewAccessToken<'_>>,
+    access_token_collection: &State<Collection<AccessToken>>,
+    token: TokenClaims,
+) -> ApiResult<Created<Value>> {
+    token.require(Scope::Account)?;
+    let name = info.name.trim();
+    if name.is_empty() || name.chars().count() > ACCESS_TOKEN_NAME_LENGTH {
+        return Err(ApiError::BadRequest("Invalid name"));
+    }
+    let mut scopes = Vec::with_capacity(info.scopes.len());
+    for scope in info.scopes.iter() {
+        let scope = scope
+            .parse::<Scope>()
+            .ok()
+            .filter(|x| x.grantable())
+            .ok_or(ApiError::BadRequest("Invalid scope"))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let ttl = match info.expires_in {
+        Some(days) if !(1..=ACCESS_TOKEN_MAX_DAYS).contains(&days) => {
+            return Err(ApiError::BadRequest("Invalid expiration"))
+        }
+        Some(days) => Some(days * 3600 * 24),
+        None => None,
+    };
+
+    let filter = doc! { ACCESS_TOKEN_USER_ID: token.user_id() };
+    let count = access_token_collection.count_documents(filter, None).await?;
+    if count >= MAX_ACCESS_TOKENS {
+        return Err(ApiError::BadRequest("Too many tokens"));
+    }
+
+    let (access_token, secret) =
+        AccessToken::new(name.to_string(), token.user_id(), scopes, ttl);
+    let inserted = access_token_collection.insert_one(&access_token, None).await?;
+    // Unwrap is safe. Inserted tokens always have an ObjectId
+    let id = inserted.inserted_id.as_object_id().unwrap();
+    Ok(Created::new(format!("/api/users/tokens/{}", id))
+        .body(json!({ "id": id.to_string(), "token": secret })))
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode<'a> {
+    pub code: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnroll<'a> {
+    pub password: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpDisable<'a> {
+    pub password: &'a str,
+    pub code: Option<&'a str>,
+    pub recovery_code: Option<&'a str>,
+}
@@ -0,0 +1,63 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::{USER_ID, USER_TOTP_ENABLED, USER_TOTP_LAST_STEP, USER_TOTP_RECOVERY_CODES};
+use crate::mongo::token;
+use crate::mongo::user::User;
+
+/// Datastructures for serializing and deserializing data
+mod data;
+/// POST /api/users/totp
+pub mod post;
+
+// helper functions
+
+/// Checks a TOTP code for a user with two-factor authentication enabled. The
+/// code is consumed, so it can't be used again
+pub async fn use_totp_code(
+    user: &User,
+    code: &str,
+    user_collection: &Collection<User>,
+) -> ApiResult<bool> {
+    let totp = user
+        .totp()
+        .as_ref()
+        .filter(|x| x.enabled())
+        .ok_or(ApiError::BadRequest("Two-factor authentication is disabled"))?;
+    let step = match totp.verify(user.alias(), code) {
+        Some(x) => x,
+        None => return Ok(false),
+    };
+    // Filtering by the last step prevents two concurrent logins from using
+    // the same code
+    let filter = doc! {
+        USER_ID: user.id(),
+        USER_TOTP_ENABLED: true,
+        "$or": [
+            { USER_TOTP_LAST_STEP: null },
+            { USER_TOTP_LAST_STEP: { "$lt": step } }
+        ]
+    };
+    let update = doc! { "$set": { USER_TOTP_LAST_STEP: step } };
+    let result = user_collection.update_one(filter, update, None).await?;
+    Ok(result.modified_count == 1)
+}
+
+/// Checks a recovery code for a user with two-factor authentication enabled.
+/// The code is removed from the user
+pub async fn use_recovery_code(
+    user: &User,
+    code: &str,
+    user_collection: &Collection<User>,
+) -> ApiResult<bool> {
+    let hash = token::hash(code.trim().to_lowercase().as_str());
+    let filter = doc! {
+        USER_ID: user.id(),
+        USER_TOTP_ENABLED: true,
+        USER_TOTP_RECOVERY_CODES: &hash
+    };
+    let update = doc! { "$pull": { USER_TOTP_RECOVERY_CODES: &hash } };
+    let result = user_collection.update_one(filter, update, None).await?;
+    Ok(result.modified_count == 1)
+}
@@ -0,0 +1,271 @@
+use mongodb::bson::doc;
+use mongodb::Collection;
+use rocket::http::Status;
+use rocket::serde::json::serde_json::json;
+use rocket::serde::json::{Json, Value};
+use rocket::State;
+
+use crate::api::result::{ApiError, ApiResult};
+use crate::api::users::auth::claims::TokenClaims;
+use crate::mongo::access_token::Scope;
+use crate::api::users::locate_user_by_id;
+use crate::api::users::totp::data::{TotpCode, TotpDisable, TotpEnroll};
+use crate::api::users::totp::{use_recovery_code, use_totp_code};
+use crate::api::{
+    USER_ID, USER_TOTP, USER_TOTP_ENABLED, USER_TOTP_LAST_STEP, USER_TOTP_RECOVERY_CODES,
+    USER_TOTP_SECRET,
+};
+use crate::mongo::user::totp::generate_recovery_codes;
+use crate::mongo::user::{Totp, User};
+
+/// # AUTH! `POST /api/users/totp/enroll`
+/// Generates a new TOTP secret for the user. Two-factor authentication stays
+/// disabled until the secret is confirmed with
+/// [crate::api::users::totp::post::confirm()]. Enrolling again replaces any
+/// unconfirmed secret. The user password is required
+///
+/// ```json
+/// {
+///     "password": String
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "secret": String,   // Base32
+///     "uri": String       // otpauth:// URI, for QR codes
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 401 | Password doesn't match |
+/// | 403 | Missing scope |
+/// | 404 | User doesn't exist |
+/// | 409 | Two-factor authentication already enabled |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/totp/enroll`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "password": "i-love-rvst"
+/// }
+/// ```
+///
+/// ## Response
+///
+/// ```json
+/// {
+///     "secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+///     "uri": "otpauth://totp/fuzzy-disco:Altair-Bueno?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=fuzzy-disco"
+/// }
+/// ```
+#[post("/enroll", format = "json", data = "<info>")]
+pub async fn enroll(
+    info: Json<TotpEnroll<'_>>,
+    user_collection: &State<Collection<User>>,
+    token: TokenClaims,
+) -> ApiResult<Value> {
+    token.require(Scope::Account)?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
+    match user.password().verify(info.password).await {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::Unauthorized("Invalid password")),
+        Err(_) => return Err(ApiError::InternalServerError("Couldn't hash password")),
+    }
+    let totp = Totp::new();
+    let uri = totp
+        .uri(user.alias())
+        .ok_or(ApiError::InternalServerError("Couldn't generate TOTP"))?;
+    let filter = doc! { USER_ID: token.user_id(), USER_TOTP_ENABLED: { "$ne": true } };
+    let update = doc! { "$set": { USER_TOTP: totp.clone() } };
+    let result = user_collection.update_one(filter, update, None).await?;
+    if result.matched_count == 0 {
+        // The user already has a confirmed secret
+        return Err(ApiError::Other(
+            "Two-factor authentication already enabled",
+            Status::Conflict,
+        ));
+    }
+    Ok(json!({ "secret": totp.secret(), "uri": uri }))
+}
+
+/// # AUTH! `POST /api/users/totp/confirm`
+/// Enables two-factor authentication using a code generated with the secret
+/// returned by [crate::api::users::totp::post::enroll()]
+///
+/// ```json
+/// {
+///     "code": String
+/// }
+/// ```
+///
+/// A list of one time recovery codes is returned. They can be used instead of
+/// a TOTP code if the user loses their device, and **won't be shown again**
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ```json
+/// {
+///     "recovery_codes": [String]
+/// }
+/// ```
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | No pending TOTP secret |
+/// | 401 | Invalid code |
+/// | 403 | Missing scope |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/totp/confirm`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "code": "123456"
+/// }
+/// ```
+///
+/// ## Response
+///
+/// ```json
+/// {
+///     "recovery_codes": ["mfrgg2lb", "nbswy3dp", "..."]
+/// }
+/// ```
+#[post("/confirm", format = "json", data = "<info>")]
+pub async fn confirm(
+    info: Json<TotpCode<'_>>,
+    user_collection: &State<Collection<User>>,
+    token: TokenClaims,
+) -> ApiResult<Value> {
+    token.require(Scope::Account)?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
+    let totp = user
+        .totp()
+        .as_ref()
+        .filter(|x| !x.enabled())
+        .ok_or(ApiError::BadRequest("No pending TOTP secret"))?;
+    let step = totp
+        .verify(user.alias(), info.code)
+        .ok_or(ApiError::Unauthorized("Invalid code"))?;
+    let (recovery_codes, hashes) = generate_recovery_codes();
+    // Filtering by the secret prevents enabling a secret that has been
+    // replaced by another enrollment
+    let filter = doc! { USER_ID: token.user_id(), USER_TOTP_SECRET: totp.secret() };
+    let update = doc! {
+        "$set": {
+            USER_TOTP_ENABLED: true,
+            USER_TOTP_RECOVERY_CODES: hashes,
+            USER_TOTP_LAST_STEP: step
+        }
+    };
+    let result = user_collection.update_one(filter, update, None).await?;
+    if result.modified_count == 0 {
+        return Err(ApiError::BadRequest("No pending TOTP secret"));
+    }
+    Ok(json!({ "recovery_codes": recovery_codes }))
+}
+
+/// # AUTH! `POST /api/users/totp/disable`
+/// Disables two-factor authentication. The user password is required. If
+/// two-factor authentication is enabled, a TOTP code or a recovery code is
+/// required too, so a stolen session can't remove the second factor
+///
+/// ```json
+/// {
+///     "password": String,
+///     "code": String,             // Optional. TOTP code
+///     "recovery_code": String     // Optional. Used if `code` is missing
+/// }
+/// ```
+///
+/// # Returns
+/// ## Ok (200)
+///
+/// ## Err
+/// ```json
+/// {
+///     "status": String,
+///     "message": String
+/// }
+/// ```
+///
+/// | Code | Description |
+/// | -----| ----------- |
+/// | 400 | Missing code |
+/// | 401 | Password or code doesn't match |
+/// | 403 | Missing scope |
+/// | 404 | User doesn't exist |
+/// | 500 | Couldn't connect to database |
+///
+/// # Example
+///
+/// `POST /api/users/totp/disable`
+///
+/// ## Body payload
+///
+/// ```json
+/// {
+///     "password": "i-love-rvst",
+///     "code": "123456"
+/// }
+/// ```
+#[post("/disable", format = "json", data = "<info>")]
+pub async fn disable(
+    info: Json<TotpDisable<'_>>,
+    user_collection: &State<Collection<User>>,
+    token: TokenClaims,
+) -> ApiResult<()> {
+    token.require(Scope::Account)?;
+    let user = locate_user_by_id(token.user_id(), user_collection).await?;
+    match user.password().verify(info.password).await {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::Unauthorized("Invalid password")),
+        Err(_) => return Err(ApiError::InternalServerError("Couldn't hash password")),
+    }
+    if user.totp_enabled() {
+        let valid = match (info.code, info.recovery_code) {
+            (Some(code), _) => use_totp_code(&user, code, user_collection).await?,
+            (None, Some(code)) => use_recovery_code(&user, code, user_collection).await?,
+            (None, None) => return Err(ApiError::BadRequest("Missing code")),
+        };
+        if !valid {
+            return Err(ApiError::Unauthorized("Invalid code"));
+        }
+    }
+    let filter = doc! { USER_ID: token.user_id() };
+    let update = doc! { "$unset": { USER_TOTP: "" } };
+    user_collection.update_one(filter, update, None).await?;
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use crate::mongo::user::policy::{PASSWORD_MAX_LENGTH, PASSWORD_MIN_LENGTH};
+use crate::mongo::user::PasswordPolicy;
+
+/// Default grace period (days) before deleted accounts are purged
+const ACCOUNT_DELETION_DAYS: i64 = 30;
+
+/// Runtime settings read from the environment on startup. See
+/// `init_config` for the list of variables
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Public URL of the server. Used to build the links sent to users
+    pub public_url: String,
+    /// Block login for users that haven't verified their email
+    pub require_verified_email: bool,
+    /// Days an account stays pending deletion before it is purged
+    pub account_deletion_days: i64,
+    /// Rules for new passwords and their hashes
+    pub password_policy: PasswordPolicy,
+    /// ImageMagick compatible program used to create
+    /// [image renditions](crate::api::media::process::create_renditions)
+    pub image_converter: String,
+    /// FFmpeg compatible program used by the
+    /// [transcoding job](crate::api::media::transcode)
+    pub audio_transcoder: String,
+    /// FFprobe compatible program used to inspect transcoded audio
+    pub audio_probe: String,
+    /// Redirect media requests to presigned URLs on the
+    /// [store](crate::storage::MediaStore), if it supports them
+    pub media_redirect: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        Config {
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:8000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            require_verified_email: env_flag("REQUIRE_VERIFIED_EMAIL"),
+            account_deletion_days: std::env::var("ACCOUNT_DELETION_DAYS")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .filter(|x| *x >= 0)
+                .unwrap_or(ACCOUNT_DELETION_DAYS),
+            password_policy: password_policy_from_env(),
+            image_converter: std::env::var("IMAGE_CONVERTER")
+                .unwrap_or_else(|_| "convert".to_string()),
+            audio_transcoder: std::env::var("FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string()),
+            audio_probe: std::env::var("FFPROBE").unwrap_or_else(|_| "ffprobe".to_string()),
+            media_redirect: env_flag("MEDIA_REDIRECT"),
+        }
+    }
+}
+
+/// Reads the password policy. Invalid values fall back to the defaults
+fn password_policy_from_env() -> PasswordPolicy {
+    let default = PasswordPolicy::default();
+    let min_length = env_number("PASSWORD_MIN_LENGTH")
+        .filter(|x| *x > 0)
+        .unwrap_or(PASSWORD_MIN_LENGTH);
+    let max_length = env_number("PASSWORD_MAX_LENGTH")
+        .filter(|x| *x >= min_length)
+        .unwrap_or_else(|| PASSWORD_MAX_LENGTH.max(min_length));
+    PasswordPolicy {
+        min_length,
+        max_length,
+        cost: env_number("BCRYPT_COST")
+            .filter(|x| (4..=31).contains(x))
+            .unwrap_or(default.cost),
+        breach_list: std::env::var("PASSWORD_BREACH_LIST").ok().map(PathBuf::from),
+    }
+}
+
+/// Reads a numeric environment variable. Missing or invalid variables are
+/// `None`
+fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|x| x.parse().ok())
+}
+
+/// Reads a boolean environment variable. Missing variables are `false`
+fn env_flag(name: &str) -> bool {
+    matches!(
+        std::env::var(name).map(|x| x.to_lowercase()).as_deref(),
+        Ok("1") | Ok("true") | Ok("yes")
+    )
+}
@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use rocket::futures::StreamExt;
+use rocket::tokio::time::{sleep, Duration, Instant};
+use thiserror::Error;
+
+use crate::api::media::post::FILE_TTL;
+use crate::api::media::{delete_media, media_key};
+use crate::api::result::ApiError;
+use crate::mongo::media::Status;
+use crate::storage::{MediaStore, StoreError};
+
+/// Time (seconds) between sweeps for expired media
+#[cfg(debug_assertions)]
+const GC_INTERVAL: u64 = 60;
+
+#[cfg(not(debug_assertions))]
+const GC_INTERVAL: u64 = 600;
+
+/// Time (seconds) between reconciliations of the store with the database.
+/// Every object on the store is listed, so they are less frequent than sweeps
+#[cfg(debug_assertions)]
+const RECONCILE_INTERVAL: u64 = 300;
+
+#[cfg(not(debug_assertions))]
+const RECONCILE_INTERVAL: u64 = 21600;
+
+/// Media ids looked up on the database at once
+const LOOKUP_BATCH: usize = 1000;
+
+/// Errors produced while collecting media. The sweep is retried on the next
+/// run
+#[derive(Error, Debug)]
+pub enum GcError {
+    #[error("Couldn't connect to database: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("Couldn't access store: {0}")]
+    Store(#[from] StoreError),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+}
+
+/// Starts the background job that removes the media that wasn't claimed in
+/// time. See [upload](crate::api::media::post::upload)
+///
+/// Media that is still waiting to be claimed [FILE_TTL] * 2 seconds after the
+/// upload is removed, together with its files. Every [RECONCILE_INTERVAL]
+/// seconds the store is compared with the database too
+///
+/// - Objects of media that doesn't have a document are removed
+/// - Documents whose original file is missing are logged so they can be fixed
+///   by hand. They may be used by posts and avatars, so they are kept
+///
+/// Objects are never removed if the database doesn't have any media, as the
+/// store is probably shared with another database
+///
+/// # Resuming
+///
+/// Nothing is kept in memory between runs. The document is removed before
+/// its files, so the media can't be claimed while it is being removed. If the
+/// server stops in the middle, the files left behind are removed by the next
+/// reconciliation
+pub fn spawn(db: Database, store: Arc<dyn MediaStore>) {
+    rocket::tokio::spawn(async move {
+        let mut reconciled: Option<Instant> = None;
+        loop {
+            if let Err(e) = expire_media(&db, store.as_ref()).await {
+                println!("[GC]: {}", e);
+            }
+            let due = reconciled
+                .map(|x| x.elapsed() >= Duration::from_secs(RECONCILE_INTERVAL))
+                .unwrap_or(true);
+            if due {
+                match reconcile(&db, store.as_ref()).await {
+                    Ok(_) => reconciled = Some(Instant::now()),
+                    Err(e) => println!("[GC]: {}", e),
+                }
+            }
+            sleep(Duration::from_secs(GC_INTERVAL)).await;
+        }
+    });
+}
+
+/// Oldest id media may have to be left alone. Anything uploaded before may
+/// be collected
+fn cutoff() -> ObjectId {
+    let now = mongodb::bson::DateTime::now().timestamp_millis() / 1000;
+    let seconds = (now - FILE_TTL as i64 * 2).max(0) as u32;
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+/// Removes the media that is still waiting to be claimed after its TTL.
+/// Media that can't be removed is logged and doesn't hold back the rest.
+/// Files left behind are removed by the next reconciliation
+async fn expire_media(db: &Database, store: &dyn MediaStore) -> Result<(), GcError> {
+    let media = db.collection::<Document>("Media");
+    let filter = doc! { "status": Status::Waiting, "_id": { "$lt": cutoff() } };
+    let ids = media.distinct("_id", filter, None).await?;
+    let mut expired = 0;
+    for oid in ids.iter().filter_map(|x| x.as_object_id()) {
+        match expire(&media, store, oid).await {
+            Ok(true) => expired += 1,
+            Ok(false) => {}
+            Err(e) => println!("[GC]: Couldn't remove media {}: {}", oid, e),
+        }
+    }
+    if expired > 0 {
+        println!("[GC]: Removed {} expired media", expired);
+    }
+    Ok(())
+}
+
+/// Removes a media document and its files, unless it has been claimed since
+async fn expire(
+    media: &Collection<Document>,
+    store: &dyn MediaStore,
+    oid: ObjectId,
+) -> Result<bool, GcError> {
+    let filter = doc! { "_id": oid, "status": Status::Waiting };
+    if media.delete_one(filter, None).await?.deleted_count == 1 {
+        delete_media(store, &oid).await?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Removes the objects that don't belong to any media and reports the media
+/// whose original file is missing
+async fn reconcile(db: &Database, store: &dyn MediaStore) -> Result<(), GcError> {
+    let cutoff = cutoff();
+    let mut objects: HashMap<ObjectId, Vec<String>> = HashMap::new();
+    for key in store.list().await? {
+        match key_media_id(&key) {
+            // Recent uploads may not have their document yet
+            Some(oid) if oid < cutoff => objects.entry(oid).or_default().push(key),
+            _ => {}
+        }
+    }
+    let stored: HashSet<ObjectId> = objects
+        .iter()
+        .filter(|(oid, keys)| keys.contains(&media_key(oid)))
+        .map(|(oid, _)| *oid)
+        .collect();
+
+    let media = db.collection::<Document>("Media");
+    let ids: Vec<ObjectId> = objects.keys().copied().collect();
+    let mut known = HashSet::new();
+    for batch in ids.chunks(LOOKUP_BATCH) {
+        let found = media
+            .distinct("_id", doc! { "_id": { "$in": batch } }, None)
+            .await?;
+        known.extend(found.iter().filter_map(|x| x.as_object_id()));
+    }
+    let orphans: Vec<&String> = objects
+        .iter()
+        .filter(|(oid, _)| !known.contains(oid))
+        .flat_map(|(_, keys)| keys)
+        .collect();
+    if !orphans.is_empty() && media.estimated_document_count(None).await? == 0 {
+        println!("[GC]: There isn't any media. Skipping {} orphan objects", orphans.len());
+    } else {
+        for key in orphans.iter() {
+            store.delete(key).await?;
+        }
+        if !orphans.is_empty() {
+            println!("[GC]: Removed {} orphan objects", orphans.len());
+        }
+    }
+
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+    let mut cursor = media.find(doc! { "_id": { "$lt": cutoff } }, options).await?;
+    let mut missing = 0;
+    while let Some(next) = cursor.next().await {
+        let next = next?;
+        match next.get_object_id("_id") {
+            Ok(oid) if !stored.contains(&oid) => {
+                println!("[GC]: Media {} doesn't have a file. Fix it by hand", oid);
+                missing += 1;
+            }
+            _ => {}
+        }
+    }
+    if missing > 0 {
+        println!("[GC]: {} media documents don't have a file", missing);
+    }
+    Ok(())
+}
+
+/// Id of the media an object belongs to. Objects are named after the media
+/// id, such as `<id>.blob` or `<id>.small.jpg`. Export archives aren't media
+fn key_media_id(key: &str) -> Option<ObjectId> {
+    if key.starts_with("exports/") {
+        return None;
+    }
+    let name = key.rsplit('/').next()?;
+    name.split('.').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::key_media_id;
+    use crate::api::media::{media_key, transcoded_key};
+    use crate::mongo::export::export_key;
+
+    #[test]
+    pub fn object_media_id() {
+        let oid: ObjectId = "6138ae1329e3d1d8a3c6a0f2".parse().unwrap();
+        assert_eq!(key_media_id(&media_key(&oid)), Some(oid));
+        assert_eq!(key_media_id(&transcoded_key(&oid)), Some(oid));
+        assert_eq!(key_media_id(&format!("{}.part", media_key(&oid))), Some(oid));
+        assert_eq!(key_media_id(&export_key(&oid)), None);
+        assert_eq!(key_media_id("61/38/notes.txt"), None);
+        assert_eq!(key_media_id(""), None);
+    }
+}
@@ -1,13 +1,31 @@
+use std::sync::Arc;
+
 use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use rocket::futures::StreamExt;
 use mongodb::Client as MongoClient;
 use mongodb::Database as MongoDatabase;
 use redis::RedisResult;
 use redis::aio::MultiplexedConnection;
 
+use crate::api::users::auth::keys::{KeyError, Keyring};
+use crate::config::Config;
+use crate::mongo::user::Role;
+use crate::mailer::{MailError, Mailer, MailboxMailer, SmtpMailer};
+use crate::oidc::{OidcError, Providers};
+use crate::storage::{FileStore, MediaStore, S3Store, StoreError};
+
+/// Version of the database schema this build expects. See [migrate]
+const SCHEMA_VERSION: i32 = 2;
+
 /// Inits Mongodb. This includes:
 ///
 /// - Reading the environment variable `MONGODB_URI`
-/// - Creating indexes for the different collections
+/// - Running the pending schema migrations. See [migrate]
+/// - Creating indexes for the different collections. Expired sessions are
+///   removed by a TTL index
+/// - Promoting the users listed on `ADMIN_ALIASES` to admins. See
+///   [promote_admins]
 /// - Creating a mongodb client
 /// - Creating a mongodb database
 ///
@@ -16,6 +34,9 @@ use redis::aio::MultiplexedConnection;
 /// This environment variable should point to a well formatted Mongodb URL,
 /// otherwise it will default to `mongodb:://127.0.0.1/`. If the server cannot
 /// connect to the Mongo instance, it will exit. showing an error message
+///
+/// Some operations, such as changing a user alias, use transactions. They
+/// only work when Mongo runs as a replica set, even if it has a single member
 pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClient)> {
     let url = std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://127.0.0.1/".to_string());
     let options = mongodb::options::ClientOptions::parse(&url).await?;
@@ -23,14 +44,15 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
     println!("[MONGO]: Expecting mongo on {}", url);
     let client = MongoClient::with_options(options)?;
     let db = client.database("fuzzy-disco");
+    migrate(&db).await?;
     let index_response = db
         .run_command(
             doc! {
                 "createIndexes": "Users",
                 "indexes": [
                     {
-                        "key": { "alias": 1 },
-                        "name": "alias",
+                        "key": { "alias_normalized": 1 },
+                        "name": "alias_normalized",
                         "unique": true
                     },
                     /*{
@@ -38,9 +60,20 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
                         "name": "search",
                     },*/
                     {
-                        "key": { "email": 1 },
-                        "name": "email",
+                        "key": { "email_normalized": 1 },
+                        "name": "email_normalized",
+                        "unique": true
+                    },
+                    {
+                        "key": { "status.state": 1 },
+                        "name": "status",
                         "unique": false
+                    },
+                    {
+                        "key": { "delete_after": 1 },
+                        "name": "delete_after",
+                        "unique": false,
+                        "sparse": true
                     }
                 ]
             },
@@ -55,8 +88,146 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
                 "createIndexes": "Sessions",
                 "indexes": [
                     {
-                        "key": { "sub": 1 },
-                        "name": "sub",
+                        "key": { "user_id": 1 },
+                        "name": "user_id",
+                        "unique": false
+                    },
+                    {
+                        "key": { "token": 1 },
+                        "name": "token",
+                        "unique": true
+                    },
+                    {
+                        "key": { "rotated": 1 },
+                        "name": "rotated",
+                        "unique": false
+                    },
+                    {
+                        "key": { "expires": 1 },
+                        "name": "expires",
+                        "expireAfterSeconds": 0
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    // Sessions created before refresh token rotation can't expire, and the
+    // ones that used their id as refresh token can be guessed. Close them
+    let delete_response = db
+        .collection::<mongodb::bson::Document>("Sessions")
+        .delete_many(
+            doc! {
+                "$or": [
+                    { "expires": { "$exists": false } },
+                    { "token": { "$not": { "$type": "string" } } }
+                ]
+            },
+            None,
+        )
+        .await?;
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Closed {} legacy sessions", delete_response.deleted_count);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "Tickets",
+                "indexes": [
+                    {
+                        "key": { "token": 1 },
+                        "name": "token",
+                        "unique": true
+                    },
+                    {
+                        "key": { "expires": 1 },
+                        "name": "expires",
+                        "expireAfterSeconds": 0
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "AccessTokens",
+                "indexes": [
+                    {
+                        "key": { "token": 1 },
+                        "name": "token",
+                        "unique": true
+                    },
+                    {
+                        "key": { "user_id": 1 },
+                        "name": "user_id",
+                        "unique": false
+                    },
+                    {
+                        "key": { "expires": 1 },
+                        "name": "expires",
+                        "expireAfterSeconds": 0
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "AliasReservations",
+                "indexes": [
+                    {
+                        "key": { "alias_normalized": 1 },
+                        "name": "alias_normalized",
+                        "unique": true
+                    },
+                    {
+                        "key": { "user_id": 1 },
+                        "name": "user_id",
+                        "unique": false
+                    },
+                    {
+                        "key": { "expires": 1 },
+                        "name": "expires",
+                        "expireAfterSeconds": 0
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "Identities",
+                "indexes": [
+                    {
+                        "key": { "provider": 1, "subject": 1 },
+                        "name": "provider_subject",
+                        "unique": true
+                    },
+                    {
+                        "key": { "user_id": 1 },
+                        "name": "user_id",
                         "unique": false
                     },
                 ]
@@ -78,6 +249,69 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
                         "name": "status",
                         "unique": false
                     },
+                    {
+                        "key": { "uploaded_by": 1 },
+                        "name": "uploaded_by",
+                        "unique": false
+                    },
+                    {
+                        "key": { "processing": 1 },
+                        "name": "processing",
+                        "unique": false
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "Posts",
+                "indexes": [
+                    {
+                        "key": { "author_id": 1, "creation_date": -1 },
+                        "name": "author_id",
+                        "unique": false
+                    },
+                    {
+                        "key": { "audio": 1 },
+                        "name": "audio",
+                        "unique": false
+                    },
+                ]
+            },
+            None,
+        )
+        .await?;
+
+    #[cfg(debug_assertions)]
+    println!("[MONGO]: Index creation response {:?}", index_response);
+
+    let index_response = db
+        .run_command(
+            doc! {
+                "createIndexes": "Exports",
+                "indexes": [
+                    {
+                        "key": { "user_id": 1 },
+                        "name": "user_id",
+                        "unique": false
+                    },
+                    {
+                        "key": { "status": 1, "date": 1 },
+                        "name": "status_date",
+                        "unique": false
+                    },
+                    {
+                        "key": { "expires": 1 },
+                        "name": "expires",
+                        "unique": false
+                    },
                 ]
             },
             None,
@@ -86,6 +320,7 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
 
     #[cfg(debug_assertions)]
     println!("[MONGO]: Index creation response {:?}", index_response);
+
 /* Text indexes perform exact match on words. They are not suitable for fuzzy-disco
     let index_response = db
         .run_command(
@@ -106,9 +341,203 @@ pub async fn init_mongo_db() -> mongodb::error::Result<(MongoDatabase, MongoClie
     #[cfg(debug_assertions)]
     println!("[MONGO]: Index creation response {:?}", index_response);
 */
+    promote_admins(&db).await?;
 
     Ok((db, client))
 }
+
+/// Gives the admin role to the users listed on `ADMIN_ALIASES`, a comma
+/// separated list of aliases. Aliases are case insensitive. Admins can promote
+/// other users from `/api/admin`, so this is only needed for the first one
+async fn promote_admins(db: &MongoDatabase) -> mongodb::error::Result<()> {
+    let aliases = std::env::var("ADMIN_ALIASES").unwrap_or_default();
+    let aliases: Vec<String> = aliases
+        .split(',')
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if aliases.is_empty() {
+        return Ok(());
+    }
+    let users = db.collection::<mongodb::bson::Document>("Users");
+    let filter = doc! { "alias_normalized": { "$in": &aliases } };
+    let update = doc! { "$set": { "role": Role::Admin } };
+    let update_response = users.update_many(filter, update, None).await?;
+    println!(
+        "[MONGO]: {} of {} admins found",
+        update_response.matched_count,
+        aliases.len()
+    );
+    Ok(())
+}
+/// Brings the database schema up to [SCHEMA_VERSION]. The current version is
+/// stored on the `Migrations` collection, and each migration runs once, in
+/// order. Databases created before migrations were versioned start at `0`
+///
+/// | Version | Migration |
+/// | ------- | --------- |
+/// | 1 | [migrate_user_identities] |
+/// | 2 | [migrate_user_references] |
+async fn migrate(db: &MongoDatabase) -> mongodb::error::Result<()> {
+    let migrations = db.collection::<mongodb::bson::Document>("Migrations");
+    let filter = doc! { "_id": "schema" };
+    let current = migrations
+        .find_one(filter.clone(), None)
+        .await?
+        .and_then(|x| x.get_i32("version").ok())
+        .unwrap_or(0);
+    for version in current + 1..=SCHEMA_VERSION {
+        match version {
+            1 => migrate_user_identities(db).await?,
+            2 => migrate_user_references(db).await?,
+            _ => unreachable!("Missing migration {}", version),
+        }
+        let update = doc! {
+            "$set": { "version": version, "date": mongodb::bson::DateTime::now() }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        migrations.update_one(filter.clone(), update, options).await?;
+        println!("[MONGO]: Migrated database to version {}", version);
+    }
+    Ok(())
+}
+
+/// Replaces the aliases used to reference users with their ids. Posts keep
+/// the author alias for display and search, and get `author_id`. Media
+/// `uploaded_by` holds the id, and the rest of the documents get `user_id`
+/// instead of `user_alias`
+///
+/// Documents that reference a user that doesn't exist anymore can't be
+/// migrated. Sessions, tickets, access tokens, identities and alias
+/// reservations are deleted. Posts and media are logged so they can be fixed
+/// by hand
+async fn migrate_user_references(db: &MongoDatabase) -> mongodb::error::Result<()> {
+    // (collection, old field, new field)
+    let references = [
+        ("Media", "uploaded_by", "uploaded_by"),
+        ("Sessions", "user_alias", "user_id"),
+        ("Tickets", "user_alias", "user_id"),
+        ("AccessTokens", "user_alias", "user_id"),
+        ("Identities", "user_alias", "user_id"),
+        ("AliasReservations", "user_alias", "user_id"),
+    ];
+    let posts = db.collection::<mongodb::bson::Document>("Posts");
+    let users = db.collection::<mongodb::bson::Document>("Users");
+    let mut cursor = users.find(None, None).await?;
+    while let Some(user) = cursor.next().await {
+        let user = user?;
+        let (id, alias) = match (user.get_object_id("_id"), user.get_str("alias")) {
+            (Ok(id), Ok(alias)) => (id, alias),
+            _ => continue,
+        };
+        let filter = doc! { "author": alias, "author_id": { "$exists": false } };
+        let update = doc! { "$set": { "author_id": id } };
+        posts.update_many(filter, update, None).await?;
+        for (collection, old, new) in references {
+            let mut update = doc! { "$set": { new: id } };
+            if old != new {
+                update.insert("$unset", doc! { old: "" });
+            }
+            db.collection::<mongodb::bson::Document>(collection)
+                .update_many(doc! { old: alias }, update, None)
+                .await?;
+        }
+    }
+
+    let orphans = posts
+        .count_documents(doc! { "author_id": { "$exists": false } }, None)
+        .await?;
+    if orphans > 0 {
+        println!("[MONGO]: {} posts reference missing users. Fix them by hand", orphans);
+    }
+    for (collection, _, new) in references {
+        let collection = db.collection::<mongodb::bson::Document>(collection);
+        let filter = doc! { new: { "$not": { "$type": "objectId" } } };
+        if collection.name() == "Media" {
+            let orphans = collection.count_documents(filter, None).await?;
+            if orphans > 0 {
+                println!("[MONGO]: {} media files reference missing users. Fix them by hand", orphans);
+            }
+        } else {
+            let delete_response = collection.delete_many(filter, None).await?;
+            if delete_response.deleted_count > 0 {
+                println!(
+                    "[MONGO]: Deleted {} {} that reference missing users",
+                    delete_response.deleted_count,
+                    collection.name()
+                );
+            }
+        }
+    }
+
+    // Indexes on the old fields
+    for (collection, index) in [
+        ("Sessions", "sub"),
+        ("AccessTokens", "user_alias"),
+        ("Identities", "user_alias"),
+        ("AliasReservations", "user_alias"),
+    ] {
+        let _ = db
+            .run_command(doc! { "dropIndexes": collection, "index": index }, None)
+            .await;
+    }
+    Ok(())
+}
+
+/// Fills the normalized alias and email of users created before they were
+/// stored, and replaces the old case sensitive indexes
+///
+/// Users that share the same normalized alias or email can't be merged
+/// automatically. The oldest one keeps the normalized value and the rest get
+/// `<normalized>#<id>`, which can't be typed by anyone, so the unique indexes
+/// can be built. Those users are logged so they can be fixed by hand
+async fn migrate_user_identities(db: &MongoDatabase) -> mongodb::error::Result<()> {
+    let users = db.collection::<mongodb::bson::Document>("Users");
+    let filter = doc! {
+        "$or": [
+            { "alias_normalized": { "$exists": false } },
+            { "email_normalized": { "$exists": false } }
+        ]
+    };
+    let pipeline = vec![doc! {
+        "$set": {
+            "alias_normalized": { "$toLower": "$alias" },
+            "email_normalized": { "$toLower": { "$trim": { "input": "$email" } } }
+        }
+    }];
+    let update_response = users.update_many(filter, pipeline, None).await?;
+    if update_response.modified_count > 0 {
+        println!("[MONGO]: Normalized {} users", update_response.modified_count);
+    }
+
+    for field in ["alias_normalized", "email_normalized"] {
+        let pipeline = vec![
+            doc! { "$sort": { "creation_date": 1 } },
+            doc! { "$group": { "_id": format!("${}", field), "ids": { "$push": "$_id" } } },
+            doc! { "$match": { "ids.1": { "$exists": true } } },
+        ];
+        let mut duplicates = users.aggregate(pipeline, None).await?;
+        while let Some(duplicate) = duplicates.next().await {
+            let duplicate = duplicate?;
+            let value = duplicate.get_str("_id").unwrap_or_default().to_string();
+            let ids = duplicate.get_array("ids").cloned().unwrap_or_default();
+            for id in ids.iter().skip(1).filter_map(|x| x.as_object_id()) {
+                let update = doc! { "$set": { field: format!("{}#{}", value, id) } };
+                users.update_one(doc! { "_id": id }, update, None).await?;
+                println!("[MONGO]: User {} shares {} {}. Fix it by hand", id, field, value);
+            }
+        }
+    }
+
+    // Case sensitive indexes from previous versions
+    for index in ["alias", "email"] {
+        let _ = db
+            .run_command(doc! { "dropIndexes": "Users", "index": index }, None)
+            .await;
+    }
+    Ok(())
+}
+
 /// Inits a redis connection. This includes:
 ///
 /// - Reading the environment variable `REDIS_URI`
@@ -127,4 +556,133 @@ pub async fn init_redis() -> RedisResult<MultiplexedConnection> {
     println!("[REDIS]: Expecting redis on {}", url);
     redis::Client::open(url)?
         .get_multiplexed_tokio_connection().await
-}
\ No newline at end of file
+}
+
+/// Loads the keys used to sign and verify JWTs. This includes:
+///
+/// - Reading the `JWT_*` environment variables for the current signing key
+/// - Reading the `JWT_PREVIOUS_*` environment variables for the key that is
+///   being rotated out
+///
+/// # `JWT_*`
+///
+/// - `JWT_ALGORITHM`: `HS256` (default), `HS384`, `HS512`, `RS256`, `RS384`,
+///   `RS512`, `PS256`, `PS384`, `PS512`, `ES256`, `ES384` or `EdDSA`
+/// - `JWT_KEY` or `JWT_KEY_FILE`: HMAC secret or PEM private key
+/// - `JWT_PUBLIC_KEY` or `JWT_PUBLIC_KEY_FILE`: PEM public key. Only used by
+///   asymmetric algorithms
+/// - `JWT_KID`: Key identifier included on the token header. Defaults to a
+///   hash of the key
+///
+/// The same variables prefixed with `JWT_PREVIOUS_` describe the key that is
+/// being rotated out. The private key is not needed for the previous key.
+/// `JWT_PREVIOUS_ROTATED_AT` must be set to the RFC 3339 date of the rotation,
+/// such as `2021-09-20T10:00:00Z`. Tokens signed with the previous key are
+/// accepted for `JWT_PREVIOUS_GRACE` seconds after that date, no matter how
+/// many times the server restarts. If no key is configured, a random secret
+/// is generated and tokens won't survive a server restart
+pub fn init_keyring() -> Result<Keyring, KeyError> {
+    Keyring::from_env()
+}
+
+/// Reads the runtime settings. This includes:
+///
+/// - `PUBLIC_URL`: Public URL of the server, used to build the links sent by
+///   email. Defaults to `http://127.0.0.1:8000`
+/// - `REQUIRE_VERIFIED_EMAIL`: If `true`, users can't log in until they verify
+///   their email. Defaults to `false`
+/// - `ACCOUNT_DELETION_DAYS`: Days a deleted account can still be restored
+///   before it is purged. Defaults to `30`
+/// - `PASSWORD_MIN_LENGTH` and `PASSWORD_MAX_LENGTH`: Password length limits,
+///   in characters. Default to `8` and `64`
+/// - `PASSWORD_BREACH_LIST`: Folder with the breached password range files.
+///   See [PasswordPolicy](crate::mongo::user::PasswordPolicy). Disabled by
+///   default
+/// - `BCRYPT_COST`: Cost for new password hashes, between `4` and `31`.
+///   Passwords hashed with a lower cost are hashed again when the user logs
+///   in. Defaults to `12`
+/// - `IMAGE_CONVERTER`: ImageMagick program used to resize uploaded images.
+///   Defaults to `convert`. Use `magick` for ImageMagick 7
+/// - `FFMPEG`: Program used to transcode uploaded audio. Defaults to `ffmpeg`
+/// - `FFPROBE`: Program used to inspect transcoded audio. Defaults to
+///   `ffprobe`
+/// - `MEDIA_REDIRECT`: If `true`, media is downloaded straight from the store
+///   with a presigned URL when the store supports them. Defaults to `false`
+pub fn init_config() -> Config {
+    Config::from_env()
+}
+
+/// Reads the OpenID Connect providers users can log in with. This includes:
+///
+/// - `OIDC_PROVIDERS`: Comma separated list of provider names, such as
+///   `google,gitlab`. No providers are enabled by default
+///
+/// And for each provider, using its name in uppercase:
+///
+/// - `OIDC_<NAME>_ISSUER`: Issuer URL. The endpoints are discovered from
+///   `<issuer>/.well-known/openid-configuration`
+/// - `OIDC_<NAME>_CLIENT_ID`: Client id registered on the provider
+/// - `OIDC_<NAME>_CLIENT_SECRET`: Client secret. Optional for public clients
+/// - `OIDC_<NAME>_SCOPES`: Requested scopes. Defaults to `openid email profile`
+///
+/// The redirect URI registered on the provider must be
+/// `<PUBLIC_URL>/api/users/oidc/<name>/callback`
+pub fn init_oidc() -> Result<Providers, OidcError> {
+    Providers::from_env()
+}
+
+/// Creates the mailer used to send emails to users. This includes:
+///
+/// - `SMTP_URI`: SMTP server URL, such as
+///   `smtps://<username>:<password>@<host>:<port>`
+/// - `MAIL_FROM`: Sender address. Defaults to `fuzzy-disco <noreply@localhost>`
+/// - `MAILBOX_DIR`: If `SMTP_URI` is not set, emails are written to this
+///   folder instead. Defaults to `mailbox/`
+pub fn init_mailer() -> Result<Box<dyn Mailer>, MailError> {
+    match std::env::var("SMTP_URI") {
+        Ok(url) => {
+            let from = std::env::var("MAIL_FROM")
+                .unwrap_or_else(|_| "fuzzy-disco <noreply@localhost>".to_string());
+            Ok(Box::new(SmtpMailer::new(&url, &from)?))
+        }
+        Err(_) => {
+            let folder = std::env::var("MAILBOX_DIR").unwrap_or_else(|_| "mailbox/".to_string());
+            println!("[MAIL]: No SMTP server configured. Writing emails to {}", folder);
+            Ok(Box::new(MailboxMailer::new(folder)))
+        }
+    }
+}
+
+/// Creates the store that keeps media files. This includes:
+///
+/// - `S3_ENDPOINT`: URL of an S3 compatible service, such as
+///   `https://s3.eu-west-1.amazonaws.com` or `http://127.0.0.1:9000` for MinIO.
+///   Buckets are addressed by path
+/// - `S3_BUCKET`: Bucket the media is stored on
+/// - `S3_REGION`: Region used to sign requests. Defaults to `us-east-1`
+/// - `S3_ACCESS_KEY` and `S3_SECRET_KEY`: Credentials
+/// - `MEDIA_DIR`: If `S3_ENDPOINT` is not set, media is stored on this folder
+///   instead. Defaults to `media/`
+pub fn init_store() -> Result<Arc<dyn MediaStore>, StoreError> {
+    match std::env::var("S3_ENDPOINT") {
+        Ok(endpoint) => {
+            let var = |name: &str| {
+                std::env::var(name).map_err(|_| StoreError::Config(format!("Missing {}", name)))
+            };
+            let region = std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+            let store = S3Store::new(
+                &endpoint,
+                &var("S3_BUCKET")?,
+                &region,
+                &var("S3_ACCESS_KEY")?,
+                &var("S3_SECRET_KEY")?,
+            )?;
+            Ok(Arc::new(store))
+        }
+        Err(_) => {
+            let folder = std::env::var("MEDIA_DIR").unwrap_or_else(|_| "media/".to_string());
+            println!("[STORE]: No S3 endpoint configured. Storing media on {}", folder);
+            Ok(Arc::new(FileStore::new(folder)))
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use crate::mailer::{Mail, MailError, Mailer};
+
+/// Writes every email as a text file inside a folder instead of sending it.
+/// Useful for local development and tests, where no SMTP server is available
+pub struct MailboxMailer {
+    folder: PathBuf,
+}
+
+impl MailboxMailer {
+    pub fn new(folder: impl Into<PathBuf>) -> MailboxMailer {
+        MailboxMailer {
+            folder: folder.into(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for MailboxMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        rocket::tokio::fs::create_dir_all(&self.folder).await?;
+        let name = format!(
+            "{}-{}.txt",
+            chrono::Utc::now().timestamp_millis(),
+            mail.to.replace(|c: char| !c.is_ascii_alphanumeric() && c != '@' && c != '.', "_")
+        );
+        let content = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+        rocket::tokio::fs::write(self.folder.join(name), content).await?;
+        #[cfg(debug_assertions)]
+        println!("[MAIL]: {} -> {}", mail.subject, mail.to);
+        Ok(())
+    }
+}
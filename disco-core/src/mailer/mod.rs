@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+pub use mailbox::MailboxMailer;
+pub use smtp::SmtpMailer;
+
+/// Writes emails to a local folder
+mod mailbox;
+/// Sends emails through an SMTP relay
+mod smtp;
+
+/// Errors produced while sending emails
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("Couldn't build email: {0}")]
+    Build(#[from] lettre::error::Error),
+    #[error("Couldn't send email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Couldn't write email: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A plain text email
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    pub fn new(to: &str, subject: &str, body: String) -> Mail {
+        Mail {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body,
+        }
+    }
+}
+
+/// Sends emails to users. Implementations must be cheap to share between
+/// requests, as a single instance is managed by Rocket
+#[rocket::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
@@ -25,6 +25,32 @@
 //!
 //! ```bash
 //! export MONGODB_URI="mongodb://<username>:<password>@<ip>:<port>/"
+//! # Optional. See `init_keyring` for asymmetric keys and key rotation
+//! export JWT_KEY_FILE="<path to the HS256 secret>"
+//! # Optional. See `init_mailer`. Emails are written to `mailbox/` otherwise
+//! export SMTP_URI="smtps://<username>:<password>@<host>:<port>"
+//! # Optional. See `init_oidc` to log in with OpenID Connect providers
+//! export OIDC_PROVIDERS="google"
+//! # Optional. Users promoted to admin on startup. See `promote_admins`
+//! export ADMIN_ALIASES="<alias>"
+//! # Optional. Days deleted accounts can be restored. See `init_config`
+//! export ACCOUNT_DELETION_DAYS="30"
+//! # Optional. Password rules and bcrypt cost. See `init_config`
+//! export PASSWORD_MIN_LENGTH="8"
+//! export PASSWORD_BREACH_LIST="<path to the range files>"
+//! export BCRYPT_COST="12"
+//! # Optional. Needs ImageMagick. See `init_config`
+//! export IMAGE_CONVERTER="convert"
+//! # Optional. Needs FFmpeg. See `init_config`
+//! export FFMPEG="ffmpeg"
+//! export FFPROBE="ffprobe"
+//! # Optional. See `init_store`. Media is stored on `media/` otherwise
+//! export S3_ENDPOINT="https://<host>"
+//! export S3_BUCKET="<bucket>"
+//! export S3_ACCESS_KEY="<access key>"
+//! export S3_SECRET_KEY="<secret key>"
+//! # Optional. Serve media from presigned store URLs. See `init_config`
+//! export MEDIA_REDIRECT="true"
 //! ```
 //!
 //! 4. Copy your static website to `static/`
@@ -48,9 +74,15 @@ use rocket::fs::FileServer;
 use init::*;
 
 pub mod api;
+mod config;
 mod init;
+mod mailer;
 mod mongo;
+mod oidc;
 mod control;
+mod gc;
+mod purge;
+mod storage;
 
 #[rocket::main]
 async fn main() -> Result<(), String> {
@@ -58,9 +90,18 @@ async fn main() -> Result<(), String> {
     let redis_connection = init_redis().await
         .map_err(|x| format!("{:?}",x))?;
 
+    // Loading JWT keys
+    let keyring = init_keyring().map_err(|x| format!("{}", x))?;
+
+    // Loading settings, mailer and media store
+    let config = init_config();
+    let mailer = init_mailer().map_err(|x| format!("{}", x))?;
+    let providers = init_oidc().map_err(|x| format!("{}", x))?;
+    let store = init_store().map_err(|x| format!("{}", x))?;
+
     // Setting up mongodb connection
     println!("Connecting to database...");
-    let (mongo_database, _mongo_client) = init_mongo_db().await
+    let (mongo_database, mongo_client) = init_mongo_db().await
         .map_err(|err|format!("{:?}", err))?;
 
     println!("Database connection successfully");
@@ -70,6 +111,23 @@ async fn main() -> Result<(), String> {
     let mongo_post_collection = mongo_database.collection::<mongo::post::Post>("Posts");
     let mongo_media_collection = mongo_database.collection::<mongo::media::Media>("Media");
     let mongo_session_collection = mongo_database.collection::<mongo::session::Session>("Sessions");
+    let mongo_ticket_collection = mongo_database.collection::<mongo::ticket::Ticket>("Tickets");
+    let mongo_access_token_collection =
+        mongo_database.collection::<mongo::access_token::AccessToken>("AccessTokens");
+    let mongo_identity_collection =
+        mongo_database.collection::<mongo::identity::Identity>("Identities");
+    let mongo_reservation_collection = mongo_database
+        .collection::<mongo::alias_reservation::AliasReservation>("AliasReservations");
+    let mongo_export_collection =
+        mongo_database.collection::<mongo::export::Export>("Exports");
+
+    // Removes the accounts whose deletion grace period is over and the
+    // expired exports
+    purge::spawn(mongo_database.clone(), store.clone());
+    // Removes the media that wasn't claimed in time and the files left behind
+    gc::spawn(mongo_database.clone(), store.clone());
+    // Transcodes uploaded audio and shows the posts waiting for it
+    api::media::transcode::spawn(mongo_database.clone(), &config, store.clone());
 
     if let Err(x) = rocket::tokio::fs::create_dir("temp/").await {
         #[cfg(debug_assertions)]
@@ -83,8 +141,19 @@ async fn main() -> Result<(), String> {
         .manage(mongo_post_collection)
         .manage(mongo_media_collection)
         .manage(mongo_session_collection)
+        .manage(mongo_ticket_collection)
+        .manage(mongo_identity_collection)
+        .manage(mongo_access_token_collection)
+        .manage(mongo_reservation_collection)
+        .manage(mongo_export_collection)
         .manage(redis_connection)
-        //.manage(mongo_client)
+        .manage(keyring)
+        .manage(config)
+        .manage(mailer)
+        .manage(providers)
+        .manage(store)
+        // Needed for transactions
+        .manage(mongo_client)
         // Mounted routes
         .mount("/api/search", routes![
             api::search::get::search_user,
@@ -114,7 +183,14 @@ async fn main() -> Result<(), String> {
                 api::users::auth::post::signup,
                 api::users::auth::post::login_alias,
                 api::users::auth::post::login_email,
+                api::users::auth::post::login_mfa,
                 api::users::auth::post::login_refresh_token,
+                api::users::auth::post::logout,
+                api::users::auth::post::resend_verification,
+                api::users::auth::post::forgot_password,
+                api::users::auth::post::reset_password,
+                api::users::auth::post::restore,
+                api::users::auth::get::verify_email,
             ],
         )
         .mount(
@@ -125,16 +201,67 @@ async fn main() -> Result<(), String> {
                 api::users::posts::get::get_posts_from,
                 api::users::posts::get::get_private_posts_from,
                 api::users::post::update_user_password,
+                api::users::post::update_user_alias,
                 api::users::post::update_user_info,
                 api::users::post::update_user_avatar,
                 api::users::delete::delete_user,
             ],
         )
+        .mount(
+            "/api/users/export",
+            routes![
+                api::users::export::post::request_export,
+                api::users::export::get::get_export,
+                api::users::export::get::download_export,
+            ],
+        )
+        .mount(
+            "/api/users/import",
+            routes![api::users::import::post::import_archive],
+        )
+        .mount(
+            "/api/users/oidc",
+            routes![
+                api::users::oidc::get::login,
+                api::users::oidc::get::callback,
+                api::users::oidc::post::signup,
+            ],
+        )
+        .mount(
+            "/api/users/tokens",
+            routes![
+                api::users::tokens::get::get_access_tokens,
+                api::users::tokens::post::new_access_token,
+                api::users::tokens::delete::delete_access_token,
+            ],
+        )
+        .mount(
+            "/api/users/totp",
+            routes![
+                api::users::totp::post::enroll,
+                api::users::totp::post::confirm,
+                api::users::totp::post::disable,
+            ],
+        )
+        .mount(
+            "/api/admin",
+            routes![
+                api::admin::get::get_users,
+                api::admin::post::update_role,
+                api::admin::post::suspend_user,
+                api::admin::post::ban_user,
+                api::admin::post::reinstate_user,
+                api::admin::delete::delete_user_sessions,
+                api::admin::delete::delete_any_post,
+                api::admin::delete::delete_any_media,
+            ],
+        )
         .mount(
             "/api/sessions",
             routes![
                 api::sessions::get::get_user_sessions,
                 api::sessions::post::delete_all_sessions,
+                api::sessions::delete::delete_session,
             ],
         )
         // Static website server
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::str::FromStr;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::token;
+use crate::mongo::traits::Document;
+
+/// Every personal access token starts with this prefix, so they can be told
+/// apart from JWTs and found by secret scanners
+pub const ACCESS_TOKEN_PREFIX: &str = "fdp_";
+
+/// Maximum number of access tokens a user can have
+pub const MAX_ACCESS_TOKENS: u64 = 25;
+
+/// Permission that a route requires. Session tokens have every scope. Personal
+/// access tokens only have the scopes chosen when they were created
+#[derive(Ord, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum Scope {
+    /// Read private posts, media and profile data
+    #[serde(rename = "read:private")]
+    ReadPrivate,
+    /// Create, edit and delete posts
+    #[serde(rename = "posts:write")]
+    PostsWrite,
+    /// Upload media files
+    #[serde(rename = "media:upload")]
+    MediaUpload,
+    /// Update the user description, email and avatar
+    #[serde(rename = "profile:write")]
+    ProfileWrite,
+    /// Manage the account: password, sessions, two-factor authentication and
+    /// access tokens. Can't be granted to access tokens
+    #[serde(rename = "account")]
+    Account,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadPrivate => "read:private",
+            Scope::PostsWrite => "posts:write",
+            Scope::MediaUpload => "media:upload",
+            Scope::ProfileWrite => "profile:write",
+            Scope::Account => "account",
+        }
+    }
+
+    /// Checks if the scope can be granted to personal access tokens
+    pub fn grantable(&self) -> bool {
+        !matches!(self, Scope::Account)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read:private" => Ok(Scope::ReadPrivate),
+            "posts:write" => Ok(Scope::PostsWrite),
+            "media:upload" => Ok(Scope::MediaUpload),
+            "profile:write" => Ok(Scope::ProfileWrite),
+            "account" => Ok(Scope::Account),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Scope> for mongodb::bson::Bson {
+    fn from(s: Scope) -> Self {
+        mongodb::bson::to_bson(&s).unwrap()
+    }
+}
+
+/// A named, long lived token that lets bots and scripts use the API on behalf
+/// of a user without their password. Only the token
+/// [hash](crate::mongo::token::hash) is stored. Tokens without an expiration
+/// date last until they are deleted
+#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct AccessToken {
+    #[serde(rename = "_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    token: String,
+    user_id: ObjectId,
+    scopes: Vec<Scope>,
+    date: DateTime,
+    last_used: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<DateTime>,
+}
+
+impl Document for AccessToken {}
+
+impl AccessToken {
+    /// Creates a new access token for the user. Returns the token and the
+    /// secret that must be shown to the user
+    pub fn new(
+        name: String,
+        user_id: ObjectId,
+        scopes: Vec<Scope>,
+        ttl: Option<i64>,
+    ) -> (AccessToken, String) {
+        let secret = format!("{}{}", ACCESS_TOKEN_PREFIX, token::generate());
+        let date = DateTime::now();
+        let access_token = AccessToken {
+            id: None,
+            name,
+            token: token::hash(&secret),
+            user_id,
+            scopes,
+            date,
+            last_used: None,
+            expires: ttl.map(|x| DateTime::from_millis(date.timestamp_millis() + x * 1000)),
+        };
+        (access_token, secret)
+    }
+
+    pub fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn token_hash(&self) -> &str {
+        &self.token
+    }
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
+    }
+    pub fn scopes(&self) -> &Vec<Scope> {
+        &self.scopes
+    }
+    pub fn date(&self) -> DateTime {
+        self.date
+    }
+    pub fn last_used(&self) -> Option<DateTime> {
+        self.last_used
+    }
+    pub fn expires(&self) -> Option<DateTime> {
+        self.expires
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expires.map(|x| x <= DateTime::now()).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::{AccessToken, Scope, ACCESS_TOKEN_PREFIX};
+
+    #[test]
+    pub fn new_access_token() {
+        let (token, secret) = AccessToken::new(
+            "bot".to_string(),
+            ObjectId::new(),
+            vec![Scope::PostsWrite],
+            None,
+        );
+        assert!(secret.starts_with(ACCESS_TOKEN_PREFIX));
+        assert_ne!(token.token_hash(), secret);
+        assert!(!token.is_expired());
+    }
+
+    #[test]
+    pub fn scope_names() {
+        for scope in [Scope::ReadPrivate, Scope::PostsWrite, Scope::MediaUpload, Scope::ProfileWrite, Scope::Account] {
+            let json = serde_json::to_string(&scope).unwrap();
+            assert_eq!(json, format!("\"{}\"", scope));
+            assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+        }
+        assert!(!Scope::Account.grantable());
+    }
+}
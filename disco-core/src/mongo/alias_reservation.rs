@@ -0,0 +1,64 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::traits::Document;
+use crate::mongo::user::Alias;
+
+/// Time (seconds) an alias stays reserved after its owner changes it. Nobody
+/// else can take it meanwhile, and requests for the old alias are redirected
+/// to the new one
+#[cfg(debug_assertions)]
+pub const ALIAS_COOLDOWN: i64 = 3600;
+
+#[cfg(not(debug_assertions))]
+pub const ALIAS_COOLDOWN: i64 = 3600 * 24 * 30;
+
+/// An alias that was given up by a user. Reservations are removed by a TTL
+/// index once they expire. They reference the user by id, so old aliases
+/// always point to the current one
+#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct AliasReservation {
+    #[serde(rename = "_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    // old alias
+    alias: Alias,
+    // lowercase old alias. Unique
+    alias_normalized: String,
+    // user that gave up the alias
+    user_id: ObjectId,
+    expires: DateTime,
+}
+
+impl Document for AliasReservation {}
+
+impl AliasReservation {
+    /// Reserves `alias` for [ALIAS_COOLDOWN] seconds on behalf of the user
+    pub fn new(alias: Alias, user_id: ObjectId) -> AliasReservation {
+        let expires = DateTime::from_millis(DateTime::now().timestamp_millis() + ALIAS_COOLDOWN * 1000);
+        AliasReservation {
+            id: None,
+            alias_normalized: alias.normalized(),
+            alias,
+            user_id,
+            expires,
+        }
+    }
+
+    pub fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    pub fn alias(&self) -> &Alias {
+        &self.alias
+    }
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
+    }
+    pub fn expires(&self) -> DateTime {
+        self.expires
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expires <= DateTime::now()
+    }
+}
@@ -0,0 +1,124 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::traits::Document;
+
+/// Time (seconds) an export can be downloaded after it is requested. Expired
+/// exports are removed by the [purge job](crate::purge)
+#[cfg(debug_assertions)]
+pub const EXPORT_TTL: i64 = 3600;
+
+#[cfg(not(debug_assertions))]
+pub const EXPORT_TTL: i64 = 3600 * 24 * 7;
+
+/// Time (seconds) an export can stay pending. Exports built for longer are
+/// considered abandoned, as the server building them may have stopped, and
+/// are marked as failed by the [purge job](crate::purge)
+pub const EXPORT_LEASE: i64 = 3600;
+
+/// Progress of an export
+#[derive(Ord, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    /// The archive is being built
+    Pending,
+    /// The archive can be downloaded
+    Ready,
+    /// The archive has already been downloaded
+    Downloaded,
+    /// The archive couldn't be built
+    Failed,
+}
+
+impl From<ExportStatus> for mongodb::bson::Bson {
+    fn from(s: ExportStatus) -> Self {
+        mongodb::bson::to_bson(&s).unwrap()
+    }
+}
+
+/// An archive with every piece of data stored about a user. Archives are
+/// built in the background, kept on the [media store](crate::storage) and can
+/// be downloaded once. `date` is also the start of the
+/// [lease](EXPORT_LEASE) of the server building it
+#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct Export {
+    #[serde(rename = "_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    user_id: ObjectId,
+    status: ExportStatus,
+    date: DateTime,
+    expires: DateTime,
+}
+
+impl Document for Export {}
+
+impl Export {
+    /// Creates a pending export for the user
+    pub fn new(user_id: ObjectId) -> Export {
+        let date = DateTime::now();
+        Export {
+            id: None,
+            user_id,
+            status: ExportStatus::Pending,
+            date,
+            expires: DateTime::from_millis(date.timestamp_millis() + EXPORT_TTL * 1000),
+        }
+    }
+
+    pub fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
+    }
+    pub fn status(&self) -> ExportStatus {
+        self.status
+    }
+    pub fn date(&self) -> DateTime {
+        self.date
+    }
+    pub fn expires(&self) -> DateTime {
+        self.expires
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expires <= DateTime::now()
+    }
+}
+
+/// Start of the lease of the exports that are still being built. Pending
+/// exports requested before it are abandoned
+pub fn lease_cutoff() -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - EXPORT_LEASE * 1000)
+}
+
+/// Store key of the archive for the given export
+pub fn export_key(id: &ObjectId) -> String {
+    format!("exports/{}.tar", id)
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::{export_key, lease_cutoff, Export, ExportStatus, EXPORT_TTL};
+
+    #[test]
+    pub fn new_export_is_pending() {
+        let export = Export::new(ObjectId::new());
+        assert_eq!(export.status(), ExportStatus::Pending);
+        assert!(!export.is_expired());
+        assert_eq!(
+            export.expires().timestamp_millis() - export.date().timestamp_millis(),
+            EXPORT_TTL * 1000
+        );
+        assert!(export.date() > lease_cutoff());
+    }
+
+    #[test]
+    pub fn archive_key() {
+        let oid: ObjectId = "6138ae1329e3d1d8a3c6a0f2".parse().unwrap();
+        assert_eq!(export_key(&oid), "exports/6138ae1329e3d1d8a3c6a0f2.tar");
+    }
+}
@@ -0,0 +1,57 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::traits::Document;
+
+/// Links an account from an external OpenID Connect provider to a user. The
+/// pair `provider` and `subject` is unique, as the `sub` claim is only unique
+/// for a given issuer
+#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct Identity {
+    #[serde(rename = "_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    // provider name, as configured on OIDC_PROVIDERS
+    provider: String,
+    // `sub` claim
+    subject: String,
+    user_id: ObjectId,
+    // email shared by the provider when the identity was linked
+    email: Option<String>,
+    date: DateTime,
+}
+
+impl Document for Identity {}
+
+impl Identity {
+    pub fn new(provider: &str, subject: &str, user_id: ObjectId, email: Option<String>) -> Identity {
+        Identity {
+            id: None,
+            provider: provider.to_string(),
+            subject: subject.to_string(),
+            user_id,
+            email,
+            date: DateTime::now(),
+        }
+    }
+
+    pub fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
+    }
+    pub fn email(&self) -> &Option<String> {
+        &self.email
+    }
+    pub fn date(&self) -> DateTime {
+        self.date
+    }
+}
@@ -1,10 +1,10 @@
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
 
 use crate::mongo::media::format::Format;
-use crate::mongo::media::Status;
+use crate::mongo::media::{AudioInfo, Processing, Rendition, Status};
 use crate::mongo::traits::Document;
-use crate::mongo::user::Alias;
 use crate::mongo::visibility::Visibility;
 
 /// A Media instance represents a Document on Mongodb with usefull information
@@ -14,22 +14,48 @@ pub struct Media {
     #[serde(rename = "_id")]
     #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
-    uploaded_by: Alias,
+    // uploader id
+    uploaded_by: ObjectId,
     status: Status,
     format: Format,
+    // MIME type of the original file. Missing on media uploaded before it
+    // was stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime: Option<String>,
     visibility: Visibility,
+    // image renditions that have been created. Empty for audio and for
+    // images uploaded before renditions existed
+    #[serde(default)]
+    renditions: Vec<Rendition>,
+    // audio transcoding. Missing on media stored before the job existed
+    #[serde(default)]
+    processing: Processing,
+    // lease of the running transcoding job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_started: Option<DateTime>,
+    // set once the audio is transcoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<AudioInfo>,
 }
 
 impl Document for Media {}
 
 impl Media {
-    pub fn new(alias: Alias, class: Format) -> Media {
+    pub fn new(user_id: ObjectId, class: Format, mime: &str) -> Media {
         Media {
             id: None,
-            uploaded_by: alias,
+            uploaded_by: user_id,
             status: Status::Waiting,
             format: class,
+            mime: Some(mime.to_string()),
             visibility: Visibility::Private,
+            renditions: Vec::new(),
+            processing: match class {
+                Format::Audio => Processing::Uploading,
+                Format::Image => Processing::Done,
+            },
+            processing_started: None,
+            audio: None,
         }
     }
 
@@ -42,10 +68,22 @@ impl Media {
     pub fn format(&self) -> Format {
         self.format
     }
-    pub fn uploaded_by(&self) -> &Alias {
-        &self.uploaded_by
+    pub fn mime(&self) -> Option<&str> {
+        self.mime.as_deref()
+    }
+    pub fn uploaded_by(&self) -> ObjectId {
+        self.uploaded_by
     }
     pub fn visibility(&self) -> &Visibility {
         &self.visibility
     }
+    pub fn renditions(&self) -> &Vec<Rendition> {
+        &self.renditions
+    }
+    pub fn processing(&self) -> Processing {
+        self.processing
+    }
+    pub fn audio(&self) -> Option<AudioInfo> {
+        self.audio
+    }
 }
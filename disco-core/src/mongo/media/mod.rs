@@ -1,10 +1,16 @@
 pub use format::Format;
 pub use media::Media;
+pub use processing::{AudioInfo, Processing};
+pub use rendition::Rendition;
 pub use result::MediaError;
 pub use status::Status;
 
 mod format;
 #[allow(dead_code)]
 mod media;
+/// Audio transcoding state
+mod processing;
+/// Resized copies of images
+mod rendition;
 mod result;
 mod status;
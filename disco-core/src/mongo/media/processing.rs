@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Progress of the [transcoding job](crate::api::media::transcode) for audio files.
+/// Images are processed when they are uploaded, and media stored before the
+/// job existed is `Done`
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Processing {
+    /// The file is still being stored. See
+    /// [queue_transcoding](crate::api::media::process::queue_transcoding)
+    Uploading,
+    /// Waiting for the job
+    Pending,
+    /// Being transcoded. Abandoned jobs are picked up again once their lease
+    /// is over
+    Running,
+    #[default]
+    Done,
+    /// The file couldn't be decoded
+    Failed,
+}
+
+impl From<Processing> for mongodb::bson::Bson {
+    fn from(p: Processing) -> Self {
+        mongodb::bson::to_bson(&p).unwrap()
+    }
+}
+
+/// Properties of a transcoded audio file
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+pub struct AudioInfo {
+    /// Milliseconds
+    pub duration: i64,
+    /// Hz
+    pub sample_rate: i32,
+    pub channels: i32,
+}
+
+impl From<AudioInfo> for mongodb::bson::Bson {
+    fn from(a: AudioInfo) -> Self {
+        mongodb::bson::to_bson(&a).unwrap()
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// Resized copies of an image. Renditions are JPEG files without metadata,
+/// rotated according to the original EXIF orientation. They are stored next
+/// to the original file
+#[derive(
+    Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, Default,
+    FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Rendition {
+    /// Up to 256px. Avatars and previews
+    #[field(value = "thumb")]
+    Thumb,
+    /// Up to 1080px. Post feeds
+    #[field(value = "feed")]
+    Feed,
+    /// Up to 2048px. Served when no rendition is requested
+    #[default]
+    #[field(value = "full")]
+    Full,
+}
+
+impl Rendition {
+    /// Every rendition created for an image
+    pub const ALL: [Rendition; 3] = [Rendition::Thumb, Rendition::Feed, Rendition::Full];
+
+    /// Name used on file names
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rendition::Thumb => "thumb",
+            Rendition::Feed => "feed",
+            Rendition::Full => "full",
+        }
+    }
+
+    /// Longest side (pixels). Smaller images aren't enlarged
+    pub fn size(&self) -> u32 {
+        match self {
+            Rendition::Thumb => 256,
+            Rendition::Feed => 1080,
+            Rendition::Full => 2048,
+        }
+    }
+
+    /// JPEG quality
+    pub fn quality(&self) -> u32 {
+        match self {
+            Rendition::Thumb => 75,
+            Rendition::Feed => 82,
+            Rendition::Full => 90,
+        }
+    }
+}
+
+impl From<Rendition> for mongodb::bson::Bson {
+    fn from(r: Rendition) -> Self {
+        mongodb::bson::to_bson(&r).unwrap()
+    }
+}
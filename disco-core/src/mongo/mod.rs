@@ -1,5 +1,14 @@
 pub use traits::IntoDocument;
 
+/// Contains data structures that represents personal access tokens
+pub mod access_token;
+/// Contains data structures that represents aliases reserved after a rename
+pub mod alias_reservation;
+/// Contains data structures that represents personal data exports
+pub mod export;
+/// Contains data structures that represents accounts from external identity
+/// providers
+pub mod identity;
 /// Contains data structures that represents media files on a document-based
 /// database
 pub mod media;
@@ -9,6 +18,10 @@ pub mod post;
 /// Contains data structures that represents a user session
 #[allow(dead_code)]
 pub mod session;
+/// Contains data structures that represents single use tickets sent to users
+pub mod ticket;
+/// Opaque tokens that are stored hashed on the database
+pub mod token;
 /// Traits related to Mongo documents
 mod traits;
 /// Contains data structures that represents users on a document-based database
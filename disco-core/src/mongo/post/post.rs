@@ -19,6 +19,16 @@ use crate::mongo::visibility::Visibility;
 /// - [mongodb::bson::oid::ObjectId]
 /// - [crate::mongo::post::Title]
 /// - [crate::mongo::post::Caption]
+///
+/// # Author
+///
+/// Posts reference their author by id. The author alias is a copy kept for
+/// display and search, and it is updated when the user changes their alias
+///
+/// # Processing
+///
+/// Posts stay hidden from everyone but their author while their audio is
+/// being [transcoded](crate::api::media::transcode)
 #[derive(Serialize, Deserialize, Debug, Ord, PartialOrd, PartialEq, Eq, Clone)]
 pub struct Post {
     #[serde(rename = "_id")]
@@ -26,11 +36,16 @@ pub struct Post {
     id: Option<ObjectId>,
     title: Title,
     caption: Caption,
+    author_id: ObjectId,
+    // author alias, for display and search only
     author: Alias,
     audio: ObjectId,
     photo: ObjectId,
     visibility: Visibility,
     creation_date: DateTime,
+    // missing on posts created before audio transcoding existed
+    #[serde(default)]
+    processing: bool,
 }
 
 impl Document for Post {}
@@ -40,6 +55,7 @@ impl Post {
     pub fn new(
         title: Title,
         caption: Caption,
+        author_id: ObjectId,
         author: Alias,
         audio: ObjectId,
         photo: ObjectId,
@@ -49,14 +65,22 @@ impl Post {
             id: None,
             title,
             caption,
+            author_id,
             author,
             audio,
             photo,
             visibility,
             creation_date: DateTime::now(),
+            processing: false,
         }
     }
 
+    /// Marks the post as waiting for its audio
+    pub fn with_processing(mut self, processing: bool) -> Self {
+        self.processing = processing;
+        self
+    }
+
     pub fn id(&self) -> Option<ObjectId> {
         self.id
     }
@@ -66,6 +90,9 @@ impl Post {
     pub fn caption(&self) -> &Caption {
         &self.caption
     }
+    pub fn author_id(&self) -> ObjectId {
+        self.author_id
+    }
     pub fn author(&self) -> &Alias {
         &self.author
     }
@@ -81,4 +108,7 @@ impl Post {
     pub fn creation_date(&self) -> DateTime {
         self.creation_date
     }
+    pub fn processing(&self) -> bool {
+        self.processing
+    }
 }
@@ -2,54 +2,161 @@ use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
 
+use crate::mongo::token;
 use crate::mongo::traits::Document;
-use crate::mongo::user::Alias;
+
+/// Absolute session lifetime (seconds). After this time the user must log in
+/// again, even if the session has been used recently
+#[cfg(debug_assertions)]
+pub const SESSION_TTL: i64 = 3600 * 24;
+
+#[cfg(not(debug_assertions))]
+pub const SESSION_TTL: i64 = 3600 * 24 * 30;
+
+/// Idle session lifetime (seconds). Sessions that haven't been refreshed for
+/// this time are closed
+#[cfg(debug_assertions)]
+pub const SESSION_IDLE_TTL: i64 = 3600;
+
+#[cfg(not(debug_assertions))]
+pub const SESSION_IDLE_TTL: i64 = 3600 * 24 * 7;
+
+/// Number of rotated refresh tokens remembered for reuse detection
+pub const SESSION_ROTATED_HISTORY: i32 = 64;
 
 /// Contains information about a user login session (aka refresh token). Each
 /// time the server recives a valid `POST /api/user/login`, a new session will
 /// be created on the server. This allows the user to refresh its JWT auth token
 /// without use of username and password
+///
+/// # Rotation
+///
+/// Refresh tokens are single use. Each time the session is refreshed a new
+/// token replaces the current one, and the old token is kept on the `rotated`
+/// list. Replaying a rotated token means the session may have been stolen, so
+/// the whole session is closed
+///
+/// Refresh tokens are never stored. The session only contains their
+/// [hash](crate::mongo::token::hash)
 #[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
 pub struct Session {
-    // Session token
+    // Session id
     #[serde(rename = "_id")]
     #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
-    // subject alias
-    user_alias: Alias,
+    // Current refresh token hash
+    token: String,
+    // Refresh token hashes that have been rotated out
+    rotated: Vec<String>,
+    // subject id
+    user_id: ObjectId,
     // where
     ip: Option<String>,
+    // device
+    user_agent: Option<String>,
     // date
     date: DateTime,
+    // last time the session was refreshed
+    last_used: DateTime,
+    // when the session should be closed
+    expires: DateTime,
 }
 
 impl Session {
-    /// Generates a new session token that is linked to the user's alias
-    pub fn new(user_alias: Alias, ip: Option<String>) -> Session {
+    /// Generates a new session that is linked to the user's id. Only the
+    /// hash of `refresh_token` is stored
+    pub fn new(
+        user_id: ObjectId,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        refresh_token: &str,
+    ) -> Session {
+        let date = DateTime::now();
         Session {
             id: None,
-            user_alias,
+            token: token::hash(refresh_token),
+            rotated: Vec::new(),
+            user_id,
             ip,
-            date: DateTime::now(),
+            user_agent,
+            date,
+            last_used: date,
+            expires: add_seconds(date, SESSION_IDLE_TTL.min(SESSION_TTL)),
         }
     }
 
     pub fn id(&self) -> Option<ObjectId> {
         self.id
     }
-    pub fn sub(&self) -> &Alias {
-        &self.user_alias
+    pub fn token_hash(&self) -> &str {
+        &self.token
+    }
+    pub fn rotated(&self) -> &Vec<String> {
+        &self.rotated
+    }
+    pub fn sub(&self) -> ObjectId {
+        self.user_id
     }
     pub fn date(&self) -> DateTime {
         self.date
     }
-    pub fn user_alias(&self) -> &Alias {
-        &self.user_alias
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
     }
 
     pub fn ip(&self) -> &Option<String> {
         &self.ip
     }
+    pub fn user_agent(&self) -> &Option<String> {
+        &self.user_agent
+    }
+    pub fn last_used(&self) -> DateTime {
+        self.last_used
+    }
+    pub fn expires(&self) -> DateTime {
+        self.expires
+    }
+
+    /// Checks if the session has reached its absolute or idle expiration date
+    pub fn is_expired(&self) -> bool {
+        self.expires <= DateTime::now()
+    }
+
+    /// Expiration date for the session if it is refreshed at `now`. It never
+    /// exceeds the absolute session lifetime
+    pub fn next_expiry(&self, now: DateTime) -> DateTime {
+        let idle = add_seconds(now, SESSION_IDLE_TTL);
+        let absolute = add_seconds(self.date, SESSION_TTL);
+        idle.min(absolute)
+    }
+}
+
+fn add_seconds(date: DateTime, seconds: i64) -> DateTime {
+    DateTime::from_millis(date.timestamp_millis() + seconds * 1000)
 }
 
 impl Document for Session {}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::oid::ObjectId;
+    use mongodb::bson::DateTime;
+
+    use super::{Session, SESSION_TTL};
+
+    #[test]
+    pub fn new_session_is_valid() {
+        let session = Session::new(ObjectId::new(), None, None, "token");
+        assert!(!session.is_expired());
+        assert_ne!(session.token_hash(), "token");
+        assert!(session.rotated().is_empty());
+    }
+
+    #[test]
+    pub fn expiry_is_capped() {
+        let session = Session::new(ObjectId::new(), None, None, "token");
+        let far = DateTime::from_millis(session.date().timestamp_millis() + SESSION_TTL * 2000);
+        let absolute = DateTime::from_millis(session.date().timestamp_millis() + SESSION_TTL * 1000);
+        assert_eq!(session.next_expiry(far), absolute);
+    }
+}
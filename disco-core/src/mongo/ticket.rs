@@ -0,0 +1,91 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::token;
+use crate::mongo::traits::Document;
+
+/// Time (seconds) a user has to verify their email
+pub const VERIFY_EMAIL_TTL: i64 = 3600 * 24;
+
+/// Time (seconds) a user has to reset their password
+pub const RESET_PASSWORD_TTL: i64 = 60 * 15;
+
+/// Time (seconds) a user has to send their TOTP code after logging in with
+/// their password
+pub const MFA_CHALLENGE_TTL: i64 = 60 * 5;
+
+/// Wrong codes allowed on a single MFA challenge before it is discarded
+pub const MFA_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Action that a ticket allows
+#[derive(Ord, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum Purpose {
+    VerifyEmail,
+    ResetPassword,
+    MfaChallenge,
+}
+
+impl From<Purpose> for mongodb::bson::Bson {
+    fn from(p: Purpose) -> Self {
+        mongodb::bson::to_bson(&p).unwrap()
+    }
+}
+
+/// A single use ticket that lets a user perform an action without logging in,
+/// such as verifying their email, resetting their password or finishing a two
+/// factor login. The ticket token is sent to the user and only its
+/// [hash](crate::mongo::token::hash) is stored. Expired tickets are removed by
+/// a TTL index
+#[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct Ticket {
+    #[serde(rename = "_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    token: String,
+    user_id: ObjectId,
+    purpose: Purpose,
+    expires: DateTime,
+    // failed attempts to use the ticket
+    #[serde(default)]
+    attempts: i32,
+}
+
+impl Document for Ticket {}
+
+impl Ticket {
+    /// Creates a new ticket for the user that expires in `ttl` seconds
+    pub fn new(user_id: ObjectId, purpose: Purpose, token: &str, ttl: i64) -> Ticket {
+        let now = DateTime::now().timestamp_millis();
+        Ticket {
+            id: None,
+            token: token::hash(token),
+            user_id,
+            purpose,
+            expires: DateTime::from_millis(now + ttl * 1000),
+            attempts: 0,
+        }
+    }
+
+    pub fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    pub fn token_hash(&self) -> &str {
+        &self.token
+    }
+    pub fn user_id(&self) -> ObjectId {
+        self.user_id
+    }
+    pub fn purpose(&self) -> Purpose {
+        self.purpose
+    }
+    pub fn expires(&self) -> DateTime {
+        self.expires
+    }
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expires <= DateTime::now()
+    }
+}
@@ -0,0 +1,41 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Number of random bytes on each generated token
+const TOKEN_BYTES: usize = 32;
+
+/// Generates a new opaque token from the operating system CSPRNG. The token is
+/// encoded as url safe base64 so it can be used on headers and query strings
+pub fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Hashes a token before storing it on the database. Generated tokens have
+/// enough entropy, so a fast hash is enough to protect them from database
+/// leaks
+pub fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate, hash};
+
+    #[test]
+    pub fn unique() {
+        assert_ne!(generate(), generate());
+    }
+
+    #[test]
+    pub fn stable_hash() {
+        let token = generate();
+        assert_eq!(hash(&token), hash(&token));
+        assert_ne!(hash(&token), token);
+    }
+}
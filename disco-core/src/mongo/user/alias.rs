@@ -14,6 +14,9 @@ lazy_static! {
 
 /// An alias represents the User's custom username for his account. For an alias
 /// to be valid, it must mach r"^[a-zA-Z_\-0-9]{4,30}$"
+///
+/// Aliases keep the case chosen by the user, but two aliases that only differ
+/// on case belong to the same user. Use [Alias::normalized] to compare them
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone)]
 #[serde(transparent)]
 pub struct Alias {
@@ -57,6 +60,11 @@ impl Alias {
     pub fn alias(&self) -> &str {
         &self.alias
     }
+
+    /// Lowercase form of the alias, used to find users regardless of case
+    pub fn normalized(&self) -> String {
+        self.alias.to_lowercase()
+    }
 }
 
 impl From<Alias> for mongodb::bson::Bson {
@@ -75,6 +83,14 @@ mod test {
         let _: Alias = username.parse().unwrap();
     }
 
+    #[test]
+    fn normalized() {
+        let a: Alias = "Altair-Bueno".parse().unwrap();
+        let b: Alias = "altair-bueno".parse().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.normalized(), b.normalized());
+    }
+
     #[test]
     fn invalid() {
         let username = "Hello world";
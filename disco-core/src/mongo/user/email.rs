@@ -4,7 +4,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::mongo::user::result::UserError;
 
-/// Represents a valid email address
+/// Represents a valid email address. Surrounding whitespace is removed.
+/// Addresses are compared using their [normalized](Email::normalized) form
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone)]
 #[serde(transparent)]
 pub struct Email {
@@ -22,6 +23,7 @@ impl FromStr for Email {
 impl Email {
     /// Checks if the email adress is valid and wraps the value
     pub fn new(s: &str) -> crate::mongo::user::result::Result<Email> {
+        let s = s.trim();
         if validator::validate_email(s) {
             Ok(Email {
                 email: s.to_string(),
@@ -34,6 +36,11 @@ impl Email {
     pub fn email(&self) -> &str {
         &self.email
     }
+
+    /// Lowercase form of the address, used to find users regardless of case
+    pub fn normalized(&self) -> String {
+        self.email.to_lowercase()
+    }
 }
 
 impl From<Email> for mongodb::bson::Bson {
@@ -57,6 +64,13 @@ mod test {
         }
     }
 
+    #[test]
+    pub fn normalized() {
+        let email: Email = " Hello@World.com ".parse().unwrap();
+        assert_eq!(email.email(), "Hello@World.com");
+        assert_eq!(email.normalized(), "hello@world.com");
+    }
+
     #[test]
     pub fn invalid() {
         let list = vec!["", " ", "@com", "pepe", "exampl @hello.com"];
@@ -1,8 +1,12 @@
 pub use alias::Alias;
 pub use email::Email;
 pub use password::Password;
+pub use policy::PasswordPolicy;
 pub use result::Result;
 pub use result::UserError;
+pub use role::Role;
+pub use status::AccountStatus;
+pub use totp::Totp;
 pub use user::User;
 
 use crate::mongo::post::Caption;
@@ -11,7 +15,15 @@ pub use crate::mongo::session::Session;
 mod alias;
 mod email;
 mod password;
+/// Rules for new passwords
+pub mod policy;
 pub mod result;
+/// User roles
+mod role;
+/// Account suspension and banning
+mod status;
+/// Two-factor authentication
+pub mod totp;
 #[allow(dead_code)]
 mod user;
 
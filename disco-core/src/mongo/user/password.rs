@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::mongo::user::result;
 use crate::mongo::user::result::UserError;
+use crate::mongo::user::policy::PASSWORD_MIN_LENGTH;
+use crate::mongo::user::PasswordPolicy;
 
 /// A Password instance represents a [bcrypt] encripted hash that is stored on
 /// the database. The hash is used to autheticate the user without storing the
@@ -24,23 +26,49 @@ impl ToString for Password {
 impl FromStr for Password {
     type Err = UserError;
 
+    /// Only checks the minimum length. Passwords chosen by users must follow
+    /// the [PasswordPolicy], see [Password::new]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Password::new(s)
+        if s.len() < PASSWORD_MIN_LENGTH {
+            Err(UserError::PasswordTooShort(PASSWORD_MIN_LENGTH))
+        } else {
+            Password::hash(s, DEFAULT_COST)
+        }
     }
 }
 
 impl Password {
-    /// Creates a new hashed password from a text string. All strings used as
-    /// passwords should be at least 8 characters long
-    pub fn new(s: &str) -> result::Result<Password> {
-        if s.len() < 8 {
-            Err(UserError::PasswordTooShort(8))
-        } else {
-            let hashed_password = bcrypt::hash(s, DEFAULT_COST);
-            match hashed_password {
-                Ok(password) => Ok(Password { password }),
-                Err(_) => Err(UserError::HashError),
-            }
+    /// Creates a new hashed password from a text string. The password must
+    /// follow the [PasswordPolicy]. `personal` contains the alias and email
+    /// of the user
+    pub fn new(s: &str, policy: &PasswordPolicy, personal: &[&str]) -> result::Result<Password> {
+        policy.check(s, personal)?;
+        Password::hash(s, policy.cost)
+    }
+
+    /// Like [Password::new], on a blocking thread. Hashing and reading the
+    /// breach list would stall the async runtime otherwise
+    pub async fn create(
+        s: &str,
+        policy: &PasswordPolicy,
+        personal: &[&str],
+    ) -> result::Result<Password> {
+        let (s, policy) = (s.to_string(), policy.clone());
+        let personal: Vec<String> = personal.iter().map(|x| x.to_string()).collect();
+        rocket::tokio::task::spawn_blocking(move || {
+            let personal: Vec<&str> = personal.iter().map(|x| x.as_str()).collect();
+            Password::new(&s, &policy, &personal)
+        })
+        .await
+        .unwrap_or(Err(UserError::HashError))
+    }
+
+    /// Hashes a password without checking the policy. Only for passwords
+    /// that are already known to be valid, such as generated ones
+    pub fn hash(s: &str, cost: u32) -> result::Result<Password> {
+        match bcrypt::hash(s, cost) {
+            Ok(password) => Ok(Password { password }),
+            Err(_) => Err(UserError::HashError),
         }
     }
 
@@ -48,7 +76,40 @@ impl Password {
         &self.password
     }
 
+    /// bcrypt cost used for this hash
+    pub fn cost(&self) -> Option<u32> {
+        self.password.split('$').nth(2).and_then(|x| x.parse().ok())
+    }
+
+    /// Hashes created with a lower cost than `cost` should be replaced
+    pub fn needs_rehash(&self, cost: u32) -> bool {
+        self.cost().map(|x| x < cost).unwrap_or(true)
+    }
+
     pub fn validate(&self, against: &str) -> bcrypt::BcryptResult<bool> {
         bcrypt::verify(against, &self.password)
     }
+
+    /// Like [Password::validate], on a blocking thread
+    pub async fn verify(&self, against: &str) -> result::Result<bool> {
+        let (hash, against) = (self.password.clone(), against.to_string());
+        rocket::tokio::task::spawn_blocking(move || bcrypt::verify(against, &hash))
+            .await
+            .map_err(|_| UserError::HashError)?
+            .map_err(|_| UserError::HashError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Password;
+
+    #[test]
+    pub fn reads_cost() {
+        let password = Password::hash("a-long-password", 4).unwrap();
+        assert_eq!(password.cost(), Some(4));
+        assert!(password.needs_rehash(5));
+        assert!(!password.needs_rehash(4));
+        assert!(password.validate("a-long-password").unwrap());
+    }
 }
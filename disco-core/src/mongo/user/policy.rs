@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use bcrypt::DEFAULT_COST;
+use lazy_static::lazy_static;
+use sha1::{Digest, Sha1};
+
+use crate::mongo::user::result;
+use crate::mongo::user::result::UserError;
+
+/// Longest password (bytes) bcrypt can hash. The rest would be silently
+/// ignored
+pub const BCRYPT_MAX_BYTES: usize = 72;
+
+/// Default shortest password (characters)
+pub const PASSWORD_MIN_LENGTH: usize = 8;
+
+/// Default longest password (characters)
+pub const PASSWORD_MAX_LENGTH: usize = 64;
+
+/// Shortest alias or email that is searched for inside passwords. Shorter
+/// values would reject too many passwords
+const PERSONAL_DATA_MIN_LENGTH: usize = 4;
+
+lazy_static! {
+    static ref COMMON_PASSWORDS: HashSet<&'static str> = include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect();
+}
+
+/// Rules new passwords must follow. Loaded with the rest of the
+/// [Config](crate::config::Config). Passwords must
+///
+/// - Be between `min_length` and `max_length` characters long, and fit on
+///   [BCRYPT_MAX_BYTES] bytes
+/// - Not be one of the most common passwords. The list is bundled with the
+///   server
+/// - Not contain the user alias or email
+/// - Not appear on the breach list, if there is one
+///
+/// # Breach list
+///
+/// The breach list is a folder with the format used by
+/// [Have I Been Pwned](https://haveibeenpwned.com/Passwords) range files.
+/// Each file is named after the first 5 characters of the SHA-1 hash, and
+/// contains one `SUFFIX:COUNT` line for each breached password. The password
+/// never leaves the server, and only one small file is read on each check
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// bcrypt cost for new hashes. Older hashes are updated on login
+    pub cost: u32,
+    pub breach_list: Option<PathBuf>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: PASSWORD_MIN_LENGTH,
+            max_length: PASSWORD_MAX_LENGTH,
+            cost: DEFAULT_COST,
+            breach_list: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks every rule. `personal` contains the alias and email of the user.
+    /// The breach list is read from disk, so this must run on a blocking
+    /// thread. See [Password::create](crate::mongo::user::Password::create)
+    pub fn check(&self, password: &str, personal: &[&str]) -> result::Result<()> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(UserError::PasswordTooShort(self.min_length));
+        }
+        if length > self.max_length {
+            return Err(UserError::PasswordTooLong(self.max_length));
+        }
+        if password.len() > BCRYPT_MAX_BYTES {
+            return Err(UserError::PasswordTooLarge(BCRYPT_MAX_BYTES));
+        }
+        let lowercase = password.to_lowercase();
+        if COMMON_PASSWORDS.contains(lowercase.as_str()) {
+            return Err(UserError::CommonPassword);
+        }
+        let contains_personal_data = personal
+            .iter()
+            .flat_map(|x| [*x, x.split('@').next().unwrap_or_default()])
+            .filter(|x| x.chars().count() >= PERSONAL_DATA_MIN_LENGTH)
+            .any(|x| lowercase.contains(&x.to_lowercase()));
+        if contains_personal_data {
+            return Err(UserError::PasswordContainsPersonalData);
+        }
+        if self.is_breached(password) {
+            return Err(UserError::BreachedPassword);
+        }
+        Ok(())
+    }
+
+    /// Looks for the password on the breach list. Missing range files mean
+    /// the password isn't there. The check is skipped if the list can't be
+    /// read, so a broken list doesn't block every signup
+    fn is_breached(&self, password: &str) -> bool {
+        let folder = match &self.breach_list {
+            Some(x) => x,
+            None => return false,
+        };
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|x| format!("{:02X}", x))
+            .collect();
+        let (prefix, suffix) = hash.split_at(5);
+        let content = match std::fs::read_to_string(folder.join(prefix))
+            .or_else(|_| std::fs::read_to_string(folder.join(format!("{}.txt", prefix))))
+        {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return false,
+            Err(e) => {
+                println!("[PASSWORD]: Couldn't read breach list: {}", e);
+                return false;
+            }
+        };
+        content
+            .lines()
+            .filter_map(|x| x.split(':').next())
+            .any(|x| x.trim().eq_ignore_ascii_case(suffix))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PasswordPolicy;
+    use crate::mongo::user::UserError;
+
+    #[test]
+    pub fn length_counts_characters() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.check("ñññññññ", &[]), Err(UserError::PasswordTooShort(8)));
+        assert!(policy.check("ññññññññ", &[]).is_ok());
+        assert_eq!(
+            policy.check(&"ñ".repeat(40), &[]),
+            Err(UserError::PasswordTooLarge(72))
+        );
+    }
+
+    #[test]
+    pub fn rejects_common_and_personal() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.check("Password123", &[]), Err(UserError::CommonPassword));
+        assert_eq!(
+            policy.check("my-Altair-Bueno-pass", &["altair-bueno", "e@hello.es"]),
+            Err(UserError::PasswordContainsPersonalData)
+        );
+        assert_eq!(
+            policy.check("hello-world-2021!", &["altair-bueno", "helloworld@hi.es"]),
+            Ok(())
+        );
+    }
+}
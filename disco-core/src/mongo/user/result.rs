@@ -7,9 +7,24 @@ pub type Result<E> = std::result::Result<E, UserError>;
 /// Different errors related to user fields
 #[derive(Ord, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize, Error)]
 pub enum UserError {
-    /// Passwords must have at least 8 characters long
-    #[error("Password must be {0} characters long")]
+    /// Password is shorter than the policy allows
+    #[error("Password must be at least {0} characters long")]
     PasswordTooShort(usize),
+    /// Password is longer than the policy allows
+    #[error("Password must be at most {0} characters long")]
+    PasswordTooLong(usize),
+    /// Password doesn't fit on a bcrypt hash
+    #[error("Password must be at most {0} bytes long")]
+    PasswordTooLarge(usize),
+    /// Password is on the common passwords list
+    #[error("Password is too common")]
+    CommonPassword,
+    /// Password contains the user alias or email
+    #[error("Password can't contain your alias or email")]
+    PasswordContainsPersonalData,
+    /// Password is on the breach list
+    #[error("Password has appeared on a data breach")]
+    BreachedPassword,
     /// Couldn't hash the given password
     #[error("Couldn't hash the given password")]
     HashError,
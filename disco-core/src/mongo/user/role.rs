@@ -0,0 +1,68 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Privileges of a user. Roles are ordered, so every role has the privileges
+/// of the roles below it
+///
+/// - `user`: Default role
+/// - `moderator`: Can list users, suspend accounts, delete any post or media
+///   and close sessions of regular users
+/// - `admin`: Same as moderators, and can ban accounts and change roles
+#[derive(Ord, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Role> for mongodb::bson::Bson {
+    fn from(r: Role) -> Self {
+        mongodb::bson::to_bson(&r).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Role;
+
+    #[test]
+    pub fn ordering() {
+        assert!(Role::Admin > Role::Moderator);
+        assert!(Role::Moderator > Role::User);
+        assert_eq!("moderator".parse::<Role>(), Ok(Role::Moderator));
+        assert_eq!(serde_json::to_string(&Role::Admin).unwrap(), "\"admin\"");
+    }
+}
@@ -0,0 +1,118 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Whether a user can use their account. Suspended accounts are restored
+/// automatically once `until` has passed. Banned accounts stay closed until a
+/// moderator reinstates them
+///
+/// Restrictions carry the `reason` given by the moderator and an optional
+/// `appeal` note telling the user how to contest it. Both are shown to the
+/// user when they try to log in
+#[derive(Serialize, Deserialize, Debug, Ord, PartialOrd, PartialEq, Eq, Clone, Default)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    Suspended {
+        until: DateTime,
+        #[serde(default)]
+        reason: String,
+        #[serde(default)]
+        appeal: Option<String>,
+    },
+    Banned {
+        #[serde(default)]
+        reason: String,
+        #[serde(default)]
+        appeal: Option<String>,
+    },
+}
+
+impl AccountStatus {
+    /// Suspends the account for the given number of seconds
+    pub fn suspended_for(seconds: i64, reason: String, appeal: Option<String>) -> AccountStatus {
+        let until = DateTime::from_millis(DateTime::now().timestamp_millis() + seconds * 1000);
+        AccountStatus::Suspended {
+            until,
+            reason,
+            appeal,
+        }
+    }
+
+    pub fn banned(reason: String, appeal: Option<String>) -> AccountStatus {
+        AccountStatus::Banned { reason, appeal }
+    }
+
+    /// Checks if the account can be used right now
+    pub fn is_active(&self) -> bool {
+        match self {
+            AccountStatus::Active => true,
+            AccountStatus::Suspended { until, .. } => *until <= DateTime::now(),
+            AccountStatus::Banned { .. } => false,
+        }
+    }
+    pub fn is_banned(&self) -> bool {
+        matches!(self, AccountStatus::Banned { .. })
+    }
+    /// Name of the current state: `active`, `suspended` or `banned`. Expired
+    /// suspensions are `active`
+    pub fn state(&self) -> &'static str {
+        match self {
+            x if x.is_active() => "active",
+            AccountStatus::Banned { .. } => "banned",
+            _ => "suspended",
+        }
+    }
+    /// End of the suspension, if the account is suspended
+    pub fn until(&self) -> Option<DateTime> {
+        match self {
+            AccountStatus::Suspended { until, .. } if !self.is_active() => Some(*until),
+            _ => None,
+        }
+    }
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            AccountStatus::Active => None,
+            AccountStatus::Suspended { reason, .. } | AccountStatus::Banned { reason, .. } => {
+                Some(reason)
+            }
+        }
+    }
+    pub fn appeal(&self) -> Option<&str> {
+        match self {
+            AccountStatus::Active => None,
+            AccountStatus::Suspended { appeal, .. } | AccountStatus::Banned { appeal, .. } => {
+                appeal.as_deref()
+            }
+        }
+    }
+}
+
+impl From<AccountStatus> for mongodb::bson::Bson {
+    fn from(s: AccountStatus) -> Self {
+        mongodb::bson::to_bson(&s).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AccountStatus;
+
+    #[test]
+    pub fn suspension_expires() {
+        let reason = || "Spam".to_string();
+        assert!(AccountStatus::Active.is_active());
+        assert!(!AccountStatus::banned(reason(), None).is_active());
+        assert!(!AccountStatus::suspended_for(60, reason(), None).is_active());
+        assert!(AccountStatus::suspended_for(-60, reason(), None).is_active());
+        assert_eq!(AccountStatus::suspended_for(-60, reason(), None).state(), "active");
+    }
+
+    #[test]
+    pub fn legacy_restrictions() {
+        let json = "{\"state\":\"banned\"}";
+        let status: AccountStatus = serde_json::from_str(json).unwrap();
+        assert!(status.is_banned());
+        assert_eq!(status.reason(), Some(""));
+    }
+}
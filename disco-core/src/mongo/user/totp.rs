@@ -0,0 +1,159 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::mongo::token;
+use crate::mongo::user::Alias;
+
+/// Number of recovery codes generated when two-factor authentication is
+/// enabled
+pub const RECOVERY_CODES: usize = 10;
+
+/// Recovery code length (bytes). Codes are stored with a fast hash, so they
+/// must be long enough to resist brute force if the database leaks
+const RECOVERY_CODE_BYTES: usize = 10;
+
+/// Issuer shown on authenticator apps
+const TOTP_ISSUER: &str = "fuzzy-disco";
+/// Secret length (bytes). RFC 4226 recommends 160 bits
+const TOTP_SECRET_BYTES: usize = 20;
+const TOTP_DIGITS: usize = 6;
+/// Seconds each code is valid for
+const TOTP_STEP: u64 = 30;
+/// Number of steps before and after the current one that are accepted, to
+/// allow some clock drift
+const TOTP_SKEW: u64 = 1;
+
+/// RFC 6238 time based one time password settings for a user. A TOTP starts
+/// disabled until the user confirms it with a valid code, so a mistyped secret
+/// can't lock anyone out
+///
+/// Recovery codes are stored as [hashes](crate::mongo::token::hash) and can
+/// only be used once. Codes whose time step is not greater than `last_step`
+/// are rejected, so each code can only be used once too
+#[derive(Serialize, Deserialize, Debug, Ord, PartialOrd, PartialEq, Eq, Clone)]
+pub struct Totp {
+    secret: String,
+    enabled: bool,
+    recovery_codes: Vec<String>,
+    last_step: Option<i64>,
+}
+
+impl Totp {
+    /// Creates a new disabled TOTP with a random secret
+    pub fn new() -> Totp {
+        let mut secret = [0u8; TOTP_SECRET_BYTES];
+        OsRng.fill_bytes(&mut secret);
+        Totp {
+            secret: Secret::Raw(secret.to_vec()).to_encoded().to_string(),
+            enabled: false,
+            recovery_codes: Vec::new(),
+            last_step: None,
+        }
+    }
+
+    /// Base32 encoded secret
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    pub fn recovery_codes(&self) -> &Vec<String> {
+        &self.recovery_codes
+    }
+    pub fn last_step(&self) -> Option<i64> {
+        self.last_step
+    }
+
+    /// `otpauth://` URI that authenticator apps can read from a QR code
+    pub fn uri(&self, alias: &Alias) -> Option<String> {
+        self.totp(alias).map(|x| x.get_url())
+    }
+
+    /// Checks the code against the current time. Returns the time step the
+    /// code belongs to, if valid and not used before
+    pub fn verify(&self, alias: &Alias, code: &str) -> Option<i64> {
+        let totp = self.totp(alias)?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        let current = now / TOTP_STEP;
+        (current.saturating_sub(TOTP_SKEW)..=current + TOTP_SKEW)
+            .map(|step| step as i64)
+            .filter(|step| self.last_step.map(|x| *step > x).unwrap_or(true))
+            .find(|step| totp.generate(*step as u64 * TOTP_STEP) == code)
+    }
+
+    fn totp(&self, alias: &Alias) -> Option<TOTP> {
+        let secret = Secret::Encoded(self.secret.clone()).to_bytes().ok()?;
+        TOTP::new(
+            Algorithm::SHA1,
+            TOTP_DIGITS,
+            0,
+            TOTP_STEP,
+            secret,
+            Some(TOTP_ISSUER.to_string()),
+            alias.alias().to_string(),
+        )
+        .ok()
+    }
+}
+
+impl Default for Totp {
+    fn default() -> Self {
+        Totp::new()
+    }
+}
+
+impl From<Totp> for mongodb::bson::Bson {
+    fn from(t: Totp) -> Self {
+        mongodb::bson::to_bson(&t).unwrap()
+    }
+}
+
+/// Generates a new set of recovery codes. Returns the codes that should be
+/// shown to the user and their hashes
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+            OsRng.fill_bytes(&mut bytes);
+            let code = Secret::Raw(bytes.to_vec()).to_encoded().to_string().to_lowercase();
+            let hash = token::hash(&code);
+            (code, hash)
+        })
+        .unzip()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_recovery_codes, Totp, RECOVERY_CODES};
+    use crate::mongo::user::Alias;
+
+    #[test]
+    pub fn verify_current_code() {
+        let alias: Alias = "Altair-Bueno".parse().unwrap();
+        let totp = Totp::new();
+        let code = totp.totp(&alias).unwrap().generate_current().unwrap();
+        assert!(totp.verify(&alias, &code).is_some());
+        assert!(totp.verify(&alias, "000000x").is_none());
+        // Used codes are rejected
+        let step = totp.verify(&alias, &code).unwrap();
+        let used = Totp { last_step: Some(step), ..totp.clone() };
+        assert!(used.verify(&alias, &code).is_none());
+        assert!(totp.uri(&alias).unwrap().starts_with("otpauth://totp/"));
+    }
+
+    #[test]
+    pub fn recovery_codes() {
+        let (codes, hashes) = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert_eq!(hashes.len(), RECOVERY_CODES);
+        assert_ne!(codes, hashes);
+        // 80 bits, base32 encoded
+        assert!(codes.iter().all(|x| x.len() == 16));
+    }
+}
@@ -6,6 +6,9 @@ use crate::mongo::traits::Document;
 use crate::mongo::user::alias::Alias;
 use crate::mongo::user::email::Email;
 use crate::mongo::user::password::Password;
+use crate::mongo::user::role::Role;
+use crate::mongo::user::status::AccountStatus;
+use crate::mongo::user::totp::Totp;
 use crate::mongo::user::Description;
 
 /// Represents a stored document on a document based database such as MongoDB.
@@ -21,32 +24,74 @@ use crate::mongo::user::Description;
 /// - [crate::mongo::user::Password]
 /// - [mongodb::bson::DateTime]
 /// - [crate::mongo::post::Caption]
+///
+/// # Identity
+///
+/// Other documents and tokens reference users by their id, which never
+/// changes. Alias and email are stored as typed by the user, together with
+/// their normalized form. Users are looked up by the normalized fields, which
+/// have unique indexes
 #[derive(Debug, Serialize, Deserialize, Ord, PartialOrd, PartialEq, Eq, Clone)]
 pub struct User {
     #[serde(rename = "_id")]
     #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
     alias: Alias,
+    // lowercase alias. Unique. Legacy users get it from the migration run by
+    // init_mongo_db
+    #[serde(default)]
+    alias_normalized: String,
     email: Email,
+    // lowercase email. Unique
+    #[serde(default)]
+    email_normalized: String,
     password: Password,
     description: Option<Description>,
     creation_date: DateTime,
     avatar: Option<ObjectId>,
+    // Users created before email verification are considered verified
+    #[serde(default = "legacy_verified")]
+    verified: bool,
+    // Two-factor authentication settings
+    #[serde(default)]
+    totp: Option<Totp>,
+    // Users created before roles are regular users
+    #[serde(default)]
+    role: Role,
+    // Suspensions and bans
+    #[serde(default)]
+    status: AccountStatus,
+    // When the account will be purged. Only set for accounts pending deletion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delete_after: Option<DateTime>,
+}
+
+fn legacy_verified() -> bool {
+    true
 }
 
 impl Document for User {}
 
 impl User {
-    /// Creates a new user with the current time and empty list of posts
+    /// Creates a new user with the current time and empty list of posts. The
+    /// user email is not verified. The id is generated beforehand, so other
+    /// documents can reference the user before it is inserted
     pub fn new(alias: Alias, email: Email, password: Password) -> Self {
         User {
-            id: None,
+            id: Some(ObjectId::new()),
+            alias_normalized: alias.normalized(),
             alias,
+            email_normalized: email.normalized(),
             email,
             password,
             description: None,
             creation_date: mongodb::bson::DateTime::now(),
             avatar: None,
+            verified: false,
+            totp: None,
+            role: Role::User,
+            status: AccountStatus::Active,
+            delete_after: None,
         }
     }
 
@@ -73,6 +118,38 @@ impl User {
     pub fn avatar(&self) -> Option<ObjectId> {
         self.avatar
     }
+    /// Checks if the user has verified their email
+    pub fn verified(&self) -> bool {
+        self.verified
+    }
+    /// Marks the user email as verified, such as when an identity provider
+    /// already verified it
+    pub fn with_verified(mut self, verified: bool) -> Self {
+        self.verified = verified;
+        self
+    }
+    pub fn totp(&self) -> &Option<Totp> {
+        &self.totp
+    }
+    /// Checks if the user must provide a TOTP code to log in
+    pub fn totp_enabled(&self) -> bool {
+        self.totp.as_ref().map(|x| x.enabled()).unwrap_or(false)
+    }
+    pub fn role(&self) -> Role {
+        self.role
+    }
+    pub fn status(&self) -> &AccountStatus {
+        &self.status
+    }
+    /// Date when the account will be purged, if the user asked to delete it
+    pub fn delete_after(&self) -> Option<DateTime> {
+        self.delete_after
+    }
+    /// Checks if the user asked to delete their account. These accounts are
+    /// hidden, and can only be used to cancel the deletion
+    pub fn pending_deletion(&self) -> bool {
+        self.delete_after.is_some()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::Buf;
+use hyper::client::conn;
+use hyper::header::{ACCEPT, CONTENT_TYPE, HOST};
+use hyper::{Body, Method, Request};
+use rocket::tokio::io::{AsyncRead, AsyncWrite};
+use rocket::tokio::net::TcpStream;
+use serde::de::DeserializeOwned;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+use crate::oidc::OidcError;
+
+/// Maximum time an identity provider has to answer a request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends a `GET` request and parses the JSON response
+pub async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, OidcError> {
+    let url = Url::parse(url).map_err(|_| OidcError::InvalidUrl(url.to_string()))?;
+    send(Method::GET, &url, None).await
+}
+
+/// Sends a `POST` request with a `application/x-www-form-urlencoded` body and
+/// parses the JSON response
+pub async fn post_form<T: DeserializeOwned>(
+    url: &str,
+    form: &[(&str, &str)],
+) -> Result<T, OidcError> {
+    let url = Url::parse(url).map_err(|_| OidcError::InvalidUrl(url.to_string()))?;
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish();
+    send(Method::POST, &url, Some(body)).await
+}
+
+async fn send<T: DeserializeOwned>(
+    method: Method,
+    url: &Url,
+    body: Option<String>,
+) -> Result<T, OidcError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| OidcError::InvalidUrl(url.to_string()))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| OidcError::InvalidUrl(url.to_string()))?;
+    let authority = &url[url::Position::BeforeHost..url::Position::AfterPort];
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(HOST, authority)
+        .header(ACCEPT, "application/json");
+    if body.is_some() {
+        request = request.header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+    }
+    let request = request.body(Body::from(body.unwrap_or_default()))?;
+
+    let response = rocket::tokio::time::timeout(TIMEOUT, async {
+        let tcp = TcpStream::connect((host, port)).await?;
+        match url.scheme() {
+            "https" => {
+                let mut config = ClientConfig::new();
+                config
+                    .root_store
+                    .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+                let name = DNSNameRef::try_from_ascii_str(host)
+                    .map_err(|_| OidcError::InvalidUrl(url.to_string()))?;
+                let tls = TlsConnector::from(Arc::new(config))
+                    .connect(name, tcp)
+                    .await?;
+                send_over(tls, request).await
+            }
+            "http" => send_over(tcp, request).await,
+            _ => Err(OidcError::InvalidUrl(url.to_string())),
+        }
+    })
+    .await
+    .map_err(|_| OidcError::Timeout)??;
+
+    if !response.status().is_success() {
+        return Err(OidcError::Status(response.status().as_u16()));
+    }
+    let body = hyper::body::aggregate(response.into_body()).await?;
+    Ok(serde_json::from_reader(body.reader())?)
+}
+
+async fn send_over<T>(io: T, request: Request<Body>) -> Result<hyper::Response<Body>, OidcError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::handshake(io).await?;
+    rocket::tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            println!("[OIDC]: Connection error: {}", e);
+        }
+    });
+    Ok(sender.send_request(request).await?)
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rocket::tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+/// Minimal HTTP client used to talk with identity providers
+mod http;
+
+/// Scopes requested when a provider doesn't configure them
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// Errors produced while talking with an identity provider
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Invalid provider configuration: {0}")]
+    Config(String),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Couldn't connect to identity provider: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't connect to identity provider: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Couldn't build request: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("Identity provider timed out")]
+    Timeout,
+    #[error("Identity provider answered with status {0}")]
+    Status(u16),
+    #[error("Invalid identity provider response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid ID token: {0}")]
+    InvalidToken(String),
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        OidcError::InvalidToken(e.to_string())
+    }
+}
+
+/// Endpoints published by the provider on
+/// `<issuer>/.well-known/openid-configuration`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Claims read from a verified ID token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEndpointResponse {
+    id_token: String,
+}
+
+/// An OpenID Connect identity provider. Users are sent to the provider using
+/// the authorization code flow with PKCE
+#[derive(Debug)]
+pub struct Provider {
+    name: String,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: String,
+    // Fetched on first use, so the server can start while the provider is
+    // down
+    discovery: RwLock<Option<Discovery>>,
+}
+
+impl Provider {
+    pub fn new(
+        name: &str,
+        issuer: &str,
+        client_id: &str,
+        client_secret: Option<String>,
+        scopes: Option<String>,
+    ) -> Provider {
+        Provider {
+            name: name.to_string(),
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret,
+            scopes: scopes.unwrap_or_else(|| DEFAULT_SCOPES.to_string()),
+            discovery: RwLock::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Returns the provider endpoints, fetching them if needed
+    pub async fn discovery(&self) -> Result<Discovery, OidcError> {
+        if let Some(discovery) = self.discovery.read().await.as_ref() {
+            return Ok(discovery.clone());
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let discovery: Discovery = http::get_json(&url).await?;
+        *self.discovery.write().await = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    /// URL the user must visit to log in with the provider
+    pub async fn authorization_url(
+        &self,
+        redirect_uri: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, OidcError> {
+        let discovery = self.discovery().await?;
+        let challenge = pkce_challenge(code_verifier);
+        let params = [
+            ("response_type", "code"),
+            ("client_id", &self.client_id),
+            ("redirect_uri", redirect_uri),
+            ("scope", &self.scopes),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ];
+        let url = Url::parse_with_params(&discovery.authorization_endpoint, &params)
+            .map_err(|_| OidcError::InvalidUrl(discovery.authorization_endpoint.clone()))?;
+        Ok(url.to_string())
+    }
+
+    /// Exchanges an authorization code for an ID token and verifies it. The
+    /// token must be signed by one of the provider keys, issued for this
+    /// client and contain the expected `nonce`
+    pub async fn exchange(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let discovery = self.discovery().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = self.client_secret.as_deref() {
+            form.push(("client_secret", secret));
+        }
+        let response: TokenEndpointResponse =
+            http::post_form(&discovery.token_endpoint, &form).await?;
+
+        let header = decode_header(&response.id_token)?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(OidcError::InvalidToken("Unsupported algorithm".to_string()));
+        }
+        // Keys are fetched every time, so rotated keys are picked up. Logins
+        // are rare enough for this to be cheap
+        let keys: JwkSet = http::get_json(&discovery.jwks_uri).await?;
+        let key = match header.kid.as_deref() {
+            Some(kid) => keys.find(kid),
+            None => keys.keys.first(),
+        }
+        .ok_or_else(|| OidcError::InvalidToken("Unknown key".to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        let data = decode::<IdTokenClaims>(
+            &response.id_token,
+            &DecodingKey::from_jwk(key)?,
+            &validation,
+        )?;
+        if data.claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidToken("Invalid nonce".to_string()));
+        }
+        Ok(data.claims)
+    }
+}
+
+/// Configured identity providers, by name
+#[derive(Debug, Default)]
+pub struct Providers {
+    providers: HashMap<String, Provider>,
+}
+
+impl Providers {
+    /// Reads the providers listed on `OIDC_PROVIDERS`. See `init_oidc`
+    pub fn from_env() -> Result<Providers, OidcError> {
+        let names = std::env::var("OIDC_PROVIDERS").unwrap_or_default();
+        let mut providers = HashMap::new();
+        for name in names.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let prefix = format!("OIDC_{}_", name.to_uppercase());
+            let var = |x: &str| std::env::var(format!("{}{}", prefix, x)).ok();
+            let issuer = var("ISSUER")
+                .ok_or_else(|| OidcError::Config(format!("{}ISSUER is missing", prefix)))?;
+            let client_id = var("CLIENT_ID")
+                .ok_or_else(|| OidcError::Config(format!("{}CLIENT_ID is missing", prefix)))?;
+            let provider = Provider::new(
+                &name.to_lowercase(),
+                &issuer,
+                &client_id,
+                var("CLIENT_SECRET"),
+                var("SCOPES"),
+            );
+            println!("[OIDC]: Using provider {} ({})", provider.name(), provider.issuer());
+            providers.insert(provider.name().to_string(), provider);
+        }
+        Ok(Providers { providers })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Provider> {
+        self.providers.get(name)
+    }
+}
+
+/// PKCE `S256` code challenge for the given verifier
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod test {
+    use super::pkce_challenge;
+
+    #[test]
+    pub fn s256_challenge() {
+        let verifier = "dBjftJeZ4CVP-mJ0kYt0_2WnTxNHBAQN3LJC4dwsLBw";
+        assert_eq!(
+            pkce_challenge(verifier),
+            "qQlK53QCv83A2Scvr5EG1SW9IG3EC3E4bH5-SDmURu8"
+        );
+    }
+}
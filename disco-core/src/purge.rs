@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::Database;
+use rocket::futures::StreamExt;
+use rocket::tokio::time::{sleep, Duration};
+use thiserror::Error;
+
+use crate::api::media::media_keys;
+use crate::mongo::export::{export_key, lease_cutoff, ExportStatus};
+use crate::mongo::media::Media;
+use crate::storage::{MediaStore, StoreError};
+
+/// Time (seconds) between purge runs
+#[cfg(debug_assertions)]
+const PURGE_INTERVAL: u64 = 60;
+
+#[cfg(not(debug_assertions))]
+const PURGE_INTERVAL: u64 = 3600;
+
+/// Documents removed together with the user, and the field that references it
+const REFERENCES: [(&str, &str); 6] = [
+    ("Posts", "author_id"),
+    ("Sessions", "user_id"),
+    ("Tickets", "user_id"),
+    ("AccessTokens", "user_id"),
+    ("Identities", "user_id"),
+    ("AliasReservations", "user_id"),
+];
+
+/// Errors produced while purging an account. The purge is retried on the
+/// next run
+#[derive(Error, Debug)]
+pub enum PurgeError {
+    #[error("Couldn't connect to database: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("Couldn't remove media: {0}")]
+    Store(#[from] StoreError),
+}
+
+/// Starts the background job that removes the accounts whose deletion grace
+/// period is over. See [delete_user](crate::api::users::delete::delete_user).
+/// Expired [exports](crate::mongo::export::Export) are removed too, along with
+/// the archives that were already downloaded. Exports whose
+/// [lease](crate::mongo::export::EXPORT_LEASE) ran out are marked as failed
+///
+/// # Resuming
+///
+/// Every step can be repeated, and the user document is removed last. If the
+/// server stops in the middle of a purge, the account is still pending
+/// deletion and the next run picks it up where it was left. Media documents
+/// are only removed once their objects are gone from the store, so no file is
+/// left behind
+pub fn spawn(db: Database, store: Arc<dyn MediaStore>) {
+    rocket::tokio::spawn(async move {
+        loop {
+            if let Err(e) = purge_accounts(&db, store.as_ref()).await {
+                println!("[PURGE]: {}", e);
+            }
+            if let Err(e) = purge_exports(&db, store.as_ref()).await {
+                println!("[PURGE]: {}", e);
+            }
+            sleep(Duration::from_secs(PURGE_INTERVAL)).await;
+        }
+    });
+}
+
+/// Purges every account whose grace period is over. Accounts that can't be
+/// purged are logged and retried on the next run, without holding back the
+/// rest
+async fn purge_accounts(db: &Database, store: &dyn MediaStore) -> Result<(), PurgeError> {
+    let users = db.collection::<Document>("Users");
+    let filter = doc! { "delete_after": { "$lte": DateTime::now() } };
+    let ids = users.distinct("_id", filter, None).await?;
+    for id in ids.iter().filter_map(|x| x.as_object_id()) {
+        match purge_user(db, store, id).await {
+            Ok(_) => println!("[PURGE]: Purged user {}", id),
+            Err(e) => println!("[PURGE]: Couldn't purge user {}: {}", id, e),
+        }
+    }
+    Ok(())
+}
+
+/// Removes the export archives that weren't downloaded in time and the ones
+/// that were already downloaded. Fails the exports that were abandoned while
+/// being built
+async fn purge_exports(db: &Database, store: &dyn MediaStore) -> Result<(), PurgeError> {
+    let exports = db.collection::<Document>("Exports");
+    let filter = doc! { "status": ExportStatus::Pending, "date": { "$lte": lease_cutoff() } };
+    let update = doc! { "$set": { "status": ExportStatus::Failed } };
+    let result = exports.update_many(filter, update, None).await?;
+    if result.modified_count > 0 {
+        println!("[PURGE]: Failed {} abandoned exports", result.modified_count);
+    }
+
+    let filter = doc! { "status": ExportStatus::Downloaded };
+    let ids = exports.distinct("_id", filter, None).await?;
+    for id in ids.iter().filter_map(|x| x.as_object_id()) {
+        store.delete(&export_key(&id)).await?;
+    }
+
+    let filter = doc! { "expires": { "$lte": DateTime::now() } };
+    remove_exports(db, store, filter).await
+}
+
+/// Removes the matching exports and their archives
+async fn remove_exports(
+    db: &Database,
+    store: &dyn MediaStore,
+    filter: Document,
+) -> Result<(), PurgeError> {
+    let exports = db.collection::<Document>("Exports");
+    let ids = exports.distinct("_id", filter, None).await?;
+    for id in ids.iter().filter_map(|x| x.as_object_id()) {
+        store.delete(&export_key(&id)).await?;
+        exports.delete_one(doc! { "_id": id }, None).await?;
+    }
+    Ok(())
+}
+
+/// Removes the user and everything it owns
+async fn purge_user(db: &Database, store: &dyn MediaStore, id: ObjectId) -> Result<(), PurgeError> {
+    remove_exports(db, store, doc! { "user_id": id }).await?;
+    let media = db.collection::<Media>("Media");
+    let mut cursor = media.find(doc! { "uploaded_by": id }, None).await?;
+    while let Some(next) = cursor.next().await {
+        // Unwrap is safe. Documents stored on the database always have an
+        // ObjectId
+        let oid = next?.id().unwrap();
+        for key in media_keys(&oid) {
+            store.delete(&key).await?;
+        }
+        media.delete_one(doc! { "_id": oid }, None).await?;
+    }
+    for (collection, field) in REFERENCES {
+        db.collection::<Document>(collection)
+            .delete_many(doc! { field: id }, None)
+            .await?;
+    }
+    let filter = doc! { "_id": id, "delete_after": { "$exists": true } };
+    db.collection::<Document>("Users").delete_one(filter, None).await?;
+    Ok(())
+}
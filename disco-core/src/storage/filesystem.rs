@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+
+use crate::storage::{MediaStore, ObjectInfo, ObjectStream, StoreError};
+
+/// Stores every object as a file inside `root`, using the key as its relative
+/// path. Only a single server can use it, unless `root` is shared storage
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<PathBuf>) -> FileStore {
+        FileStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+/// Missing files are reported with their key
+fn not_found(key: &str) -> impl FnOnce(std::io::Error) -> StoreError + '_ {
+    move |e| match e.kind() {
+        std::io::ErrorKind::NotFound => StoreError::NotFound(key.to_string()),
+        _ => e.into(),
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStore for FileStore {
+    async fn put(&self, key: &str, source: &Path, _: &str) -> Result<(), StoreError> {
+        let path = self.path(key);
+        if let Some(folder) = path.parent() {
+            rocket::tokio::fs::create_dir_all(folder).await?;
+        }
+        let mut partial = path.clone().into_os_string();
+        partial.push(".part");
+        rocket::tokio::fs::copy(source, &partial).await?;
+        rocket::tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<ObjectStream, StoreError> {
+        let mut file = File::open(self.path(key)).await.map_err(not_found(key))?;
+        match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Ok(Box::pin(file.take(end - start + 1)))
+            }
+            None => Ok(Box::pin(file)),
+        }
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StoreError> {
+        let metadata = match rocket::tokio::fs::metadata(self.path(key)).await {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let modified: DateTime<Utc> = metadata.modified()?.into();
+        Ok(Some(ObjectInfo {
+            size: metadata.len(),
+            modified,
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), modified.timestamp_nanos()),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match rocket::tokio::fs::remove_file(self.path(key)).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn presigned_url(&self, _: &str, _: u64) -> Option<String> {
+        None
+    }
+
+    async fn list(&self) -> Result<Vec<String>, StoreError> {
+        let mut keys = Vec::new();
+        let mut folders = vec![self.root.clone()];
+        while let Some(folder) = folders.pop() {
+            let mut entries = match rocket::tokio::fs::read_dir(&folder).await {
+                Ok(x) => x,
+                // Nothing has been stored yet
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    folders.push(path);
+                } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                    let parts: Vec<_> = relative.iter().map(|x| x.to_string_lossy()).collect();
+                    keys.push(parts.join("/"));
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use rocket::tokio::io::AsyncRead;
+use thiserror::Error;
+
+pub use filesystem::FileStore;
+pub use s3::S3Store;
+
+/// Stores objects as files inside a local folder
+mod filesystem;
+/// Stores objects on an S3 compatible bucket
+mod s3;
+
+/// Errors produced while accessing the media store
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Invalid store configuration: {0}")]
+    Config(String),
+    #[error("Object {0} not found")]
+    NotFound(String),
+    #[error("Couldn't access file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't reach store: {0}")]
+    Request(String),
+    #[error("Store timed out")]
+    Timeout,
+    #[error("Store answered with status {0}")]
+    Status(u16),
+}
+
+/// Contents of a stored object
+pub type ObjectStream = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Metadata of a stored object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    /// Bytes
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    /// Quoted entity tag. Changes whenever the object is replaced
+    pub etag: String,
+}
+
+/// Keeps media files. Objects are identified by a key such as
+/// `97/21/.../<id>.blob`, see [media_key](crate::api::media::media_key).
+/// Implementations must be cheap to share between requests and background
+/// jobs, as a single instance is shared by all of them
+#[rocket::async_trait]
+pub trait MediaStore: Send + Sync {
+    /// Stores the local file at `source` as `key`, replacing any previous
+    /// object. A partially stored object is never visible
+    async fn put(&self, key: &str, source: &Path, content_type: &str) -> Result<(), StoreError>;
+
+    /// Reads the object, or the inclusive byte `range` of it
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<ObjectStream, StoreError>;
+
+    /// Metadata of the object. `None` if it doesn't exist
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StoreError>;
+
+    /// Removes the object. Missing objects are ignored
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+
+    /// URL anyone can download the object from, without going through the
+    /// server, for the next `expires` seconds. `None` if the store can't
+    /// create them
+    async fn presigned_url(&self, key: &str, expires: u64) -> Option<String>;
+
+    /// Keys of every stored object, in no particular order. Used by the
+    /// [media GC](crate::gc) to find objects that don't belong to any media
+    async fn list(&self) -> Result<Vec<String>, StoreError>;
+
+    async fn exists(&self, key: &str) -> Result<bool, StoreError> {
+        Ok(self.stat(key).await?.is_some())
+    }
+
+    /// Copies the object to a local file. Used by programs that can only
+    /// read local files, such as the media processors
+    async fn fetch(&self, key: &str, target: &Path) -> Result<(), StoreError> {
+        let mut stream = self.get(key, None).await?;
+        let mut file = rocket::tokio::fs::File::create(target).await?;
+        rocket::tokio::io::copy(&mut stream, &mut file).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::path::Path;
+use std::time::Duration;
+
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{
+    BehaviorVersion, Credentials, Region, RequestChecksumCalculation,
+    ResponseChecksumValidation,
+};
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+use chrono::{TimeZone, Utc};
+use url::Url;
+
+use crate::storage::{MediaStore, ObjectInfo, ObjectStream, StoreError};
+
+/// Maximum time the store has to answer a request. Uploads must be sent
+/// within this time too
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest validity S3 accepts for presigned URLs (seconds)
+const MAX_PRESIGNED_EXPIRES: u64 = 604800;
+
+/// Stores objects on a bucket of Amazon S3 or any compatible store, such as
+/// MinIO, using the [AWS SDK](aws_sdk_s3). The bucket is addressed on the
+/// path (`<endpoint>/<bucket>/<key>`), which every compatible store supports
+///
+/// The client pools its connections, so only the first requests pay for the
+/// TCP and TLS handshakes. Checksums are only sent when S3 requires them, as
+/// some compatible stores reject the newer ones
+pub struct S3Store {
+    bucket: String,
+    client: Client,
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<S3Store, StoreError> {
+        let invalid = || StoreError::Config(format!("Invalid endpoint {}", endpoint));
+        let url = Url::parse(endpoint).map_err(|_| invalid())?;
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+            return Err(invalid());
+        }
+        if bucket.is_empty() {
+            return Err(StoreError::Config("Missing bucket".to_string()));
+        }
+        let credentials = Credentials::new(access_key, secret_key, None, None, "disco-core");
+        let timeouts = TimeoutConfig::builder().operation_timeout(TIMEOUT).build();
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url(endpoint.trim_end_matches('/'))
+            .region(Region::new(region.to_string()))
+            .credentials_provider(credentials)
+            .force_path_style(true)
+            .timeout_config(timeouts)
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
+            .build();
+        Ok(S3Store {
+            bucket: bucket.to_string(),
+            client: Client::from_conf(config),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStore for S3Store {
+    async fn put(&self, key: &str, source: &Path, content_type: &str) -> Result<(), StoreError> {
+        // Read from the file as it is sent
+        let body = ByteStream::from_path(source)
+            .await
+            .map_err(|e| StoreError::Io(std::io::Error::other(e)))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(body)
+            .send()
+            .await
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<ObjectStream, StoreError> {
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range.map(|(start, end)| format!("bytes={}-{}", start, end)))
+            .send()
+            .await;
+        match response {
+            Ok(x) => Ok(Box::pin(x.body.into_async_read())),
+            Err(SdkError::ServiceError(e)) if matches!(e.err(), GetObjectError::NoSuchKey(_)) => {
+                Err(StoreError::NotFound(key.to_string()))
+            }
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectInfo>, StoreError> {
+        let response = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+        let response = match response {
+            Ok(x) => x,
+            Err(SdkError::ServiceError(e)) if e.err().is_not_found() => return Ok(None),
+            Err(e) => return Err(request_error(e)),
+        };
+        let modified = response
+            .last_modified()
+            .and_then(|x| Utc.timestamp_opt(x.secs(), x.subsec_nanos()).single())
+            .unwrap_or_else(Utc::now);
+        Ok(Some(ObjectInfo {
+            size: response.content_length().unwrap_or_default().max(0) as u64,
+            modified,
+            etag: response.e_tag().unwrap_or_default().to_string(),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        // S3 answers the same whether the object existed or not
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    async fn presigned_url(&self, key: &str, expires: u64) -> Option<String> {
+        let expires = Duration::from_secs(expires.min(MAX_PRESIGNED_EXPIRES));
+        let config = PresigningConfig::expires_in(expires).ok()?;
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(config)
+            .await
+            .ok()?;
+        Some(request.uri().to_string())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, StoreError> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .into_paginator()
+            .send();
+        let mut keys = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(request_error)?;
+            keys.extend(page.contents().iter().filter_map(|x| x.key().map(String::from)));
+        }
+        Ok(keys)
+    }
+}
+
+/// Converts the errors of the SDK. Errors answered by the store keep their
+/// status code
+fn request_error<E>(e: SdkError<E>) -> StoreError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match e {
+        SdkError::TimeoutError(_) => StoreError::Timeout,
+        SdkError::ServiceError(x) => StoreError::Status(x.raw().status().as_u16()),
+        e => StoreError::Request(DisplayErrorContext(e).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::StatusCode;
+    use mongodb::bson::oid::ObjectId;
+    use rocket::tokio::io::AsyncReadExt;
+
+    use super::S3Store;
+    use crate::storage::{MediaStore, StoreError};
+
+    #[rocket::async_test]
+    pub async fn presigned_url() {
+        let store =
+            S3Store::new("http://127.0.0.1:9000/", "media", "us-east-1", "key", "secret").unwrap();
+        let url = store.presigned_url("1/2/a b.blob", 86400).await.unwrap();
+        assert!(url.starts_with("http://127.0.0.1:9000/media/1/2/a%20b.blob?"));
+        assert!(url.contains("X-Amz-Expires=86400"));
+        assert!(url.contains("X-Amz-Signature="));
+        let url = store.presigned_url("1/2/a.blob", 3600 * 24 * 30).await.unwrap();
+        assert!(url.contains("X-Amz-Expires=604800"));
+        assert!(S3Store::new("ftp://127.0.0.1", "media", "us-east-1", "key", "secret").is_err());
+        assert!(S3Store::new("http://127.0.0.1", "", "us-east-1", "key", "secret").is_err());
+    }
+
+    /// MinIO service from `docker-compose.yaml`. The `S3_*` variables point
+    /// the test to another store
+    fn minio() -> S3Store {
+        let var = |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+        S3Store::new(
+            &var("S3_ENDPOINT", "http://127.0.0.1:9000"),
+            &var("S3_BUCKET", "media"),
+            &var("S3_REGION", "us-east-1"),
+            &var("S3_ACCESS_KEY", "minioadmin"),
+            &var("S3_SECRET_KEY", "minioadmin"),
+        )
+        .unwrap()
+    }
+
+    /// Run with `docker compose up minio minio-setup` and
+    /// `cargo test -- --ignored`
+    #[rocket::async_test]
+    #[ignore]
+    pub async fn minio_objects() {
+        let store = minio();
+        let key = format!("test/{}/a b.txt", ObjectId::new());
+        let source = std::env::temp_dir().join(format!("s3-{}", ObjectId::new()));
+        std::fs::write(&source, "hello world").unwrap();
+        store.put(&key, &source, "text/plain").await.unwrap();
+        std::fs::remove_file(&source).unwrap();
+
+        let mut content = String::new();
+        let mut stream = store.get(&key, None).await.unwrap();
+        stream.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "hello world");
+        let mut content = String::new();
+        let mut stream = store.get(&key, Some((6, 9))).await.unwrap();
+        stream.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "worl");
+
+        let info = store.stat(&key).await.unwrap().unwrap();
+        assert_eq!(info.size, 11);
+        assert!(!info.etag.is_empty());
+        assert!(store.list().await.unwrap().contains(&key));
+
+        let url = store.presigned_url(&key, 60).await.unwrap();
+        let response = hyper::Client::new().get(url.parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello world");
+
+        store.delete(&key).await.unwrap();
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.stat(&key).await.unwrap(), None);
+        assert!(matches!(store.get(&key, None).await, Err(StoreError::NotFound(_))));
+        assert!(!store.list().await.unwrap().contains(&key));
+    }
+}
@@ -21,12 +21,12 @@ def get_random_file(path:str):
 
 
 def load(id:int):
-    body = payloads.new_user(f"hello{id}", f"a{id}@uma.es", "12341234")
+    body = payloads.new_user(f"hello{id}", f"a{id}@uma.es", "violet-harbor-82")
     r = users.create_user(body)
     if not r.ok:
         print(r.text)
         return -1
-    login = payloads.login_alias(f"hello{id}", "12341234")
+    login = payloads.login_alias(f"hello{id}", "violet-harbor-82")
     r = users.alias_log_in(login)
     auth_header = payloads.auth_header(r.json()["access_token"])
     refresh_token = r.json()["refresh_token"]
@@ -17,9 +17,9 @@ def download_media(id: str, headers: dict[str, str]):
 
 def test_media_upload():
     print('Create user and log in')
-    body = payloads.new_user('cool', 'a@a.com', '12341234')
+    body = payloads.new_user('cool', 'a@a.com', 'violet-harbor-82')
     users.create_user(body)
-    body = payloads.login_alias('cool', '12341234')
+    body = payloads.login_alias('cool', 'violet-harbor-82')
     r = users.alias_log_in(body)
     auth_header = payloads.auth_header(r.json()['access_token'])
 
@@ -0,0 +1,49 @@
+# Requires a mock OpenID Connect provider and disco-core configured to use it:
+#
+#   docker run -p 8080:8080 -v $PWD/resources:/config \
+#       -e JSON_CONFIG_PATH=/config/mock-oidc.json \
+#       ghcr.io/navikt/mock-oauth2-server:2.1.10
+#
+#   export OIDC_PROVIDERS=mock
+#   export OIDC_MOCK_ISSUER=http://localhost:8080/default
+#   export OIDC_MOCK_CLIENT_ID=fuzzy-disco
+#   export OIDC_MOCK_CLIENT_SECRET=secret
+import requests
+
+import payloads
+from users import delete_user
+
+_URL = 'http://127.0.0.1:8000/api/users/oidc/'
+
+
+def oidc_log_in(provider: str):
+    # Follows the redirects to the provider and back to the callback
+    return requests.get(_URL + f'{provider}/login')
+
+
+def oidc_sign_up(body: str):
+    return requests.post(_URL + 'signup', body,
+                         headers=payloads.basic_header())
+
+
+def test_api_oidc():
+    r = oidc_log_in('mock')
+    if r.status_code == 404:
+        print('Mock provider not configured. Skipping')
+        return
+    print(f'First login: {r.text}')
+    body = r.json()
+    if body.get('status') == 'alias_required':
+        body = payloads.oidc_sign_up(body['oidc_token'], 'mock-user')
+        r = oidc_sign_up(body)
+        print(f'Sign up: {r.text}')
+        if not r.ok:
+            return
+    r = oidc_log_in('mock')
+    print(f'Should return tokens: {r.text}')
+    if r.ok and 'access_token' in r.json():
+        delete_user(payloads.auth_header(r.json()['access_token']))
+
+
+if __name__ == '__main__':
+    test_api_oidc()
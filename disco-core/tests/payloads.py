@@ -34,6 +34,15 @@ def login_refresh_token(refresh_token: str):
     """
 
 
+def oidc_sign_up(oidc_token: str, alias: str):
+    return f"""
+    {{
+        "oidc_token": "{oidc_token}",
+        "alias": "{alias}"
+    }}
+    """
+
+
 def auth_header(access_token: str):
     return {
         "Authorization": ("Bearer " + access_token),
@@ -26,9 +26,9 @@ def edit_post(id: str, body: str, auth_headers: dict[str, str]):
 def test_posts_api():
     # start
     print('Create user and log in')
-    body = payloads.new_user('hello', 'a@a.com', '12341234')
+    body = payloads.new_user('hello', 'a@a.com', 'violet-harbor-82')
     users.create_user(body)
-    body = payloads.login_alias('hello', '12341234')
+    body = payloads.login_alias('hello', 'violet-harbor-82')
     r = users.alias_log_in(body)
     auth_header = payloads.auth_header(r.json()['access_token'])
 
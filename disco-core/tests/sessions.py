@@ -19,9 +19,9 @@ def delete_all_sessions(auth_headers: dict[str, str]):
 def test_api_sessions():
     # Start
     print('Creating sessions')
-    body = payloads.new_user('cool', 'a@a.com', '12341234')
+    body = payloads.new_user('cool', 'a@a.com', 'violet-harbor-82')
     create_user(body)
-    body = payloads.login_alias('cool', '12341234')
+    body = payloads.login_alias('cool', 'violet-harbor-82')
     r = None
     for _ in range(0, 5):
         r = alias_log_in(body)
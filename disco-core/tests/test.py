@@ -1,4 +1,5 @@
 from media import test_media_upload
+from oidc import test_api_oidc
 from post import test_posts_api
 from sessions import test_api_sessions
 from users import test_api_users
@@ -13,6 +14,8 @@ def main():
     test_media_upload()
     print("\ntesting post API...")
     test_posts_api()
+    print("\ntesting OpenID Connect login...")
+    test_api_oidc()
 
 
 if __name__ == '__main__':
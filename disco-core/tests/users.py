@@ -77,7 +77,7 @@ def easy_refresh_login(refresh_token: str):
 
 def test_api_users():
     # Create a user
-    body = payloads.new_user('hello', 'a@gmail.com', '12341234')
+    body = payloads.new_user('hello', 'a@gmail.com', 'violet-harbor-82')
     r = create_user(body)
     print(f'Created user: {r.json()}')
 
@@ -85,13 +85,13 @@ def test_api_users():
     r = get_basic_user_data("hello")
     print(f'Get basic data: {r.json()}')
 
-    body = payloads.login_email('a@gmail.com', '12341234')
+    body = payloads.login_email('a@gmail.com', 'violet-harbor-82')
     r = email_log_in(body)
     if not r.ok:
         print('Email log in failed')
 
     # alias log in
-    old_user_login = payloads.login_alias('hello', '12341234')
+    old_user_login = payloads.login_alias('hello', 'violet-harbor-82')
     r = alias_log_in(old_user_login)
     if not r.ok:
         print('Alias log in failed')
@@ -103,7 +103,7 @@ def test_api_users():
     r = get_full_user_data(auth_header)
     print(f'Full user info: {r.json()}')
 
-    body = payloads.change_password('12341234', '123412344')
+    body = payloads.change_password('violet-harbor-82', 'amber-cascade-57')
     r = change_password(body, auth_header)
     if not r.ok:
         print('password change failed')
@@ -119,7 +119,7 @@ def test_api_users():
 
     # First stage completed
 
-    r = alias_log_in(payloads.login_alias('hello', '123412344'))
+    r = alias_log_in(payloads.login_alias('hello', 'amber-cascade-57'))
     auth_header = payloads.auth_header(r.json()['access_token'])
     refresh_token = r.json()['refresh_token']
 
@@ -6,6 +6,7 @@ import UserProfile from "@/components/user/UserProfile";
 import NewPost from "@/components/NewPost";
 import UserSettings from "@/components/user/UserSettings";
 import HomePage from "@/components/HomePage";
+import {refreshSession} from "@/session";
 
 Vue.use(VueRouter)
 
@@ -86,32 +87,7 @@ async function isAuthenticated() {
     if(refreshToken) {
         let accessToken = findCookie("access_token");
         if(!accessToken) {
-            let payload = {
-                refresh_token: getCookieValue(refreshToken)
-            }
-            //Fixme: Localhost
-            let response = await fetch("/api/users/auth/login?using=refresh_token", {
-                method: "POST",
-                headers: {
-                    'Content-Type': 'application/json'
-                },
-                body: JSON.stringify(payload)
-            });
-            let server_payload = await response.json();
-            console.log(server_payload);
-            let status_code = response.status;
-            if(status_code >= 200 && status_code <= 299) {
-                let ttl = server_payload.expires_in * 1000;
-                console.log(ttl);
-                let a = "access_token=" + server_payload.access_token + "; SameSite=Lax; expires=" + (new Date(Date.now() + ttl)).toUTCString() + ";";
-                document.cookie = a;
-                console.log(a);
-                console.log(document.cookie);
-
-                res = true;
-            } else {
-                alert(status_code + " error");
-            }
+            res = await refreshSession(getCookieValue(refreshToken));
         } else {
             res = true;
         }
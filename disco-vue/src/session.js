@@ -0,0 +1,43 @@
+// Refresh tokens can only be used once. Pages check the session from several
+// components at the same time, so they share a single refresh request instead
+// of racing each other with the same token
+let pendingRefresh = null;
+
+export function refreshSession(refreshToken) {
+    if(!pendingRefresh) {
+        pendingRefresh = requestTokens(refreshToken).finally(() => {
+            pendingRefresh = null;
+        });
+    }
+    return pendingRefresh;
+}
+
+async function requestTokens(refreshToken) {
+    let payload = {
+        refresh_token: refreshToken
+    }
+    //Fixme: Localhost
+    let response = await fetch("/api/users/auth/login?using=refresh_token", {
+        method: "POST",
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(payload)
+    });
+    let server_payload = await response.json();
+    console.log(server_payload);
+    let status_code = response.status;
+    if(status_code >= 200 && status_code <= 299) {
+        let ttl = server_payload.expires_in * 1000;
+        console.log(ttl);
+        let a = "access_token=" + server_payload.access_token + "; SameSite=Lax; expires=" + (new Date(Date.now() + ttl)).toUTCString() + ";";
+        document.cookie = a;
+        document.cookie = "refresh_token=" + server_payload.refresh_token + "; SameSite=Lax; expires=" + new Date(9999, 1, 1) + ";";
+        console.log(a);
+        console.log(document.cookie);
+        return true;
+    } else {
+        alert(status_code + " error");
+        return false;
+    }
+}